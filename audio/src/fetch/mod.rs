@@ -156,6 +156,24 @@ pub struct StreamLoaderController {
 }
 
 impl StreamLoaderController {
+    /// Creates a controller for a file that is read from disk, so there is nothing to load.
+    pub fn from_local_file(file: &fs::File) -> Result<Self, Error> {
+        Ok(Self {
+            channel_tx: None,
+            stream_shared: None,
+            file_size: file.metadata()?.len() as usize,
+        })
+    }
+
+    /// Creates a controller for a file that is held in memory, so there is nothing to load.
+    pub fn from_buffer(data: &[u8]) -> Self {
+        Self {
+            channel_tx: None,
+            stream_shared: None,
+            file_size: data.len(),
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.file_size
     }
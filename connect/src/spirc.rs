@@ -13,7 +13,8 @@ use crate::{
     model::{LoadRequest, PlayingTrack, SpircPlayStatus},
     playback::{
         mixer::Mixer,
-        player::{Player, PlayerEvent, PlayerEventChannel},
+        player::{Player, PlayerEvent, PlayerEventChannel, SleepTimer},
+        time_stretch::VALID_PLAYBACK_SPEED_RANGE,
     },
     protocol::{
         connect::{Cluster, ClusterUpdate, LogoutCommand, SetVolumeCommand},
@@ -40,7 +41,10 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use thiserror::Error;
-use tokio::{sync::mpsc, time::sleep};
+use tokio::{
+    sync::mpsc,
+    time::{Instant, sleep, sleep_until},
+};
 
 #[derive(Debug, Error)]
 enum SpircError {
@@ -89,6 +93,8 @@ struct SpircTask {
 
     commands: Option<mpsc::UnboundedReceiver<SpircCommand>>,
     player_events: Option<PlayerEventChannel>,
+    /// volume changes made outside of librespot, if the mixer can follow them
+    mixer_volume_changes: Option<mpsc::UnboundedReceiver<u16>>,
 
     context_resolver: ContextResolver,
 
@@ -106,6 +112,13 @@ struct SpircTask {
     /// when no other future resolves, otherwise resets the delay
     update_state: bool,
 
+    sleep_timer: Option<SleepTimer>,
+    /// only set for [SleepTimer::After]
+    sleep_timer_deadline: Option<Instant>,
+    sleep_timer_fade_out: bool,
+    /// the end of the volume ramp-down that was sent to the player
+    sleep_fade: Option<std::time::Instant>,
+
     spirc_id: usize,
 }
 
@@ -124,9 +137,16 @@ enum SpircCommand {
     Shuffle(bool),
     Repeat(bool),
     RepeatTrack(bool),
-    Disconnect { pause: bool },
+    Disconnect {
+        pause: bool,
+    },
     SetPosition(u32),
     SetVolume(u16),
+    SetPlaybackSpeed(f64),
+    SetSleepTimer {
+        timer: Option<SleepTimer>,
+        fade_out: bool,
+    },
     Activate,
     Load(LoadRequest),
 }
@@ -219,6 +239,7 @@ impl Spirc {
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
 
         let player_events = player.get_player_event_channel();
+        let mixer_volume_changes = mixer.volume_changes();
 
         let mut task = SpircTask {
             player,
@@ -240,6 +261,7 @@ impl Spirc {
             user_attributes_mutation,
             commands: Some(cmd_rx),
             player_events: Some(player_events),
+            mixer_volume_changes,
 
             context_resolver: ContextResolver::new(session.clone()),
 
@@ -250,6 +272,11 @@ impl Spirc {
             update_volume: false,
             update_state: false,
 
+            sleep_timer: None,
+            sleep_timer_deadline: None,
+            sleep_timer_fade_out: false,
+            sleep_fade: None,
+
             spirc_id,
         };
 
@@ -375,6 +402,27 @@ impl Spirc {
         Ok(self.commands.send(SpircCommand::SetPosition(position_ms))?)
     }
 
+    /// Changes the playback speed without affecting the pitch.
+    ///
+    /// Does nothing if we are not the active device.
+    ///
+    /// If value is outside of 0.5 to 3.0, the update is ignored.
+    pub fn set_playback_speed(&self, speed: f64) -> Result<(), Error> {
+        Ok(self.commands.send(SpircCommand::SetPlaybackSpeed(speed))?)
+    }
+
+    /// Pauses playback after some time, at the end of the current track or
+    /// at the end of the current context. `None` cancels the sleep timer.
+    ///
+    /// With `fade_out`, the volume is ramped down over the final minute.
+    ///
+    /// A timer set before the device is active starts once it becomes active.
+    pub fn set_sleep_timer(&self, timer: Option<SleepTimer>, fade_out: bool) -> Result<(), Error> {
+        Ok(self
+            .commands
+            .send(SpircCommand::SetSleepTimer { timer, fade_out })?)
+    }
+
     /// Load a new context and replace the current.
     ///
     /// Does nothing if we are not the active device.
@@ -428,11 +476,14 @@ impl SpircTask {
         while !self.session.is_invalid() && !self.shutdown {
             let commands = self.commands.as_mut();
             let player_events = self.player_events.as_mut();
+            let mixer_volume_changes = self.mixer_volume_changes.as_mut();
 
             // when state and volume update have a higher priority than context resolving
             // because of that the context resolving has to wait, so that the other tasks can finish
             let allow_context_resolving = !self.update_state && !self.update_volume;
 
+            let sleep_timer_deadline = self.sleep_timer_deadline;
+
             tokio::select! {
                 // startup of the dealer requires a connection_id, which is retrieved at the very beginning
                 connection_id_update = self.connection_id_update.next() => unwrap! {
@@ -499,6 +550,13 @@ impl SpircTask {
                         error!("could not dispatch player event: {e}");
                     }
                 },
+                volume = async { mixer_volume_changes?.recv().await }, if mixer_volume_changes.is_some() => match volume {
+                    Some(volume) => self.set_volume(volume),
+                    None => self.mixer_volume_changes = None,
+                },
+                _ = sleep_until(sleep_timer_deadline.unwrap_or_else(Instant::now)), if sleep_timer_deadline.is_some() => {
+                    self.handle_sleep_timer_fired()
+                },
                 _ = async { sleep(UPDATE_STATE_DELAY).await }, if self.update_state => {
                     self.update_state = false;
 
@@ -630,6 +688,11 @@ impl SpircTask {
             SpircCommand::Activate => {
                 warn!("SpircCommand::{cmd:?} will be ignored while already active")
             }
+            // kept while not active, a timed sleep timer only starts running once active
+            SpircCommand::SetSleepTimer { timer, fade_out } if !self.connect_state.is_active() => {
+                self.handle_sleep_timer(timer, fade_out);
+                return Ok(());
+            }
             _ if !self.connect_state.is_active() => {
                 warn!("SpircCommand::{cmd:?} will be ignored while Not Active")
             }
@@ -651,6 +714,10 @@ impl SpircTask {
             SpircCommand::RepeatTrack(repeat) => self.handle_repeat_track(repeat),
             SpircCommand::SetPosition(position) => self.handle_seek(position),
             SpircCommand::SetVolume(volume) => self.set_volume(volume),
+            SpircCommand::SetPlaybackSpeed(speed) => self.handle_playback_speed(speed),
+            SpircCommand::SetSleepTimer { timer, fade_out } => {
+                self.handle_sleep_timer(timer, fade_out)
+            }
             SpircCommand::Load(command) => self.handle_load(command, None).await?,
         };
 
@@ -690,7 +757,17 @@ impl SpircTask {
                     .repeat_track()
                     .then(|| self.connect_state.current_track(|t| t.uri.clone()));
 
-                self.handle_next(next_track)?
+                let sleep_timer_fires = match self.sleep_timer {
+                    Some(SleepTimer::EndOfTrack) => true,
+                    Some(SleepTimer::EndOfContext) => self.connect_state.is_context_ending(),
+                    _ => false,
+                };
+
+                self.handle_next(next_track)?;
+
+                if sleep_timer_fires {
+                    self.handle_sleep_timer_fired();
+                }
             }
             PlayerEvent::Loading { .. } => match self.play_status {
                 SpircPlayStatus::LoadingPlay { position_ms } => {
@@ -716,7 +793,7 @@ impl SpircTask {
             PlayerEvent::Playing { position_ms, .. }
             | PlayerEvent::PositionCorrection { position_ms, .. } => {
                 trace!("==> Playing");
-                let new_nominal_start_time = self.now_ms() - position_ms as i64;
+                let new_nominal_start_time = self.nominal_start_time(position_ms);
                 match self.play_status {
                     SpircPlayStatus::Playing {
                         ref mut nominal_start_time,
@@ -786,6 +863,7 @@ impl SpircTask {
             _ => return Ok(()),
         }
 
+        self.update_sleep_fade();
         self.update_state = true;
         Ok(())
     }
@@ -1211,6 +1289,8 @@ impl SpircTask {
             self.connect_state.repeat_context(),
             self.connect_state.repeat_track(),
         );
+
+        self.start_sleep_timer();
     }
 
     async fn handle_load(
@@ -1378,7 +1458,7 @@ impl SpircTask {
                 self.connect_state
                     .update_position(position_ms, self.now_ms());
                 self.play_status = SpircPlayStatus::Playing {
-                    nominal_start_time: self.now_ms() - position_ms as i64,
+                    nominal_start_time: self.nominal_start_time(position_ms),
                     preloading_of_next_track_triggered,
                 };
             }
@@ -1414,7 +1494,7 @@ impl SpircTask {
                 preloading_of_next_track_triggered,
             } => {
                 self.player.pause();
-                let position_ms = (self.now_ms() - nominal_start_time) as u32;
+                let position_ms = self.position_since(nominal_start_time);
                 self.connect_state
                     .update_position(position_ms, self.now_ms());
                 self.play_status = SpircPlayStatus::Paused {
@@ -1440,7 +1520,7 @@ impl SpircTask {
         self.connect_state
             .update_position(position_ms, self.now_ms());
         self.player.seek(position_ms);
-        let now = self.now_ms();
+        let new_nominal_start_time = self.nominal_start_time(position_ms);
         match self.play_status {
             SpircPlayStatus::Stopped => (),
             SpircPlayStatus::LoadingPause {
@@ -1456,10 +1536,115 @@ impl SpircTask {
             SpircPlayStatus::Playing {
                 ref mut nominal_start_time,
                 ..
-            } => *nominal_start_time = now - position_ms as i64,
+            } => *nominal_start_time = new_nominal_start_time,
         };
     }
 
+    fn handle_playback_speed(&mut self, speed: f64) {
+        if !VALID_PLAYBACK_SPEED_RANGE.contains(&speed) {
+            warn!("tried to set the playback speed to {speed}");
+            return;
+        }
+
+        // keep the current position, as it is in media time
+        let position_ms = self.position();
+
+        self.player.set_playback_speed(speed);
+        self.connect_state.set_playback_speed(speed);
+
+        let new_nominal_start_time = self.nominal_start_time(position_ms);
+        if let SpircPlayStatus::Playing {
+            ref mut nominal_start_time,
+            ..
+        } = self.play_status
+        {
+            *nominal_start_time = new_nominal_start_time;
+            self.connect_state
+                .update_position(position_ms, self.now_ms());
+        }
+
+        self.update_sleep_fade();
+    }
+
+    fn handle_sleep_timer(&mut self, timer: Option<SleepTimer>, fade_out: bool) {
+        match timer {
+            Some(timer) => {
+                info!("Sleep timer armed: {timer:?}");
+                self.player.emit_sleep_timer_armed_event(timer);
+            }
+            None if self.sleep_timer.is_some() => {
+                info!("Sleep timer cancelled");
+                self.player.emit_sleep_timer_cancelled_event();
+            }
+            None => (),
+        }
+
+        self.sleep_timer = timer;
+        self.sleep_timer_deadline = None;
+        self.sleep_timer_fade_out = fade_out;
+
+        if self.connect_state.is_active() {
+            self.start_sleep_timer();
+        }
+    }
+
+    fn start_sleep_timer(&mut self) {
+        if let (Some(SleepTimer::After(duration)), None) =
+            (self.sleep_timer, self.sleep_timer_deadline)
+        {
+            self.sleep_timer_deadline = Some(Instant::now() + duration);
+        }
+
+        self.update_sleep_fade();
+    }
+
+    fn handle_sleep_timer_fired(&mut self) {
+        let Some(timer) = self.sleep_timer.take() else {
+            return;
+        };
+
+        info!("Sleep timer fired, pausing playback");
+        self.sleep_timer_deadline = None;
+
+        self.handle_pause();
+        self.player.emit_sleep_timer_fired_event(timer);
+
+        // restores the volume, after the player has been paused
+        self.update_sleep_fade();
+        self.update_state = true;
+    }
+
+    /// the time at which the sleep timer fires, when the volume should be ramped down to it
+    fn sleep_fade_end(&mut self) -> Option<std::time::Instant> {
+        if !self.sleep_timer_fade_out {
+            return None;
+        }
+
+        let is_last_track = match self.sleep_timer? {
+            SleepTimer::After(_) => return self.sleep_timer_deadline.map(Instant::into_std),
+            SleepTimer::EndOfTrack => true,
+            SleepTimer::EndOfContext => self.connect_state.is_context_ending(),
+        };
+
+        if !is_last_track || !matches!(self.play_status, SpircPlayStatus::Playing { .. }) {
+            return None;
+        }
+
+        let duration = u32::try_from(self.connect_state.player().duration).unwrap_or_default();
+        let remaining = Duration::from_millis(duration.saturating_sub(self.position()) as u64)
+            .div_f64(self.connect_state.playback_speed());
+
+        Some(std::time::Instant::now() + remaining)
+    }
+
+    fn update_sleep_fade(&mut self) {
+        let end = self.sleep_fade_end();
+        if end != self.sleep_fade {
+            self.sleep_fade = end;
+            self.player.set_sleep_fade(end);
+        }
+    }
+
     fn handle_shuffle(&mut self, shuffle: bool) -> Result<(), Error> {
         self.player.emit_shuffle_changed_event(shuffle);
         self.connect_state.handle_shuffle(shuffle)
@@ -1692,10 +1877,19 @@ impl SpircTask {
             | SpircPlayStatus::Paused { position_ms, .. } => position_ms,
             SpircPlayStatus::Playing {
                 nominal_start_time, ..
-            } => (self.now_ms() - nominal_start_time) as u32,
+            } => self.position_since(nominal_start_time),
         }
     }
 
+    /// the time at which the track would have started at the current playback speed
+    fn nominal_start_time(&self, position_ms: u32) -> i64 {
+        self.now_ms() - (position_ms as f64 / self.connect_state.playback_speed()) as i64
+    }
+
+    fn position_since(&self, nominal_start_time: i64) -> u32 {
+        ((self.now_ms() - nominal_start_time) as f64 * self.connect_state.playback_speed()) as u32
+    }
+
     fn load_track(&mut self, start_playing: bool, position_ms: u32) -> Result<(), Error> {
         if self.connect_state.current_track(MessageField::is_none) {
             debug!("current track is none, stopping playback");
@@ -14,7 +14,10 @@ use crate::{
     },
     model::SpircPlayStatus,
     protocol::{
-        connect::{Capabilities, Device, DeviceInfo, MemberType, PutStateReason, PutStateRequest},
+        connect::{
+            Capabilities, CapabilitySupportDetails, Device, DeviceInfo, MemberType, PutStateReason,
+            PutStateRequest,
+        },
         media::AudioQuality,
         player::{
             ContextIndex, ContextPlayerOptions, PlayOrigin, PlayerState, ProvidedTrack,
@@ -90,6 +93,8 @@ pub struct ConnectConfig {
     pub disable_volume: bool,
     /// Number of incremental steps (default: 64)
     pub volume_steps: u16,
+    /// Advertises lossless playback, which should match the bitrate of the player (default: false)
+    pub supports_hifi: bool,
 }
 
 impl Default for ConnectConfig {
@@ -101,6 +106,7 @@ impl Default for ConnectConfig {
             initial_volume: u16::MAX / 2,
             disable_volume: false,
             volume_steps: 64,
+            supports_hifi: false,
         }
     }
 }
@@ -131,6 +137,9 @@ pub(super) struct ConnectState {
 
     /// The volume adjustment per step when handling individual volume adjustments.
     pub volume_step_size: u16,
+
+    /// the speed that is reported while playing, see [ConnectState::set_status]
+    playback_speed: f64,
 }
 
 impl ConnectState {
@@ -173,12 +182,19 @@ impl ConnectState {
                 supports_rename: false,
                 supports_external_episodes: false,
                 supports_set_backend_metadata: false,
-                supports_hifi: MessageField::none(),
+                // whether the account may play lossless is not known here, that is up to spotify
+                supports_hifi: MessageField::some(CapabilitySupportDetails {
+                    device_supported: cfg.supports_hifi,
+                    ..Default::default()
+                }),
                 // that "AI" dj thingy only available to specific regions/users
                 supports_dj: false,
                 supports_rooms: false,
-                // AudioQuality::HIFI is available, further investigation necessary
-                supported_audio_quality: EnumOrUnknown::new(AudioQuality::VERY_HIGH),
+                supported_audio_quality: EnumOrUnknown::new(if cfg.supports_hifi {
+                    AudioQuality::HIFI_24
+                } else {
+                    AudioQuality::VERY_HIGH
+                }),
 
                 command_acks: true,
 
@@ -202,6 +218,7 @@ impl ConnectState {
                 ..Default::default()
             },
             volume_step_size,
+            playback_speed: 1.,
             ..Default::default()
         };
         state.reset();
@@ -271,6 +288,14 @@ impl ConnectState {
         player.is_playing && player.is_paused && player.is_buffering
     }
 
+    pub fn playback_speed(&self) -> f64 {
+        self.playback_speed
+    }
+
+    pub fn set_playback_speed(&mut self, speed: f64) {
+        self.playback_speed = speed;
+    }
+
     pub fn set_volume(&mut self, volume: u32) {
         self.device_mut()
             .device_info
@@ -320,6 +345,7 @@ impl ConnectState {
     }
 
     pub(crate) fn set_status(&mut self, status: &SpircPlayStatus) {
+        let playback_speed = self.playback_speed;
         let player = self.player_mut();
         player.is_paused = matches!(
             status,
@@ -331,7 +357,7 @@ impl ConnectState {
         if player.is_paused {
             player.playback_speed = 0.;
         } else {
-            player.playback_speed = 1.;
+            player.playback_speed = playback_speed;
         }
 
         // desktop and mobile require all 'states' set to true, when we are paused,
@@ -433,10 +459,11 @@ impl ConnectState {
     }
 
     pub fn update_position_in_relation(&mut self, timestamp: i64) {
+        let playback_speed = self.playback_speed;
         let player = self.player_mut();
 
         let diff = timestamp - player.timestamp;
-        player.position_as_of_timestamp += diff;
+        player.position_as_of_timestamp += (diff as f64 * playback_speed) as i64;
 
         if log::max_level() >= LevelFilter::Debug {
             let pos = Duration::from_millis(player.position_as_of_timestamp as u64);
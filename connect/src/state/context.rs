@@ -19,6 +19,7 @@ use std::collections::HashMap;
 use uuid::Uuid;
 
 const LOCAL_FILES_IDENTIFIER: &str = "spotify:local-files";
+const LOCAL_TRACK_PREFIX: &str = "spotify:local:";
 const SEARCH_IDENTIFIER: &str = "spotify:search";
 
 #[derive(Debug)]
@@ -194,6 +195,8 @@ impl ConnectState {
             error!("context didn't have any tracks: {context:#?}");
             Err(StateError::ContextHasNoTracks)?;
         } else if matches!(context.uri, Some(ref uri) if uri.starts_with(LOCAL_FILES_IDENTIFIER)) {
+            // the "Local Files" context is only known to the device that has the files, only
+            // local tracks in other contexts can be played from the local files directory
             Err(StateError::UnsupportedLocalPlayback)?;
         }
 
@@ -446,7 +449,8 @@ impl ConnectState {
         provider: Option<Provider>,
     ) -> Result<ProvidedTrack, Error> {
         let id = match (ctx_track.uri.as_ref(), ctx_track.gid.as_ref()) {
-            (Some(uri), _) if uri.contains(['?', '%']) => {
+            // the fields of local files are percent-encoded
+            (Some(uri), _) if uri.contains(['?', '%']) && !uri.starts_with(LOCAL_TRACK_PREFIX) => {
                 Err(StateError::InvalidTrackUri(Some(uri.clone())))?
             }
             (Some(uri), _) if !uri.is_empty() => SpotifyUri::from_uri(uri)?,
@@ -456,7 +460,10 @@ impl ConnectState {
             _ => Err(StateError::InvalidTrackUri(None))?,
         };
 
-        let uri = id.to_uri()?.replace("unknown", "track");
+        let uri = match id {
+            SpotifyUri::Local { .. } => id.to_uri()?,
+            _ => id.to_uri()?.replace("unknown", "track"),
+        };
 
         let provider = if self.unavailable_uri.contains(&uri) {
             Provider::Unavailable
@@ -362,6 +362,16 @@ impl<'ct> ConnectState {
         SpotifyUri::from_uri(next).ok()
     }
 
+    /// Whether the current context ends with the current track, ignoring queued tracks.
+    ///
+    /// Afterwards, playback either stops, repeats the context or continues with autoplay.
+    pub fn is_context_ending(&self) -> bool {
+        self.next_tracks()
+            .iter()
+            .find(|track| !track.is_queue())
+            .is_none_or(|track| track.uid.starts_with(IDENTIFIER_DELIMITER) || track.is_autoplay())
+    }
+
     pub fn has_next_tracks(&self, min: Option<usize>) -> bool {
         if let Some(min) = min {
             self.next_tracks().len() >= min
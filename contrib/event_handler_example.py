@@ -29,6 +29,11 @@ elif player_event == 'repeat_changed':
 elif player_event == 'auto_play_changed':
     json_dict['auto_play'] = os.environ['AUTO_PLAY']
 
+elif player_event in ('sleep_timer_armed', 'sleep_timer_fired'):
+    json_dict['sleep_timer'] = os.environ['SLEEP_TIMER']
+    if json_dict['sleep_timer'] == 'after':
+        json_dict['sleep_timer_ms'] = os.environ['SLEEP_TIMER_MS']
+
 elif player_event == 'filter_explicit_content_changed':
     json_dict['filter'] = os.environ['FILTER']
 
@@ -39,6 +44,24 @@ elif player_event in ('seeked', 'position_correction', 'playing', 'paused'):
     json_dict['track_id'] = os.environ['TRACK_ID']
     json_dict['position_ms'] = os.environ['POSITION_MS']
 
+elif player_event == 'levels':
+    json_dict['track_id'] = os.environ['TRACK_ID']
+    json_dict['peak_db'] = [float(level) for level in os.environ['PEAK_DB'].split(',')]
+    json_dict['rms_db'] = [float(level) for level in os.environ['RMS_DB'].split(',')]
+    json_dict['bands_db'] = [float(level) for level in os.environ['BANDS_DB'].split(',')]
+
+elif player_event == 'sink_error':
+    json_dict['error'] = os.environ['ERROR']
+
+elif player_event == 'output_buffer_xrun':
+    json_dict['underruns'] = os.environ['UNDERRUNS']
+    json_dict['overruns'] = os.environ['OVERRUNS']
+
+elif player_event == 'silence_trimmed':
+    json_dict['track_id'] = os.environ['TRACK_ID']
+    json_dict['position_ms'] = os.environ['POSITION_MS']
+    json_dict['trimmed_ms'] = os.environ['TRIMMED_MS']
+
 elif player_event in ('unavailable', 'end_of_track', 'preload_next', 'preloading', 'loading', 'stopped'): 
     json_dict['track_id'] = os.environ['TRACK_ID']
 
@@ -29,6 +29,17 @@ impl From<SpotifyUriError> for Error {
 
 pub type SpotifyUriResult = Result<SpotifyUri, Error>;
 
+// The fields of local URIs are form-urlencoded, with spaces as `+`.
+fn decode_local_field(field: &str) -> String {
+    form_urlencoded::parse(field.as_bytes())
+        .map(|(key, _)| key)
+        .collect()
+}
+
+fn encode_local_field(field: &str) -> String {
+    form_urlencoded::byte_serialize(field.as_bytes()).collect()
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum SpotifyUri {
     Album {
@@ -98,6 +109,9 @@ impl SpotifyUri {
                 track_title,
                 duration,
             } => {
+                let artist = encode_local_field(artist);
+                let album_title = encode_local_field(album_title);
+                let track_title = encode_local_field(track_title);
                 let duration_secs = duration.as_secs();
                 Ok(format!(
                     "{artist}:{album_title}:{track_title}:{duration_secs}"
@@ -167,12 +181,21 @@ impl SpotifyUri {
             SPOTIFY_ITEM_TYPE_TRACK => Ok(Self::Track {
                 id: SpotifyId::from_base62(name)?,
             }),
-            SPOTIFY_ITEM_TYPE_LOCAL => Ok(Self::Local {
-                artist: "unimplemented".to_owned(),
-                album_title: "unimplemented".to_owned(),
-                track_title: "unimplemented".to_owned(),
-                duration: Default::default(),
-            }),
+            SPOTIFY_ITEM_TYPE_LOCAL => {
+                let album_title = parts.next().ok_or(SpotifyUriError::InvalidFormat)?;
+                let track_title = parts.next().ok_or(SpotifyUriError::InvalidFormat)?;
+                let duration_secs = parts
+                    .next()
+                    .and_then(|secs| secs.parse::<u64>().ok())
+                    .ok_or(SpotifyUriError::InvalidFormat)?;
+
+                Ok(Self::Local {
+                    artist: decode_local_field(name),
+                    album_title: decode_local_field(album_title),
+                    track_title: decode_local_field(track_title),
+                    duration: std::time::Duration::from_secs(duration_secs),
+                })
+            }
             _ => Ok(Self::Unknown {
                 kind: item_type.to_owned().into(),
                 id: name.to_owned(),
@@ -533,17 +556,28 @@ mod tests {
 
     #[test]
     fn from_local_uri() {
-        let actual = SpotifyUri::from_uri("spotify:local:xyz:123").unwrap();
+        let actual =
+            SpotifyUri::from_uri("spotify:local:The+Artist:Some+Album:A+Title%3A+Part+2:215")
+                .unwrap();
 
         assert_eq!(
             actual,
             SpotifyUri::Local {
-                artist: "unimplemented".to_owned(),
-                album_title: "unimplemented".to_owned(),
-                track_title: "unimplemented".to_owned(),
-                duration: Default::default(),
+                artist: "The Artist".to_owned(),
+                album_title: "Some Album".to_owned(),
+                track_title: "A Title: Part 2".to_owned(),
+                duration: std::time::Duration::from_secs(215),
             }
         );
+
+        assert!(SpotifyUri::from_uri("spotify:local:xyz:123").is_err());
+    }
+
+    #[test]
+    fn to_local_uri() {
+        let uri = "spotify:local:The+Artist::A+Title%3A+Part+2:215";
+
+        assert_eq!(SpotifyUri::from_uri(uri).unwrap().to_uri().unwrap(), uri);
     }
 
     #[test]
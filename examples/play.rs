@@ -39,8 +39,9 @@ async fn main() {
         exit(1);
     }
 
+    let sample_rate = player_config.sample_rate;
     let player = Player::new(player_config, session, Box::new(NoOpVolume), move || {
-        backend(None, audio_format)
+        backend(None, audio_format, sample_rate)
     });
 
     player.load(track, true, 0);
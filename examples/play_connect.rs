@@ -52,11 +52,12 @@ async fn main() -> Result<(), Error> {
     let session = Session::new(session_config, Some(cache));
     let mixer = mixer_builder(mixer_config)?;
 
+    let sample_rate = player_config.sample_rate;
     let player = Player::new(
         player_config,
         session.clone(),
         mixer.get_soft_volume(),
-        move || sink_builder(None, audio_format),
+        move || sink_builder(None, audio_format, sample_rate),
     );
 
     let (spirc, spirc_task) =
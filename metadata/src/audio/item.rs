@@ -2,7 +2,7 @@ use std::fmt::Debug;
 
 use crate::{
     Metadata,
-    artist::ArtistsWithRole,
+    artist::{ArtistRole, ArtistWithRole, ArtistsWithRole},
     availability::{AudioItemAvailability, Availabilities, UnavailabilityReason},
     episode::Episode,
     error::MetadataError,
@@ -30,6 +30,8 @@ pub struct AudioItem {
     pub track_id: SpotifyUri,
     pub uri: String,
     pub files: AudioFiles,
+    /// 30-second MP3 previews, which don't need a premium account.
+    pub previews: AudioFiles,
     pub name: String,
     pub covers: Vec<CoverImage>,
     pub language: Vec<String>,
@@ -123,6 +125,7 @@ impl AudioItem {
                     track_id: uri,
                     uri: uri_string,
                     files: track.files,
+                    previews: track.previews,
                     name: track.name,
                     covers,
                     language: track.language_of_performance,
@@ -166,6 +169,7 @@ impl AudioItem {
                     track_id: uri,
                     uri: uri_string,
                     files: episode.audio,
+                    previews: episode.audio_previews,
                     name: episode.name,
                     covers,
                     language: vec![episode.language],
@@ -179,6 +183,52 @@ impl AudioItem {
             _ => Err(Error::unavailable(MetadataError::NonPlayable)),
         }
     }
+
+    /// Builds an item from the fields of a `spotify:local:` URI, as local files have no
+    /// metadata on Spotify's servers.
+    pub fn local(uri: SpotifyUri) -> AudioItemResult {
+        let SpotifyUri::Local {
+            ref artist,
+            ref album_title,
+            ref track_title,
+            duration,
+        } = uri
+        else {
+            return Err(Error::unavailable(MetadataError::NonPlayable));
+        };
+
+        let unique_fields = UniqueFields::Track {
+            artists: ArtistsWithRole(vec![ArtistWithRole {
+                // local artists have no Spotify ID
+                id: SpotifyUri::Unknown {
+                    kind: "artist".into(),
+                    id: String::new(),
+                },
+                name: artist.clone(),
+                role: ArtistRole::ARTIST_ROLE_MAIN_ARTIST,
+            }]),
+            album: album_title.clone(),
+            album_artists: vec![artist.clone()],
+            popularity: 0,
+            number: 0,
+            disc_number: 0,
+        };
+
+        Ok(Self {
+            uri: uri.to_uri()?,
+            files: AudioFiles::default(),
+            previews: AudioFiles::default(),
+            name: track_title.clone(),
+            covers: Vec::new(),
+            language: Vec::new(),
+            duration_ms: duration.as_millis() as u32,
+            is_explicit: false,
+            availability: Ok(()),
+            alternatives: None,
+            unique_fields,
+            track_id: uri,
+        })
+    }
 }
 
 fn get_covers(covers: Images, image_url: String) -> Vec<CoverImage> {
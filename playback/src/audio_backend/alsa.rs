@@ -1,16 +1,17 @@
-use super::{Open, Sink, SinkAsBytes, SinkError, SinkResult};
+use super::{AudioDevice, Open, Sink, SinkAsBytes, SinkError, SinkResult};
+use crate::NUM_CHANNELS;
 use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
-use crate::{NUM_CHANNELS, SAMPLE_RATE};
 use alsa::device_name::HintIter;
 use alsa::pcm::{Access, Format, Frames, HwParams, PCM};
 use alsa::{Direction, ValueOr};
 use std::process::exit;
+use std::time::Duration;
 use thiserror::Error;
 
-const MAX_BUFFER: Frames = (SAMPLE_RATE / 2) as Frames;
-const MIN_BUFFER: Frames = (SAMPLE_RATE / 10) as Frames;
+const MAX_BUFFER_DIVISOR: Frames = 2;
+const MIN_BUFFER_DIVISOR: Frames = 10;
 const ZERO_FRAMES: Frames = 0;
 
 const MAX_PERIOD_DIVISOR: Frames = 4;
@@ -98,15 +99,20 @@ impl From<AudioFormat> for Format {
 pub struct AlsaSink {
     pcm: Option<PCM>,
     format: AudioFormat,
+    sample_rate: u32,
     device: String,
     period_buffer: Vec<u8>,
 }
 
-fn list_compatible_devices() -> SinkResult<()> {
-    let i = HintIter::new_str(None, "pcm").map_err(AlsaError::Parsing)?;
+struct CompatibleDevice {
+    name: String,
+    description: String,
+    formats: Vec<AudioFormat>,
+}
 
-    println!("\n\n\tCompatible alsa device(s):\n");
-    println!("\t------------------------------------------------------\n");
+fn compatible_devices(sample_rate: u32) -> SinkResult<Vec<CompatibleDevice>> {
+    let i = HintIter::new_str(None, "pcm").map_err(AlsaError::Parsing)?;
+    let mut devices = vec![];
 
     for a in i {
         if let Some(Direction::Playback) = a.direction {
@@ -114,43 +120,30 @@ fn list_compatible_devices() -> SinkResult<()> {
                 if let Ok(pcm) = PCM::new(&name, Direction::Playback, false) {
                     if let Ok(hwp) = HwParams::any(&pcm) {
                         // Only show devices that support
-                        // 2 ch 44.1 Interleaved.
+                        // 2 ch Interleaved at the output sample rate.
 
                         if hwp.set_access(Access::RWInterleaved).is_ok()
-                            && hwp.set_rate(SAMPLE_RATE, ValueOr::Nearest).is_ok()
+                            && hwp.set_rate(sample_rate, ValueOr::Nearest).is_ok()
                             && hwp.set_channels(NUM_CHANNELS as u32).is_ok()
                         {
-                            let mut supported_formats = vec![];
-
-                            for f in &[
+                            let formats: Vec<AudioFormat> = [
                                 AudioFormat::S16,
                                 AudioFormat::S24,
                                 AudioFormat::S24_3,
                                 AudioFormat::S32,
                                 AudioFormat::F32,
                                 AudioFormat::F64,
-                            ] {
-                                if hwp.test_format(Format::from(*f)).is_ok() {
-                                    supported_formats.push(format!("{f:?}"));
-                                }
-                            }
-
-                            if !supported_formats.is_empty() {
-                                println!("\tDevice:\n\n\t\t{name}\n");
-
-                                println!(
-                                    "\tDescription:\n\n\t\t{}\n",
-                                    a.desc.unwrap_or_default().replace('\n', "\n\t\t")
-                                );
-
-                                println!(
-                                    "\tSupported Format(s):\n\n\t\t{}\n",
-                                    supported_formats.join(" ")
-                                );
-
-                                println!(
-                                    "\t------------------------------------------------------\n"
-                                );
+                            ]
+                            .into_iter()
+                            .filter(|f| hwp.test_format(Format::from(*f)).is_ok())
+                            .collect();
+
+                            if !formats.is_empty() {
+                                devices.push(CompatibleDevice {
+                                    name,
+                                    description: a.desc.unwrap_or_default(),
+                                    formats,
+                                });
                             }
                         }
                     };
@@ -159,10 +152,48 @@ fn list_compatible_devices() -> SinkResult<()> {
         }
     }
 
+    Ok(devices)
+}
+
+fn list_compatible_devices(sample_rate: u32) -> SinkResult<()> {
+    let devices = compatible_devices(sample_rate)?;
+
+    println!("\n\n\tCompatible alsa device(s):\n");
+    println!("\t------------------------------------------------------\n");
+
+    for device in devices {
+        let supported_formats: Vec<String> =
+            device.formats.iter().map(|f| format!("{f:?}")).collect();
+
+        println!("\tDevice:\n\n\t\t{}\n", device.name);
+
+        println!(
+            "\tDescription:\n\n\t\t{}\n",
+            device.description.replace('\n', "\n\t\t")
+        );
+
+        println!(
+            "\tSupported Format(s):\n\n\t\t{}\n",
+            supported_formats.join(" ")
+        );
+
+        println!("\t------------------------------------------------------\n");
+    }
+
     Ok(())
 }
 
-fn open_device(dev_name: &str, format: AudioFormat) -> SinkResult<(PCM, usize)> {
+pub fn list_devices(sample_rate: u32) -> SinkResult<Vec<AudioDevice>> {
+    Ok(compatible_devices(sample_rate)?
+        .into_iter()
+        .map(|device| AudioDevice {
+            name: device.name,
+            description: Some(device.description.replace('\n', " ")),
+        })
+        .collect())
+}
+
+fn open_device(dev_name: &str, format: AudioFormat, sample_rate: u32) -> SinkResult<(PCM, usize)> {
     let pcm = PCM::new(dev_name, Direction::Playback, false).map_err(|e| AlsaError::PcmSetUp {
         device: dev_name.to_string(),
         e,
@@ -187,10 +218,10 @@ fn open_device(dev_name: &str, format: AudioFormat) -> SinkResult<(PCM, usize)>
                 e,
             })?;
 
-        hwp.set_rate(SAMPLE_RATE, ValueOr::Nearest).map_err(|e| {
+        hwp.set_rate(sample_rate, ValueOr::Nearest).map_err(|e| {
             AlsaError::UnsupportedSampleRate {
                 device: dev_name.to_string(),
-                samplerate: SAMPLE_RATE,
+                samplerate: sample_rate,
                 e,
             }
         })?;
@@ -211,10 +242,13 @@ fn open_device(dev_name: &str, format: AudioFormat) -> SinkResult<(PCM, usize)>
         // error state.
         let hwp_clone = hwp.clone();
 
-        // At a sampling rate of 44100:
-        // The largest buffer is 22050 Frames (500ms) with 5512 Frame periods (125ms).
-        // The smallest buffer is 4410 Frames (100ms) with 441 Frame periods (10ms).
+        // The largest buffer is 500ms with 125ms periods.
+        // The smallest buffer is 100ms with 10ms periods.
+        // At a sampling rate of 44100 that is 22050 Frames with 5512 Frame periods,
+        // and 4410 Frames with 441 Frame periods respectively.
         // Actual values may vary.
+        let max_buffer = sample_rate as Frames / MAX_BUFFER_DIVISOR;
+        let min_buffer = sample_rate as Frames / MIN_BUFFER_DIVISOR;
         //
         // Larger buffer and period sizes are preferred as extremely small values
         // will cause high CPU useage.
@@ -241,7 +275,7 @@ fn open_device(dev_name: &str, format: AudioFormat) -> SinkResult<(PCM, usize)>
             };
 
             let buffer_size = if min < max {
-                match (MIN_BUFFER..=MAX_BUFFER)
+                match (min_buffer..=max_buffer)
                     .rev()
                     .find(|f| (min..=max).contains(f))
                 {
@@ -269,7 +303,7 @@ fn open_device(dev_name: &str, format: AudioFormat) -> SinkResult<(PCM, usize)>
             };
 
             if buffer_size == ZERO_FRAMES {
-                trace!("Desired Buffer Frame range: {MIN_BUFFER:?} - {MAX_BUFFER:?}",);
+                trace!("Desired Buffer Frame range: {min_buffer:?} - {max_buffer:?}",);
 
                 trace!("Actual Buffer Frame range as reported by the device: {min:?} - {max:?}",);
             }
@@ -386,9 +420,9 @@ fn open_device(dev_name: &str, format: AudioFormat) -> SinkResult<(PCM, usize)>
 }
 
 impl Open for AlsaSink {
-    fn open(device: Option<String>, format: AudioFormat) -> Self {
+    fn open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> Self {
         let name = match device.as_deref() {
-            Some("?") => match list_compatible_devices() {
+            Some("?") => match list_compatible_devices(sample_rate) {
                 Ok(_) => {
                     exit(0);
                 }
@@ -402,11 +436,12 @@ impl Open for AlsaSink {
         }
         .to_string();
 
-        info!("Using AlsaSink with format: {format:?}");
+        info!("Using AlsaSink with format: {format:?}, sample rate: {sample_rate}");
 
         Self {
             pcm: None,
             format,
+            sample_rate,
             device: name,
             period_buffer: vec![],
         }
@@ -416,7 +451,7 @@ impl Open for AlsaSink {
 impl Sink for AlsaSink {
     fn start(&mut self) -> SinkResult<()> {
         if self.pcm.is_none() {
-            let (pcm, bytes_per_period) = open_device(&self.device, self.format)?;
+            let (pcm, bytes_per_period) = open_device(&self.device, self.format, self.sample_rate)?;
             self.pcm = Some(pcm);
 
             if self.period_buffer.capacity() != bytes_per_period {
@@ -448,6 +483,19 @@ impl Sink for AlsaSink {
         Ok(())
     }
 
+    fn delay(&self) -> Option<Duration> {
+        let pcm = self.pcm.as_ref()?;
+
+        // Includes what is still in the period buffer, as it has not been written to the PCM yet.
+        let pcm_frames = pcm.delay().ok()?.max(ZERO_FRAMES) as usize;
+        let buffered_frames =
+            self.period_buffer.len() / (self.format.size() * NUM_CHANNELS as usize);
+
+        Some(Duration::from_secs_f64(
+            (pcm_frames + buffered_frames) as f64 / self.sample_rate as f64,
+        ))
+    }
+
     sink_as_bytes!();
 }
 
@@ -0,0 +1,629 @@
+use super::{Sink, SinkError, SinkResult};
+use crate::NUM_CHANNELS;
+use crate::convert::Converter;
+use crate::decoder::AudioPacket;
+use crate::dither::DithererBuilder;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// How long a write waits for room in a full buffer before the packet is dropped, so that
+// a stalled output can't block the player.
+const OVERRUN_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Discarded audio is faded out over this time instead of cut off, to avoid a click.
+const DISCARD_FADE_DURATION: Duration = Duration::from_millis(10);
+
+const STATE_POISON_MSG: &str = "buffered sink state mutex should not be poisoned";
+
+/// Counts of the underruns and overruns of a [`BufferedSink`] since it was created.
+#[derive(Debug, Default)]
+pub struct BufferedSinkStats {
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+}
+
+impl BufferedSinkStats {
+    /// The number of times the buffer ran empty while playing.
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// The number of packets that were dropped because the buffer stayed full.
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Request {
+    Start,
+    Stop,
+}
+
+#[derive(Default)]
+struct State {
+    packets: VecDeque<AudioPacket>,
+    // Samples in `packets`. Raw packets have no samples, so their bytes are counted to
+    // keep the buffer bounded.
+    buffered: usize,
+    request: Option<Request>,
+    response: Option<SinkResult<()>>,
+    running: bool,
+    // Waiting for the buffer to fill up to the prefill level, after starting or an underrun.
+    filling: bool,
+    error: Option<SinkError>,
+    sink_delay: Option<Duration>,
+    shutdown: bool,
+    // The output thread is gone, for instance because the sink panicked.
+    finished: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    stats: Arc<BufferedSinkStats>,
+    // Whether the player waits for the output thread, see `BufferedSink::non_blocking`.
+    blocking: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect(STATE_POISON_MSG)
+    }
+
+    fn wait<'a>(&self, state: MutexGuard<'a, State>) -> MutexGuard<'a, State> {
+        self.changed.wait(state).expect(STATE_POISON_MSG)
+    }
+}
+
+enum Job {
+    Request(Request),
+    Write(AudioPacket),
+}
+
+/// Plays to a sink from a separate output thread, through a buffer of a fixed duration.
+///
+/// This decouples the sink from the player thread, so that a slow decode or a network
+/// stall doesn't directly cause an xrun.
+///
+/// The buffered audio is dropped by `discard`, which the player does before pausing,
+/// seeking or skipping. Otherwise it is played before the sink stops, like at the end of
+/// the last track.
+pub struct BufferedSink {
+    shared: Arc<Shared>,
+    capacity: usize,
+    sample_rate: u32,
+    // Dropping audio since the last packet that fit in the buffer.
+    dropping: bool,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BufferedSink {
+    pub const NAME: &'static str = "buffered";
+
+    /// Spawns the output thread, which builds the sink with `sink_builder`. The thread
+    /// converts the samples itself, with a converter for `ditherer`.
+    pub fn new<F>(
+        sink_builder: F,
+        ditherer: Option<DithererBuilder>,
+        sample_rate: u32,
+        size: Duration,
+    ) -> Self
+    where
+        F: FnOnce() -> Box<dyn Sink> + Send + 'static,
+    {
+        Self::spawn(
+            move || Ok(sink_builder()),
+            ditherer,
+            sample_rate,
+            size,
+            true,
+        )
+        .0
+    }
+
+    /// Like [`new`](Self::new), but with a builder that can fail. This waits for the
+    /// output thread to build the sink, and returns the error if it couldn't.
+    pub fn try_new<F>(
+        sink_builder: F,
+        ditherer: Option<DithererBuilder>,
+        sample_rate: u32,
+        size: Duration,
+    ) -> SinkResult<Self>
+    where
+        F: FnOnce() -> SinkResult<Box<dyn Sink>> + Send + 'static,
+    {
+        let (sink, built) = Self::spawn(sink_builder, ditherer, sample_rate, size, true);
+
+        match built.recv() {
+            Ok(Ok(())) => Ok(sink),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(finished_error()),
+        }
+    }
+
+    /// Like [`try_new`](Self::try_new), but never holds up the caller, for outputs that
+    /// should not stall the player. Packets that don't fit in the buffer are dropped right
+    /// away, and `start` and `stop` return without waiting for the sink. Errors, including
+    /// the one of the builder, are returned by a later write.
+    pub fn non_blocking<F>(
+        sink_builder: F,
+        ditherer: Option<DithererBuilder>,
+        sample_rate: u32,
+        size: Duration,
+    ) -> Self
+    where
+        F: FnOnce() -> SinkResult<Box<dyn Sink>> + Send + 'static,
+    {
+        Self::spawn(sink_builder, ditherer, sample_rate, size, false).0
+    }
+
+    fn spawn<F>(
+        sink_builder: F,
+        ditherer: Option<DithererBuilder>,
+        sample_rate: u32,
+        size: Duration,
+        blocking: bool,
+    ) -> (Self, mpsc::Receiver<SinkResult<()>>)
+    where
+        F: FnOnce() -> SinkResult<Box<dyn Sink>> + Send + 'static,
+    {
+        let capacity =
+            ((size.as_secs_f64() * sample_rate as f64) as usize).max(1) * NUM_CHANNELS as usize;
+
+        info!("Using BufferedSink with a {} ms buffer", size.as_millis());
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+            stats: Arc::new(BufferedSinkStats::default()),
+            blocking,
+        });
+
+        let (built_tx, built_rx) = mpsc::channel();
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || {
+            let _guard = FinishedGuard(thread_shared.clone());
+            let sink = match sink_builder() {
+                Ok(sink) => sink,
+                Err(e) => {
+                    // Nobody waits for the builder of a non-blocking sink.
+                    if let Err(mpsc::SendError(Err(e))) = built_tx.send(Err(e)) {
+                        thread_shared.lock().error = Some(e);
+                    }
+                    return;
+                }
+            };
+            let _ = built_tx.send(Ok(()));
+
+            let converter = Converter::new(ditherer);
+            run_output(&thread_shared, sink, converter, capacity / 2);
+        });
+
+        let sink = Self {
+            shared,
+            capacity,
+            sample_rate,
+            dropping: false,
+            thread: Some(thread),
+        };
+
+        (sink, built_rx)
+    }
+
+    pub fn stats(&self) -> Arc<BufferedSinkStats> {
+        self.shared.stats.clone()
+    }
+
+    fn request(&mut self, request: Request) -> SinkResult<()> {
+        let mut state = self.shared.lock();
+        state.request = Some(request);
+        state.response = None;
+        self.shared.changed.notify_all();
+
+        if !self.shared.blocking {
+            return match state.error.take() {
+                Some(e) => Err(e),
+                None if state.finished => Err(finished_error()),
+                None => Ok(()),
+            };
+        }
+
+        loop {
+            if let Some(response) = state.response.take() {
+                return response;
+            }
+            if state.finished {
+                return Err(finished_error());
+            }
+            state = self.shared.wait(state);
+        }
+    }
+}
+
+impl Sink for BufferedSink {
+    fn start(&mut self) -> SinkResult<()> {
+        self.shared.lock().error = None;
+        self.request(Request::Start)
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        self.request(Request::Stop)
+    }
+
+    fn write(&mut self, packet: AudioPacket, _converter: &mut Converter) -> SinkResult<()> {
+        let len = packet_len(&packet);
+        let timeout = if self.shared.blocking {
+            OVERRUN_TIMEOUT
+        } else {
+            Duration::ZERO
+        };
+        let deadline = Instant::now() + timeout;
+
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(e) = state.error.take() {
+                return Err(e);
+            }
+            if state.finished {
+                return Err(finished_error());
+            }
+            if state.packets.is_empty() || state.buffered + len <= self.capacity {
+                break;
+            }
+
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                self.shared.stats.overruns.fetch_add(1, Ordering::Relaxed);
+                if !self.dropping {
+                    warn!("<BufferedSink> Output is stalled, dropping audio");
+                    self.dropping = true;
+                }
+                return Ok(());
+            }
+
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, timeout)
+                .expect(STATE_POISON_MSG)
+                .0;
+        }
+
+        state.buffered += len;
+        state.packets.push_back(packet);
+        self.shared.changed.notify_all();
+        self.dropping = false;
+
+        Ok(())
+    }
+
+    fn discard(&mut self) {
+        let fade_len = (DISCARD_FADE_DURATION.as_secs_f64() * self.sample_rate as f64) as usize
+            * NUM_CHANNELS as usize;
+
+        let mut state = self.shared.lock();
+        let mut fade = Vec::with_capacity(fade_len);
+        for packet in state.packets.drain(..) {
+            if fade.len() >= fade_len {
+                break;
+            }
+            if let AudioPacket::Samples(samples) = packet {
+                fade.extend_from_slice(&samples);
+            }
+        }
+        fade.truncate(fade_len);
+
+        let frames = fade.len() / NUM_CHANNELS as usize;
+        for (i, frame) in fade.chunks_exact_mut(NUM_CHANNELS as usize).enumerate() {
+            let gain = 1.0 - (i + 1) as f64 / frames as f64;
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+
+        state.buffered = fade.len();
+        if !fade.is_empty() {
+            state.packets.push_back(AudioPacket::Samples(fade));
+        }
+        self.shared.changed.notify_all();
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        let state = self.shared.lock();
+        let frames = state.buffered / NUM_CHANNELS as usize;
+        let buffered = Duration::from_secs_f64(frames as f64 / self.sample_rate as f64);
+
+        Some(buffered + state.sink_delay.unwrap_or_default())
+    }
+}
+
+impl Drop for BufferedSink {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.changed.notify_all();
+
+        // A stalled sink would hold up the player, so the output thread of a non-blocking
+        // sink is left to end by itself.
+        if !self.shared.blocking {
+            return;
+        }
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("<BufferedSink> Output thread panicked");
+            }
+        }
+    }
+}
+
+fn run_output(shared: &Shared, mut sink: Box<dyn Sink>, mut converter: Converter, prefill: usize) {
+    loop {
+        let job = {
+            let mut state = shared.lock();
+            loop {
+                if state.shutdown {
+                    return;
+                }
+
+                let draining = match state.request {
+                    Some(Request::Start) => break Job::Request(Request::Start),
+                    Some(Request::Stop) if state.packets.is_empty() => {
+                        break Job::Request(Request::Stop);
+                    }
+                    Some(Request::Stop) => true,
+                    None => false,
+                };
+
+                if state.filling && (state.buffered >= prefill || draining) {
+                    state.filling = false;
+                }
+
+                if !state.filling {
+                    if let Some(packet) = state.packets.pop_front() {
+                        state.buffered -= packet_len(&packet);
+                        break Job::Write(packet);
+                    }
+
+                    if state.running {
+                        shared.stats.underruns.fetch_add(1, Ordering::Relaxed);
+                        warn!("<BufferedSink> Buffer underrun");
+                        state.filling = true;
+                    }
+                }
+
+                state = shared.wait(state);
+            }
+        };
+
+        // Room in the buffer for the player.
+        shared.changed.notify_all();
+
+        match job {
+            Job::Write(packet) => {
+                let result = sink.write(packet, &mut converter);
+                let sink_delay = sink.delay();
+
+                let mut state = shared.lock();
+                state.sink_delay = sink_delay;
+                if let Err(e) = result {
+                    state.error = Some(e);
+                }
+            }
+            Job::Request(request) => {
+                let result = match request {
+                    Request::Start => sink.start(),
+                    Request::Stop => sink.stop(),
+                };
+
+                let mut state = shared.lock();
+                state.running = request == Request::Start && result.is_ok();
+                state.filling = state.running;
+                // A newer request may have come in while this one was handled.
+                if state.request == Some(request) {
+                    state.request = None;
+                }
+                if shared.blocking {
+                    state.response = Some(result);
+                } else if let Err(e) = result {
+                    state.error = Some(e);
+                }
+                shared.changed.notify_all();
+            }
+        }
+    }
+}
+
+// Lets a waiting player know when the output thread ends, even when it panics.
+struct FinishedGuard(Arc<Shared>);
+
+impl Drop for FinishedGuard {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.0.state.lock() {
+            state.finished = true;
+        }
+        self.0.changed.notify_all();
+    }
+}
+
+fn packet_len(packet: &AudioPacket) -> usize {
+    match packet {
+        AudioPacket::Samples(samples) => samples.len(),
+        AudioPacket::Raw(bytes) => bytes.len(),
+    }
+}
+
+fn finished_error() -> SinkError {
+    SinkError::NotConnected("BufferedSink output thread has stopped".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SAMPLE_RATE;
+
+    const SIZE: Duration = Duration::from_millis(200);
+    // Samples in a buffer of `SIZE`.
+    const CAPACITY: usize = (SAMPLE_RATE / 5 * NUM_CHANNELS as u32) as usize;
+
+    // Records the samples that are written, and holds up each write until `gate` sends or
+    // is dropped.
+    struct TestSink {
+        written: Written,
+        gate: Option<mpsc::Receiver<()>>,
+    }
+
+    impl Sink for TestSink {
+        fn write(&mut self, packet: AudioPacket, _converter: &mut Converter) -> SinkResult<()> {
+            if let AudioPacket::Samples(samples) = packet {
+                self.written.lock().unwrap().extend(samples);
+            }
+            if let Some(gate) = &self.gate {
+                let _ = gate.recv();
+            }
+            Ok(())
+        }
+    }
+
+    type Written = Arc<Mutex<Vec<f64>>>;
+
+    fn test_sink(gated: bool, blocking: bool) -> (BufferedSink, Written, Option<mpsc::Sender<()>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let (gate_tx, gate_rx) = mpsc::channel();
+        let sink = TestSink {
+            written: written.clone(),
+            gate: gated.then_some(gate_rx),
+        };
+
+        let (buffered, _) = BufferedSink::spawn(
+            move || Ok(Box::new(sink) as Box<dyn Sink>),
+            None,
+            SAMPLE_RATE,
+            SIZE,
+            blocking,
+        );
+
+        (buffered, written, gated.then_some(gate_tx))
+    }
+
+    fn packet(len: usize, sample: f64) -> AudioPacket {
+        AudioPacket::Samples(vec![sample; len])
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn prefills_and_plays_everything_before_stopping() {
+        let (mut sink, written, _) = test_sink(false, true);
+        let mut converter = Converter::new(None);
+
+        sink.start().unwrap();
+        sink.write(packet(CAPACITY / 4, 0.25), &mut converter)
+            .unwrap();
+
+        // Less than the prefill level is held back.
+        thread::sleep(Duration::from_millis(50));
+        assert!(written.lock().unwrap().is_empty());
+        assert_eq!(sink.delay(), Some(SIZE / 4));
+
+        sink.write(packet(CAPACITY / 4, 0.5), &mut converter)
+            .unwrap();
+        sink.stop().unwrap();
+
+        let written = written.lock().unwrap();
+        assert_eq!(written.len(), CAPACITY / 2);
+        assert!(written[..CAPACITY / 4].iter().all(|&sample| sample == 0.25));
+        assert!(written[CAPACITY / 4..].iter().all(|&sample| sample == 0.5));
+        assert_eq!(sink.delay(), Some(Duration::ZERO));
+        assert_eq!(sink.stats().underruns(), 0);
+        assert_eq!(sink.stats().overruns(), 0);
+    }
+
+    #[test]
+    fn counts_underruns() {
+        let (mut sink, written, _) = test_sink(false, true);
+        let mut converter = Converter::new(None);
+        let stats = sink.stats();
+
+        sink.start().unwrap();
+        sink.write(packet(CAPACITY / 2, 0.0), &mut converter)
+            .unwrap();
+        wait_until(|| stats.underruns() == 1);
+
+        // After an underrun the buffer fills up to the prefill level again.
+        sink.write(packet(CAPACITY / 4, 0.0), &mut converter)
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(written.lock().unwrap().len(), CAPACITY / 2);
+        assert_eq!(stats.underruns(), 1);
+
+        sink.stop().unwrap();
+        assert_eq!(written.lock().unwrap().len(), CAPACITY * 3 / 4);
+        assert_eq!(stats.overruns(), 0);
+    }
+
+    #[test]
+    fn drops_packets_when_full() {
+        const PACKETS: usize = 10;
+
+        let (mut sink, written, gate) = test_sink(true, false);
+        let mut converter = Converter::new(None);
+        let stats = sink.stats();
+
+        sink.start().unwrap();
+        for _ in 0..PACKETS {
+            // Never waits for the stalled sink.
+            let started = Instant::now();
+            sink.write(packet(CAPACITY, 0.0), &mut converter).unwrap();
+            assert!(started.elapsed() < OVERRUN_TIMEOUT / 2);
+        }
+
+        // At most one packet is in the sink and one in the buffer.
+        assert!(stats.overruns() >= PACKETS as u64 - 2);
+
+        drop(gate);
+        wait_until(|| sink.delay() == Some(Duration::ZERO));
+        let played = written.lock().unwrap().len() / CAPACITY;
+        assert_eq!(played as u64 + stats.overruns(), PACKETS as u64);
+    }
+
+    #[test]
+    fn discard_fades_out_the_buffered_audio() {
+        let (mut sink, written, gate) = test_sink(true, true);
+        let mut converter = Converter::new(None);
+
+        sink.start().unwrap();
+        sink.write(packet(CAPACITY / 2, 1.0), &mut converter)
+            .unwrap();
+        wait_until(|| !written.lock().unwrap().is_empty());
+
+        // The sink is busy with the first packet, so these stay in the buffer.
+        sink.write(packet(CAPACITY / 4, 0.5), &mut converter)
+            .unwrap();
+        sink.write(packet(CAPACITY / 4, 0.5), &mut converter)
+            .unwrap();
+        sink.discard();
+        assert_eq!(sink.delay(), Some(DISCARD_FADE_DURATION));
+
+        drop(gate);
+        sink.stop().unwrap();
+
+        let fade_frames = SAMPLE_RATE as usize / 100;
+        let written = written.lock().unwrap();
+        let (played, fade) = written.split_at(CAPACITY / 2);
+        assert!(played.iter().all(|&sample| sample == 1.0));
+        assert_eq!(fade.len(), fade_frames * NUM_CHANNELS as usize);
+
+        let frames: Vec<&[f64]> = fade.chunks_exact(NUM_CHANNELS as usize).collect();
+        assert!(frames[0][0] < 0.5 && frames[0][0] > 0.49);
+        assert!(frames.windows(2).all(|pair| pair[1][0] < pair[0][0]));
+        assert_eq!(frames[fade_frames - 1], [0.0, 0.0]);
+    }
+}
@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use gstreamer::{
     State,
@@ -14,21 +17,20 @@ const GSTREAMER_ASYNC_ERROR_POISON_MSG: &str = "gstreamer async error mutex shou
 
 use super::{Open, Sink, SinkAsBytes, SinkError, SinkResult};
 
-use crate::{
-    NUM_CHANNELS, SAMPLE_RATE, config::AudioFormat, convert::Converter, decoder::AudioPacket,
-};
+use crate::{NUM_CHANNELS, config::AudioFormat, convert::Converter, decoder::AudioPacket};
 
 pub struct GstreamerSink {
     appsrc: gst_app::AppSrc,
     bufferpool: gst::BufferPool,
     pipeline: gst::Pipeline,
     format: AudioFormat,
+    sample_rate: u32,
     async_error: Arc<Mutex<Option<String>>>,
 }
 
 impl Open for GstreamerSink {
-    fn open(device: Option<String>, format: AudioFormat) -> Self {
-        info!("Using GStreamer sink with format: {format:?}");
+    fn open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> Self {
+        info!("Using GStreamer sink with format: {format:?}, sample rate: {sample_rate}");
         gst::init().expect("failed to init GStreamer!");
 
         let gst_format = match format {
@@ -40,7 +42,7 @@ impl Open for GstreamerSink {
             AudioFormat::S16 => gst_audio::AUDIO_FORMAT_S16,
         };
 
-        let gst_info = gst_audio::AudioInfo::builder(gst_format, SAMPLE_RATE, NUM_CHANNELS as u32)
+        let gst_info = gst_audio::AudioInfo::builder(gst_format, sample_rate, NUM_CHANNELS as u32)
             .build()
             .expect("Failed to create GStreamer audio format");
         let gst_caps = gst_info.to_caps().expect("Failed to create GStreamer caps");
@@ -136,6 +138,7 @@ impl Open for GstreamerSink {
             bufferpool,
             pipeline,
             format,
+            sample_rate,
             async_error,
         }
     }
@@ -172,6 +175,23 @@ impl Sink for GstreamerSink {
         Ok(())
     }
 
+    fn delay(&self) -> Option<Duration> {
+        // What is queued in the appsrc, plus the latency of the rest of the pipeline.
+        let bytes_per_second =
+            self.sample_rate as usize * NUM_CHANNELS as usize * self.format.size();
+        let queued = Duration::from_secs_f64(
+            self.appsrc.current_level_bytes() as f64 / bytes_per_second as f64,
+        );
+
+        let mut query = gst::query::Latency::new();
+        if !self.pipeline.query(&mut query) {
+            return Some(queued);
+        }
+        let (_, min_latency, _) = query.result();
+
+        Some(queued + Duration::from_nanos(min_latency.nseconds()))
+    }
+
     sink_as_bytes!();
 }
 
@@ -4,19 +4,30 @@ use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
 use jack::{
-    AsyncClient, AudioOut, Client, ClientOptions, Control, Port, ProcessHandler, ProcessScope,
+    AsyncClient, AudioOut, Client, ClientOptions, Control, LatencyType, Port, ProcessHandler,
+    ProcessScope,
+};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    time::Duration,
 };
-use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
 
 pub struct JackSink {
     send: SyncSender<f32>,
+    // Samples that were sent, but not yet taken by the process callback.
+    queued: Arc<AtomicUsize>,
+    port_name: String,
     // We have to keep hold of this object, or the Sink can't play...
-    #[allow(dead_code)]
     active_client: AsyncClient<(), JackData>,
 }
 
 pub struct JackData {
     rec: Receiver<f32>,
+    queued: Arc<AtomicUsize>,
     port_l: Port<AudioOut>,
     port_r: Port<AudioOut>,
 }
@@ -28,40 +39,79 @@ impl ProcessHandler for JackData {
         let buf_l: &mut [f32] = self.port_l.as_mut_slice(ps);
         // get queue iterator
         let mut queue_iter = self.rec.try_iter();
+        let mut taken = 0;
 
         for i in 0..buf_r.len() {
-            buf_r[i] = queue_iter.next().unwrap_or(0.0);
-            buf_l[i] = queue_iter.next().unwrap_or(0.0);
+            for sample in [&mut buf_r[i], &mut buf_l[i]] {
+                *sample = match queue_iter.next() {
+                    Some(queued) => {
+                        taken += 1;
+                        queued
+                    }
+                    None => 0.0,
+                };
+            }
         }
+
+        self.queued.fetch_sub(taken, Ordering::Relaxed);
         Control::Continue
     }
 }
 
 impl Open for JackSink {
-    fn open(client_name: Option<String>, format: AudioFormat) -> Self {
+    fn open(client_name: Option<String>, format: AudioFormat, sample_rate: u32) -> Self {
+        match Self::try_open(client_name, format, sample_rate) {
+            Ok(sink) => sink,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    fn try_open(
+        client_name: Option<String>,
+        format: AudioFormat,
+        sample_rate: u32,
+    ) -> SinkResult<Self> {
         if format != AudioFormat::F32 {
             warn!("JACK currently does not support {format:?} output");
         }
         info!("Using JACK sink with format {:?}", AudioFormat::F32);
 
         let client_name = client_name.unwrap_or_else(|| "librespot".to_string());
-        let (client, _status) =
-            Client::new(&client_name[..], ClientOptions::NO_START_SERVER).unwrap();
-        let ch_r = client.register_port("out_0", AudioOut::default()).unwrap();
-        let ch_l = client.register_port("out_1", AudioOut::default()).unwrap();
+        let (client, _status) = Client::new(&client_name[..], ClientOptions::NO_START_SERVER)
+            .map_err(|e| SinkError::NotConnected(e.to_string()))?;
+        if client.sample_rate() != sample_rate as usize {
+            warn!(
+                "JACK is running at {} Hz, but the output sample rate is {sample_rate} Hz",
+                client.sample_rate()
+            );
+        }
+        let ch_r = client
+            .register_port("out_0", AudioOut::default())
+            .map_err(|e| SinkError::ConnectionRefused(e.to_string()))?;
+        let ch_l = client
+            .register_port("out_1", AudioOut::default())
+            .map_err(|e| SinkError::ConnectionRefused(e.to_string()))?;
+        let port_name = ch_r
+            .name()
+            .map_err(|e| SinkError::ConnectionRefused(e.to_string()))?;
         // buffer for samples from librespot (~10ms)
         let (tx, rx) = sync_channel::<f32>(NUM_CHANNELS as usize * 1024 * AudioFormat::F32.size());
+        let queued = Arc::new(AtomicUsize::new(0));
         let jack_data = JackData {
             rec: rx,
+            queued: queued.clone(),
             port_l: ch_l,
             port_r: ch_r,
         };
-        let active_client = AsyncClient::new(client, (), jack_data).unwrap();
+        let active_client = AsyncClient::new(client, (), jack_data)
+            .map_err(|e| SinkError::ConnectionRefused(e.to_string()))?;
 
-        Self {
+        Ok(Self {
             send: tx,
+            queued,
+            port_name,
             active_client,
-        }
+        })
     }
 }
 
@@ -72,14 +122,32 @@ impl Sink for JackSink {
             .map_err(|e| SinkError::OnWrite(e.to_string()))?;
 
         let samples_f32: &[f32] = &converter.f64_to_f32(samples);
-        for sample in samples_f32.iter() {
-            let res = self.send.send(*sample);
-            if res.is_err() {
-                error!("cannot write to channel");
+        // Counted before sending, so that the process callback never takes more than was
+        // counted, and uncounted again for the samples that could not be sent.
+        self.queued.fetch_add(samples_f32.len(), Ordering::Relaxed);
+        for (sent, sample) in samples_f32.iter().enumerate() {
+            if self.send.send(*sample).is_err() {
+                self.queued
+                    .fetch_sub(samples_f32.len() - sent, Ordering::Relaxed);
+                return Err(SinkError::OnWrite(
+                    "cannot write to channel, the JACK client has stopped".to_string(),
+                ));
             }
         }
         Ok(())
     }
+
+    fn delay(&self) -> Option<Duration> {
+        let client = self.active_client.as_client();
+        let port = client.port_by_name(&self.port_name)?;
+        let (_, latency_frames) = port.get_latency_range(LatencyType::Playback);
+
+        let queued_frames = self.queued.load(Ordering::Relaxed) / NUM_CHANNELS as usize;
+
+        Some(Duration::from_secs_f64(
+            (queued_frames + latency_frames as usize) as f64 / client.sample_rate() as f64,
+        ))
+    }
 }
 
 impl JackSink {
@@ -1,6 +1,8 @@
 use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -20,7 +22,17 @@ pub enum SinkError {
 pub type SinkResult<T> = Result<T, SinkError>;
 
 pub trait Open {
-    fn open(_: Option<String>, format: AudioFormat) -> Self;
+    fn open(_: Option<String>, format: AudioFormat, sample_rate: u32) -> Self;
+
+    /// Like `open`, but returns an error instead of panicking or exiting the process when
+    /// the device can't be opened. Backends that only open their device once started
+    /// don't need to implement this.
+    fn try_open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> SinkResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self::open(device, format, sample_rate))
+    }
 }
 
 pub trait Sink {
@@ -31,16 +43,45 @@ pub trait Sink {
         Ok(())
     }
     fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()>;
+    /// Drops audio that was written but not played yet, before playback jumps elsewhere.
+    /// Only sinks that buffer a lot of audio themselves need this.
+    fn discard(&mut self) {}
+    /// The time until audio that is written now will be heard, if the backend can tell.
+    fn delay(&self) -> Option<Duration> {
+        None
+    }
 }
 
-pub type SinkBuilder = fn(Option<String>, AudioFormat) -> Box<dyn Sink>;
+pub type SinkBuilder = fn(Option<String>, AudioFormat, u32) -> Box<dyn Sink>;
+
+type TrySinkBuilder = fn(Option<String>, AudioFormat, u32) -> SinkResult<Box<dyn Sink>>;
+
+/// An output device of a backend.
+#[derive(Debug, Clone)]
+pub struct AudioDevice {
+    /// The name to pass as the device of the backend.
+    pub name: String,
+    pub description: Option<String>,
+}
 
 pub trait SinkAsBytes {
     fn write_bytes(&mut self, data: &[u8]) -> SinkResult<()>;
 }
 
-fn mk_sink<S: Sink + Open + 'static>(device: Option<String>, format: AudioFormat) -> Box<dyn Sink> {
-    Box::new(S::open(device, format))
+fn mk_sink<S: Sink + Open + 'static>(
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> Box<dyn Sink> {
+    Box::new(S::open(device, format, sample_rate))
+}
+
+fn try_mk_sink<S: Sink + Open + 'static>(
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> SinkResult<Box<dyn Sink>> {
+    Ok(Box::new(S::try_open(device, format, sample_rate)?))
 }
 
 // reuse code for various backends
@@ -95,6 +136,13 @@ mod pulseaudio;
 #[cfg(feature = "pulseaudio-backend")]
 use self::pulseaudio::PulseAudioSink;
 
+#[cfg(feature = "pipewire-backend")]
+mod pipewire;
+#[cfg(feature = "pipewire-backend")]
+use self::pipewire::PipeWireSink;
+#[cfg(feature = "pipewire-backend")]
+use crate::mixer::pipewiremixer::PipeWireVolume;
+
 #[cfg(feature = "jackaudio-backend")]
 mod jackaudio;
 #[cfg(feature = "jackaudio-backend")]
@@ -121,6 +169,12 @@ use self::pipe::StdoutSink;
 mod subprocess;
 use self::subprocess::SubprocessSink;
 
+mod tee;
+pub use self::tee::{TeeOutput, TeeSink};
+
+mod buffered;
+pub use self::buffered::{BufferedSink, BufferedSinkStats};
+
 pub const BACKENDS: &[(&str, SinkBuilder)] = &[
     #[cfg(feature = "rodio-backend")]
     (RodioSink::NAME, rodio::mk_rodio), // default goes first
@@ -130,6 +184,8 @@ pub const BACKENDS: &[(&str, SinkBuilder)] = &[
     (PortAudioSink::NAME, mk_sink::<PortAudioSink<'_>>),
     #[cfg(feature = "pulseaudio-backend")]
     (PulseAudioSink::NAME, mk_sink::<PulseAudioSink>),
+    #[cfg(feature = "pipewire-backend")]
+    (PipeWireSink::NAME, mk_sink::<PipeWireSink>),
     #[cfg(feature = "jackaudio-backend")]
     (JackSink::NAME, mk_sink::<JackSink>),
     #[cfg(feature = "gstreamer-backend")]
@@ -142,6 +198,107 @@ pub const BACKENDS: &[(&str, SinkBuilder)] = &[
     (SubprocessSink::NAME, mk_sink::<SubprocessSink>),
 ];
 
+/// Lists the output devices of a backend that play at the output sample rate. Backends
+/// that can't list their devices return an empty list.
+#[cfg_attr(not(feature = "alsa-backend"), allow(unused_variables))]
+pub fn list_devices(backend: &str, sample_rate: u32) -> SinkResult<Vec<AudioDevice>> {
+    match backend {
+        #[cfg(feature = "rodio-backend")]
+        RodioSink::NAME => rodio::list_devices(&cpal::default_host()),
+        #[cfg(feature = "rodiojack-backend")]
+        "rodiojack" => match cpal::host_from_id(cpal::HostId::Jack) {
+            Ok(host) => rodio::list_devices(&host),
+            Err(e) => Err(SinkError::NotConnected(e.to_string())),
+        },
+        #[cfg(feature = "alsa-backend")]
+        AlsaSink::NAME => alsa::list_devices(sample_rate),
+        #[cfg(feature = "portaudio-backend")]
+        PortAudioSink::NAME => Ok(portaudio::list_devices()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Opens a sink of the backend with this name, like its [`SinkBuilder`], but returns an error
+/// when the device can't be opened, so that a bad device can't take down a running player.
+///
+/// Backends return their errors through [`Open::try_open`]. Those that don't implement it
+/// panic instead, which is caught as well unless panics abort. The `?` device, which the
+/// backends answer by printing their devices and exiting, is refused.
+pub fn open_sink(
+    backend: &str,
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> SinkResult<Box<dyn Sink>> {
+    if device.as_deref() == Some("?") {
+        return Err(SinkError::InvalidParams(
+            "Listing the devices with `?` is only supported at startup".to_string(),
+        ));
+    }
+
+    let try_builder: TrySinkBuilder = match backend {
+        #[cfg(feature = "rodio-backend")]
+        RodioSink::NAME => rodio::try_mk_rodio,
+        #[cfg(feature = "alsa-backend")]
+        AlsaSink::NAME => try_mk_sink::<AlsaSink>,
+        #[cfg(feature = "portaudio-backend")]
+        PortAudioSink::NAME => try_mk_sink::<PortAudioSink<'_>>,
+        #[cfg(feature = "pulseaudio-backend")]
+        PulseAudioSink::NAME => try_mk_sink::<PulseAudioSink>,
+        #[cfg(feature = "pipewire-backend")]
+        PipeWireSink::NAME => try_mk_sink::<PipeWireSink>,
+        #[cfg(feature = "jackaudio-backend")]
+        JackSink::NAME => try_mk_sink::<JackSink>,
+        #[cfg(feature = "gstreamer-backend")]
+        GstreamerSink::NAME => try_mk_sink::<GstreamerSink>,
+        #[cfg(feature = "rodiojack-backend")]
+        "rodiojack" => rodio::try_mk_rodiojack,
+        #[cfg(feature = "sdl-backend")]
+        SdlSink::NAME => try_mk_sink::<SdlSink>,
+        StdoutSink::NAME => try_mk_sink::<StdoutSink>,
+        SubprocessSink::NAME => try_mk_sink::<SubprocessSink>,
+        _ => {
+            return Err(SinkError::InvalidParams(format!(
+                "Unknown audio backend: {backend}"
+            )));
+        }
+    };
+
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        try_builder(device.clone(), format, sample_rate)
+    }))
+    .map_err(|payload| {
+        let reason = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("the backend panicked");
+
+        SinkError::ConnectionRefused(format!(
+            "Unable to open device {}: {reason}",
+            device.as_deref().unwrap_or("default")
+        ))
+    })?
+}
+
+/// Opens a `pipewire` sink whose stream volume is controlled by the `pipewire` mixer that was
+/// opened with the same `volume`, see
+/// [`MixerConfig::pipewire_volume`](crate::mixer::MixerConfig::pipewire_volume).
+#[cfg(feature = "pipewire-backend")]
+pub fn open_pipewire(
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+    volume: PipeWireVolume,
+) -> Box<dyn Sink> {
+    Box::new(PipeWireSink::with_volume(
+        device,
+        format,
+        sample_rate,
+        volume,
+    ))
+}
+
 pub fn find(name: Option<String>) -> Option<SinkBuilder> {
     if let Some(name) = name {
         BACKENDS
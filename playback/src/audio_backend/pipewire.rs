@@ -0,0 +1,441 @@
+use super::{Open, Sink, SinkAsBytes, SinkError, SinkResult};
+use crate::NUM_CHANNELS;
+use crate::config::AudioFormat;
+use crate::convert::Converter;
+use crate::decoder::AudioPacket;
+use crate::mixer::pipewiremixer::PipeWireVolume;
+use pipewire::{
+    self as pw,
+    properties::properties,
+    spa::{
+        self,
+        param::audio::{AudioFormat as SpaAudioFormat, AudioInfoRaw},
+        pod::{Object, Pod, Value, serialize::PodSerializer},
+    },
+    stream::{Stream, StreamFlags, StreamState},
+};
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::rc::Rc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+// Audio that is buffered ahead of the stream, which paces the writes.
+const BUFFER_DURATION: Duration = Duration::from_millis(100);
+
+// A stream that takes nothing for this long is considered broken.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const NODE_NAME: &str = "librespot";
+
+const BUFFER_POISON_MSG: &str = "PipeWire buffer mutex should not be poisoned";
+
+#[derive(Debug, Error)]
+enum PipeWireError {
+    #[error("<PipeWireSink> Unable to Connect to PipeWire, {0}")]
+    ConnectionRefused(String),
+
+    #[error("<PipeWireSink> Stream Error, {0}")]
+    Stream(String),
+
+    #[error("<PipeWireSink> Stream Stopped Playing")]
+    Stalled,
+
+    #[error("<PipeWireSink>")]
+    NotConnected,
+}
+
+impl From<PipeWireError> for SinkError {
+    fn from(e: PipeWireError) -> SinkError {
+        use PipeWireError::*;
+        let es = e.to_string();
+        match e {
+            Stream(_) | Stalled => SinkError::OnWrite(es),
+            ConnectionRefused(_) => SinkError::ConnectionRefused(es),
+            NotConnected => SinkError::NotConnected(es),
+        }
+    }
+}
+
+// Audio written by the player and taken by the stream, which runs on its own thread.
+#[derive(Default)]
+struct StreamBuffer {
+    bytes: VecDeque<u8>,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct Shared {
+    buffer: Mutex<StreamBuffer>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, StreamBuffer> {
+        self.buffer.lock().expect(BUFFER_POISON_MSG)
+    }
+
+    fn fail(&self, error: String) {
+        self.lock().error = Some(error);
+        self.changed.notify_all();
+    }
+
+    // Fills as much of the PipeWire buffer as there is audio for, in whole frames.
+    fn fill(&self, data: &mut [u8], stride: usize) -> usize {
+        let mut buffer = self.lock();
+        let len = buffer.bytes.len().min(data.len()) / stride * stride;
+
+        for (byte, sample_byte) in data.iter_mut().zip(buffer.bytes.drain(..len)) {
+            *byte = sample_byte;
+        }
+
+        self.changed.notify_all();
+        len
+    }
+}
+
+struct StreamThread {
+    quit: pw::channel::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+pub struct PipeWireSink {
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+    volume: PipeWireVolume,
+    shared: Arc<Shared>,
+    stream: Option<StreamThread>,
+}
+
+impl Open for PipeWireSink {
+    fn open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> Self {
+        Self::with_volume(device, format, sample_rate, PipeWireVolume::default())
+    }
+}
+
+impl Sink for PipeWireSink {
+    fn start(&mut self) -> SinkResult<()> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+
+        *self.shared.lock() = StreamBuffer::default();
+
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (quit_tx, quit_rx) = pw::channel::channel();
+        let stream_config = StreamConfig {
+            device: self.device.clone(),
+            format: spa_format(self.format),
+            stride: self.stride(),
+            sample_rate: self.sample_rate,
+            volume: self.volume.clone(),
+        };
+        let shared = self.shared.clone();
+
+        let handle = thread::spawn(move || {
+            if let Err(e) = run_stream(stream_config, shared, quit_rx, &ready_tx) {
+                let _ = ready_tx.send(Err(e.to_string()));
+            }
+        });
+
+        match ready_rx.recv_timeout(CONNECT_TIMEOUT) {
+            Ok(Ok(())) => {
+                self.stream = Some(StreamThread {
+                    quit: quit_tx,
+                    handle,
+                });
+                Ok(())
+            }
+            Ok(Err(e)) => Err(PipeWireError::ConnectionRefused(e).into()),
+            Err(_) => {
+                let _ = quit_tx.send(());
+                Err(PipeWireError::ConnectionRefused("timed out".to_string()).into())
+            }
+        }
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        let stream = self.stream.take().ok_or(PipeWireError::NotConnected)?;
+
+        // Let the stream play what is left.
+        let result = self.wait_for_room(0);
+
+        let _ = stream.quit.send(());
+        if stream.handle.join().is_err() {
+            error!("<PipeWireSink> Stream thread panicked");
+        }
+
+        result
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        self.stream.as_ref()?;
+
+        let frames = self.shared.lock().bytes.len() / self.stride();
+        Some(Duration::from_secs_f64(
+            frames as f64 / self.sample_rate as f64,
+        ))
+    }
+
+    sink_as_bytes!();
+}
+
+impl SinkAsBytes for PipeWireSink {
+    #[inline]
+    fn write_bytes(&mut self, data: &[u8]) -> SinkResult<()> {
+        if self.stream.is_none() {
+            return Err(PipeWireError::NotConnected.into());
+        }
+
+        let max_buffered =
+            (BUFFER_DURATION.as_secs_f64() * self.sample_rate as f64) as usize * self.stride();
+        self.wait_for_room(max_buffered.saturating_sub(data.len()))?;
+
+        self.shared.lock().bytes.extend(data);
+        Ok(())
+    }
+}
+
+impl PipeWireSink {
+    pub const NAME: &'static str = "pipewire";
+
+    /// Opens a sink whose stream volume is shared with a `PipeWireMixer`.
+    pub fn with_volume(
+        device: Option<String>,
+        format: AudioFormat,
+        sample_rate: u32,
+        volume: PipeWireVolume,
+    ) -> Self {
+        info!("Using PipeWireSink with format: {format:?}, sample rate: {sample_rate}");
+
+        Self {
+            device,
+            format,
+            sample_rate,
+            volume,
+            shared: Arc::new(Shared::default()),
+            stream: None,
+        }
+    }
+
+    fn stride(&self) -> usize {
+        self.format.size() * NUM_CHANNELS as usize
+    }
+
+    // Waits until no more than `max_buffered` bytes are left for the stream to play.
+    fn wait_for_room(&self, max_buffered: usize) -> SinkResult<()> {
+        let mut buffer = self.shared.lock();
+        let mut deadline = Instant::now() + STALL_TIMEOUT;
+        let mut buffered = buffer.bytes.len();
+
+        loop {
+            if let Some(e) = buffer.error.take() {
+                return Err(PipeWireError::Stream(e).into());
+            }
+            if buffer.bytes.len() <= max_buffered {
+                return Ok(());
+            }
+
+            // The stream is alive as long as it takes audio.
+            if buffer.bytes.len() < buffered {
+                buffered = buffer.bytes.len();
+                deadline = Instant::now() + STALL_TIMEOUT;
+            }
+
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(PipeWireError::Stalled.into());
+            }
+
+            buffer = self
+                .shared
+                .changed
+                .wait_timeout(buffer, timeout)
+                .expect(BUFFER_POISON_MSG)
+                .0;
+        }
+    }
+}
+
+struct StreamConfig {
+    device: Option<String>,
+    format: SpaAudioFormat,
+    stride: usize,
+    sample_rate: u32,
+    volume: PipeWireVolume,
+}
+
+fn spa_format(format: AudioFormat) -> SpaAudioFormat {
+    let big_endian = cfg!(target_endian = "big");
+
+    match (format, big_endian) {
+        (AudioFormat::F64, false) => SpaAudioFormat::F64LE,
+        (AudioFormat::F64, true) => SpaAudioFormat::F64BE,
+        (AudioFormat::F32, false) => SpaAudioFormat::F32LE,
+        (AudioFormat::F32, true) => SpaAudioFormat::F32BE,
+        (AudioFormat::S32, false) => SpaAudioFormat::S32LE,
+        (AudioFormat::S32, true) => SpaAudioFormat::S32BE,
+        (AudioFormat::S24, false) => SpaAudioFormat::S24_32LE,
+        (AudioFormat::S24, true) => SpaAudioFormat::S24_32BE,
+        (AudioFormat::S24_3, false) => SpaAudioFormat::S24LE,
+        (AudioFormat::S24_3, true) => SpaAudioFormat::S24BE,
+        (AudioFormat::S16, false) => SpaAudioFormat::S16LE,
+        (AudioFormat::S16, true) => SpaAudioFormat::S16BE,
+    }
+}
+
+fn set_stream_volume(stream: &pw::stream::StreamRef, volume: f32) {
+    if let Err(e) = stream.set_control(
+        spa::sys::SPA_PROP_channelVolumes,
+        &[volume; NUM_CHANNELS as usize],
+    ) {
+        warn!("<PipeWireSink> Unable to set the stream volume: {e}");
+    }
+}
+
+// Runs the PipeWire loop with the stream until told to quit.
+fn run_stream(
+    config: StreamConfig,
+    shared: Arc<Shared>,
+    quit: pw::channel::Receiver<()>,
+    ready: &mpsc::Sender<Result<(), String>>,
+) -> Result<(), pw::Error> {
+    pw::init();
+
+    let mainloop = pw::main_loop::MainLoop::new(None)?;
+    let context = pw::context::Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+
+    let mut props = properties! {
+        *pw::keys::MEDIA_TYPE => "Audio",
+        *pw::keys::MEDIA_CATEGORY => "Playback",
+        *pw::keys::MEDIA_ROLE => "Music",
+        *pw::keys::APP_NAME => NODE_NAME,
+        *pw::keys::NODE_NAME => NODE_NAME,
+    };
+    if let Some(device) = &config.device {
+        props.insert("target.object", device.as_str());
+    }
+
+    let stream = Rc::new(Stream::new(&core, NODE_NAME, props)?);
+    let stride = config.stride;
+    let volume = config.volume;
+
+    let state_shared = shared.clone();
+    let process_shared = shared;
+    let state_volume = volume.clone();
+    let control_volume = volume.clone();
+    let _listener = stream
+        .add_local_listener_with_user_data(())
+        .state_changed(move |stream, _, old, new| {
+            trace!("<PipeWireSink> Stream state changed from {old:?} to {new:?}");
+            match new {
+                // Negotiated, so the controls can be set.
+                StreamState::Paused if old == StreamState::Connecting => {
+                    if let Some(volume) = state_volume.volume() {
+                        set_stream_volume(stream, volume);
+                    }
+                }
+                StreamState::Error(e) => state_shared.fail(e),
+                StreamState::Unconnected => {
+                    state_shared.fail("disconnected from PipeWire".to_string())
+                }
+                _ => (),
+            }
+        })
+        .control_info(move |_, _, id, control| {
+            if id != spa::sys::SPA_PROP_channelVolumes || control.is_null() {
+                return;
+            }
+
+            // SAFETY: PipeWire passes a valid control, whose values are valid for the
+            // duration of the callback.
+            let values = unsafe {
+                let control = &*control;
+                if control.values.is_null() || control.n_values == 0 {
+                    return;
+                }
+                std::slice::from_raw_parts(control.values, control.n_values as usize)
+            };
+
+            let volume = values.iter().sum::<f32>() / values.len() as f32;
+            control_volume.stream_volume_changed(volume);
+        })
+        .process(move |stream, _| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let requested = buffer.requested() as usize;
+            let data = &mut buffer.datas_mut()[0];
+            let len = match data.data() {
+                Some(slice) => {
+                    let max_frames = slice.len() / stride;
+                    let frames = match requested {
+                        0 => max_frames,
+                        requested => requested.min(max_frames),
+                    };
+                    process_shared.fill(&mut slice[..frames * stride], stride)
+                }
+                None => 0,
+            };
+
+            let chunk = data.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = stride as _;
+            *chunk.size_mut() = len as _;
+        })
+        .register()?;
+
+    let mut audio_info = AudioInfoRaw::new();
+    audio_info.set_format(config.format);
+    audio_info.set_rate(config.sample_rate);
+    audio_info.set_channels(NUM_CHANNELS as u32);
+    let mut position = [0; spa::sys::SPA_AUDIO_MAX_CHANNELS as usize];
+    position[0] = spa::sys::SPA_AUDIO_CHANNEL_FL;
+    position[1] = spa::sys::SPA_AUDIO_CHANNEL_FR;
+    audio_info.set_position(position);
+
+    let values = PodSerializer::serialize(
+        Cursor::new(Vec::new()),
+        &Value::Object(Object {
+            type_: spa::sys::SPA_TYPE_OBJECT_Format,
+            id: spa::sys::SPA_PARAM_EnumFormat,
+            properties: audio_info.into(),
+        }),
+    )
+    .map_err(|_| pw::Error::CreationFailed)?
+    .0
+    .into_inner();
+    let mut params = [Pod::from_bytes(&values).ok_or(pw::Error::CreationFailed)?];
+
+    stream.connect(
+        spa::utils::Direction::Output,
+        None,
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS,
+        &mut params,
+    )?;
+
+    let (volume_tx, volume_rx) = pw::channel::channel();
+    let volume_stream = stream.clone();
+    let _volume_receiver = volume_rx.attach(mainloop.loop_(), move |volume| {
+        set_stream_volume(&volume_stream, volume)
+    });
+    volume.attach(volume_tx);
+
+    let quit_mainloop = mainloop.clone();
+    let _quit_receiver = quit.attach(mainloop.loop_(), move |()| quit_mainloop.quit());
+
+    let _ = ready.send(Ok(()));
+    mainloop.run();
+
+    volume.detach();
+    let _ = stream.disconnect();
+
+    Ok(())
+}
@@ -1,8 +1,8 @@
-use super::{Open, Sink, SinkError, SinkResult};
+use super::{AudioDevice, Open, Sink, SinkError, SinkResult};
+use crate::NUM_CHANNELS;
 use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
-use crate::{NUM_CHANNELS, SAMPLE_RATE};
 use portaudio_rs::device::{DeviceIndex, DeviceInfo, get_default_output_index};
 use portaudio_rs::stream::*;
 use std::process::exit;
@@ -12,14 +12,17 @@ pub enum PortAudioSink<'a> {
     F32(
         Option<portaudio_rs::stream::Stream<'a, f32, f32>>,
         StreamParameters<f32>,
+        u32,
     ),
     S32(
         Option<portaudio_rs::stream::Stream<'a, i32, i32>>,
         StreamParameters<i32>,
+        u32,
     ),
     S16(
         Option<portaudio_rs::stream::Stream<'a, i16, i16>>,
         StreamParameters<i16>,
+        u32,
     ),
 }
 
@@ -44,6 +47,22 @@ fn list_outputs() {
     }
 }
 
+pub fn list_devices() -> Vec<AudioDevice> {
+    if let Err(e) = portaudio_rs::initialize() {
+        warn!("Unable to initialize PortAudio: {e}");
+        return Vec::new();
+    }
+
+    let default = get_default_output_index();
+
+    output_devices()
+        .map(|(idx, info)| AudioDevice {
+            name: info.name,
+            description: (Some(idx) == default).then(|| "default".to_string()),
+        })
+        .collect()
+}
+
 fn find_output(device: &str) -> Option<DeviceIndex> {
     output_devices()
         .find(|(_, info)| info.name == device)
@@ -51,8 +70,8 @@ fn find_output(device: &str) -> Option<DeviceIndex> {
 }
 
 impl<'a> Open for PortAudioSink<'a> {
-    fn open(device: Option<String>, format: AudioFormat) -> PortAudioSink<'a> {
-        info!("Using PortAudio sink with format: {format:?}");
+    fn open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> PortAudioSink<'a> {
+        info!("Using PortAudio sink with format: {format:?}, sample rate: {sample_rate}");
 
         portaudio_rs::initialize().unwrap();
 
@@ -80,7 +99,7 @@ impl<'a> Open for PortAudioSink<'a> {
                     suggested_latency: latency,
                     data: 0.0 as $type,
                 };
-                $sink(None, params)
+                $sink(None, params, sample_rate)
             }};
         }
         match format {
@@ -97,13 +116,13 @@ impl<'a> Open for PortAudioSink<'a> {
 impl Sink for PortAudioSink<'_> {
     fn start(&mut self) -> SinkResult<()> {
         macro_rules! start_sink {
-            (ref mut $stream: ident, ref $parameters: ident) => {{
+            (ref mut $stream: ident, ref $parameters: ident, $sample_rate: ident) => {{
                 if $stream.is_none() {
                     *$stream = Some(
                         Stream::open(
                             None,
                             Some(*$parameters),
-                            SAMPLE_RATE as f64,
+                            *$sample_rate as f64,
                             FRAMES_PER_BUFFER_UNSPECIFIED,
                             StreamFlags::DITHER_OFF, // no need to dither twice; use librespot dithering instead
                             None,
@@ -116,9 +135,15 @@ impl Sink for PortAudioSink<'_> {
         }
 
         match self {
-            Self::F32(stream, parameters) => start_sink!(ref mut stream, ref parameters),
-            Self::S32(stream, parameters) => start_sink!(ref mut stream, ref parameters),
-            Self::S16(stream, parameters) => start_sink!(ref mut stream, ref parameters),
+            Self::F32(stream, parameters, sample_rate) => {
+                start_sink!(ref mut stream, ref parameters, sample_rate)
+            }
+            Self::S32(stream, parameters, sample_rate) => {
+                start_sink!(ref mut stream, ref parameters, sample_rate)
+            }
+            Self::S16(stream, parameters, sample_rate) => {
+                start_sink!(ref mut stream, ref parameters, sample_rate)
+            }
         };
 
         Ok(())
@@ -132,9 +157,9 @@ impl Sink for PortAudioSink<'_> {
             }};
         }
         match self {
-            Self::F32(stream, _) => stop_sink!(ref mut stream),
-            Self::S32(stream, _) => stop_sink!(ref mut stream),
-            Self::S16(stream, _) => stop_sink!(ref mut stream),
+            Self::F32(stream, ..) => stop_sink!(ref mut stream),
+            Self::S32(stream, ..) => stop_sink!(ref mut stream),
+            Self::S16(stream, ..) => stop_sink!(ref mut stream),
         };
 
         Ok(())
@@ -152,15 +177,15 @@ impl Sink for PortAudioSink<'_> {
             .map_err(|e| SinkError::OnWrite(e.to_string()))?;
 
         let result = match self {
-            Self::F32(stream, _parameters) => {
+            Self::F32(stream, ..) => {
                 let samples_f32: &[f32] = &converter.f64_to_f32(samples);
                 write_sink!(ref mut stream, samples_f32)
             }
-            Self::S32(stream, _parameters) => {
+            Self::S32(stream, ..) => {
                 let samples_s32: &[i32] = &converter.f64_to_s32(samples);
                 write_sink!(ref mut stream, samples_s32)
             }
-            Self::S16(stream, _parameters) => {
+            Self::S16(stream, ..) => {
                 let samples_s16: &[i16] = &converter.f64_to_s16(samples);
                 write_sink!(ref mut stream, samples_s16)
             }
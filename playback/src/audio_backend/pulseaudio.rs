@@ -1,11 +1,12 @@
 use super::{Open, Sink, SinkAsBytes, SinkError, SinkResult};
+use crate::NUM_CHANNELS;
 use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
-use crate::{NUM_CHANNELS, SAMPLE_RATE};
 use libpulse_binding::{self as pulse, error::PAErr, stream::Direction};
 use libpulse_simple_binding::Simple;
 use std::env;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -52,10 +53,11 @@ pub struct PulseAudioSink {
     app_name: String,
     stream_desc: String,
     format: AudioFormat,
+    sample_rate: u32,
 }
 
 impl Open for PulseAudioSink {
-    fn open(device: Option<String>, format: AudioFormat) -> Self {
+    fn open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> Self {
         let app_name = env::var("PULSE_PROP_application.name").unwrap_or_default();
         let stream_desc = env::var("PULSE_PROP_stream.description").unwrap_or_default();
 
@@ -66,7 +68,7 @@ impl Open for PulseAudioSink {
             actual_format = AudioFormat::F32;
         }
 
-        info!("Using PulseAudioSink with format: {actual_format:?}");
+        info!("Using PulseAudioSink with format: {actual_format:?}, sample rate: {sample_rate}");
 
         Self {
             sink: None,
@@ -74,6 +76,7 @@ impl Open for PulseAudioSink {
             app_name,
             stream_desc,
             format: actual_format,
+            sample_rate,
         }
     }
 }
@@ -94,7 +97,7 @@ impl Sink for PulseAudioSink {
             let sample_spec = pulse::sample::Spec {
                 format: pulse_format,
                 channels: NUM_CHANNELS,
-                rate: SAMPLE_RATE,
+                rate: self.sample_rate,
             };
 
             if !sample_spec.is_valid() {
@@ -102,7 +105,7 @@ impl Sink for PulseAudioSink {
                     pulse_format,
                     format: self.format,
                     channels: NUM_CHANNELS,
-                    rate: SAMPLE_RATE,
+                    rate: self.sample_rate,
                 };
 
                 return Err(SinkError::from(pulse_error));
@@ -133,6 +136,11 @@ impl Sink for PulseAudioSink {
         Ok(())
     }
 
+    fn delay(&self) -> Option<Duration> {
+        let latency = self.sink.as_ref()?.get_latency().ok()?;
+        Some(Duration::from_micros(latency.0))
+    }
+
     sink_as_bytes!();
 }
 
@@ -5,11 +5,11 @@ use std::time::Duration;
 use cpal::traits::{DeviceTrait, HostTrait};
 use thiserror::Error;
 
-use super::{Sink, SinkError, SinkResult};
+use super::{AudioDevice, Sink, SinkError, SinkResult};
+use crate::NUM_CHANNELS;
 use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
-use crate::{NUM_CHANNELS, SAMPLE_RATE};
 
 #[cfg(all(
     feature = "rodiojack-backend",
@@ -18,19 +18,49 @@ use crate::{NUM_CHANNELS, SAMPLE_RATE};
 compile_error!("Rodio JACK backend is currently only supported on linux.");
 
 #[cfg(feature = "rodio-backend")]
-pub fn mk_rodio(device: Option<String>, format: AudioFormat) -> Box<dyn Sink> {
-    Box::new(open(cpal::default_host(), device, format))
+pub fn mk_rodio(device: Option<String>, format: AudioFormat, sample_rate: u32) -> Box<dyn Sink> {
+    Box::new(open(cpal::default_host(), device, format, sample_rate))
 }
 
 #[cfg(feature = "rodiojack-backend")]
-pub fn mk_rodiojack(device: Option<String>, format: AudioFormat) -> Box<dyn Sink> {
+pub fn mk_rodiojack(
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> Box<dyn Sink> {
     Box::new(open(
         cpal::host_from_id(cpal::HostId::Jack).unwrap(),
         device,
         format,
+        sample_rate,
     ))
 }
 
+#[cfg(feature = "rodio-backend")]
+pub fn try_mk_rodio(
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> SinkResult<Box<dyn Sink>> {
+    Ok(Box::new(try_open(
+        cpal::default_host(),
+        device,
+        format,
+        sample_rate,
+    )?))
+}
+
+#[cfg(feature = "rodiojack-backend")]
+pub fn try_mk_rodiojack(
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> SinkResult<Box<dyn Sink>> {
+    let host = cpal::host_from_id(cpal::HostId::Jack)
+        .map_err(|e| SinkError::NotConnected(e.to_string()))?;
+    Ok(Box::new(try_open(host, device, format, sample_rate)?))
+}
+
 #[derive(Debug, Error)]
 pub enum RodioError {
     #[error("<RodioSink> No Device Available")]
@@ -73,6 +103,7 @@ impl From<cpal::SupportedStreamConfigsError> for RodioError {
 
 pub struct RodioSink {
     rodio_sink: rodio::Sink,
+    sample_rate: u32,
     _stream: rodio::OutputStream,
 }
 
@@ -142,10 +173,28 @@ fn list_outputs(host: &cpal::Host) -> Result<(), cpal::DevicesError> {
     Ok(())
 }
 
+pub fn list_devices(host: &cpal::Host) -> SinkResult<Vec<AudioDevice>> {
+    let default_device_name = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+
+    Ok(host
+        .output_devices()
+        .map_err(RodioError::from)?
+        .filter_map(|device| device.name().ok())
+        .map(|name| AudioDevice {
+            description: (Some(&name) == default_device_name.as_ref())
+                .then(|| "default".to_string()),
+            name,
+        })
+        .collect())
+}
+
 fn create_sink(
     host: &cpal::Host,
     device: Option<String>,
     format: AudioFormat,
+    sample_rate: u32,
 ) -> Result<(rodio::Sink, rodio::OutputStream), RodioError> {
     let cpal_device = match device.as_deref() {
         Some("?") => match list_outputs(host) {
@@ -172,15 +221,15 @@ fn create_sink(
         name.as_deref().unwrap_or("[unknown name]")
     );
 
-    // First try native stereo 44.1 kHz playback, then fall back to the device default sample rate
-    // (some devices only support 48 kHz and Rodio will resample linearly), then fall back to
+    // First try native stereo playback at the output sample rate, then fall back to the device
+    // default sample rate (Rodio will resample linearly), then fall back to
     // whatever the default device config is (like mono).
     let default_config = cpal_device.default_output_config()?;
     let config = cpal_device
         .supported_output_configs()?
         .find(|c| c.channels() == NUM_CHANNELS as cpal::ChannelCount)
         .and_then(|c| {
-            c.try_with_sample_rate(cpal::SampleRate(SAMPLE_RATE))
+            c.try_with_sample_rate(cpal::SampleRate(sample_rate))
                 .or_else(|| c.try_with_sample_rate(default_config.sample_rate()))
         })
         .unwrap_or(default_config);
@@ -213,19 +262,34 @@ fn create_sink(
     Ok((sink, stream))
 }
 
-pub fn open(host: cpal::Host, device: Option<String>, format: AudioFormat) -> RodioSink {
+pub fn open(
+    host: cpal::Host,
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> RodioSink {
+    try_open(host, device, format, sample_rate).unwrap()
+}
+
+pub fn try_open(
+    host: cpal::Host,
+    device: Option<String>,
+    format: AudioFormat,
+    sample_rate: u32,
+) -> Result<RodioSink, RodioError> {
     info!(
-        "Using Rodio sink with format {format:?} and cpal host: {}",
+        "Using Rodio sink with format {format:?}, sample rate {sample_rate} and cpal host: {}",
         host.id().name()
     );
 
-    let (sink, stream) = create_sink(&host, device, format).unwrap();
+    let (sink, stream) = create_sink(&host, device, format, sample_rate)?;
 
     debug!("Rodio sink was created");
-    RodioSink {
+    Ok(RodioSink {
         rodio_sink: sink,
+        sample_rate,
         _stream: stream,
-    }
+    })
 }
 
 impl Sink for RodioSink {
@@ -247,7 +311,7 @@ impl Sink for RodioSink {
         let samples_f32: &[f32] = &converter.f64_to_f32(samples);
         let source = rodio::buffer::SamplesBuffer::new(
             NUM_CHANNELS as cpal::ChannelCount,
-            SAMPLE_RATE,
+            self.sample_rate,
             samples_f32,
         );
         self.rodio_sink.append(source);
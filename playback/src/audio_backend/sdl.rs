@@ -1,8 +1,8 @@
 use super::{Open, Sink, SinkError, SinkResult};
+use crate::NUM_CHANNELS;
 use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
-use crate::{NUM_CHANNELS, SAMPLE_RATE};
 use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use std::thread;
 use std::time::Duration;
@@ -14,39 +14,50 @@ pub enum SdlSink {
 }
 
 impl Open for SdlSink {
-    fn open(device: Option<String>, format: AudioFormat) -> Self {
-        info!("Using SDL sink with format: {:?}", format);
+    fn open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> Self {
+        match Self::try_open(device, format, sample_rate) {
+            Ok(sink) => sink,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    fn try_open(device: Option<String>, format: AudioFormat, sample_rate: u32) -> SinkResult<Self> {
+        info!("Using SDL sink with format: {format:?}, sample rate: {sample_rate}");
 
         if device.is_some() {
             warn!("SDL sink does not support specifying a device name");
         }
 
-        let ctx = sdl2::init().expect("could not initialize SDL");
-        let audio = ctx
-            .audio()
-            .expect("could not initialize SDL audio subsystem");
+        let ctx = sdl2::init()
+            .map_err(|e| SinkError::NotConnected(format!("could not initialize SDL: {e}")))?;
+        let audio = ctx.audio().map_err(|e| {
+            SinkError::NotConnected(format!("could not initialize SDL audio subsystem: {e}"))
+        })?;
 
         let desired_spec = AudioSpecDesired {
-            freq: Some(SAMPLE_RATE as i32),
+            freq: Some(sample_rate as i32),
             channels: Some(NUM_CHANNELS),
             samples: None,
         };
 
         macro_rules! open_sink {
             ($sink: expr, $type: ty) => {{
-                let queue: AudioQueue<$type> = audio
-                    .open_queue(None, &desired_spec)
-                    .expect("could not open SDL audio device");
-                $sink(queue)
+                let queue: AudioQueue<$type> =
+                    audio.open_queue(None, &desired_spec).map_err(|e| {
+                        SinkError::ConnectionRefused(format!(
+                            "could not open SDL audio device: {e}"
+                        ))
+                    })?;
+                Ok($sink(queue))
             }};
         }
         match format {
             AudioFormat::F32 => open_sink!(Self::F32, f32),
             AudioFormat::S32 => open_sink!(Self::S32, i32),
             AudioFormat::S16 => open_sink!(Self::S16, i16),
-            _ => {
-                unimplemented!("SDL currently does not support {format:?} output")
-            }
+            _ => Err(SinkError::InvalidParams(format!(
+                "SDL currently does not support {format:?} output"
+            ))),
         }
     }
 }
@@ -86,7 +97,9 @@ impl Sink for SdlSink {
         macro_rules! drain_sink {
             ($queue: expr, $size: expr) => {{
                 // sleep and wait for sdl thread to drain the queue a bit
-                while $queue.size() > (NUM_CHANNELS as u32 * $size as u32 * SAMPLE_RATE) {
+                while $queue.size()
+                    > (NUM_CHANNELS as u32 * $size as u32 * $queue.spec().freq as u32)
+                {
                     thread::sleep(Duration::from_millis(10));
                 }
             }};
@@ -0,0 +1,135 @@
+use super::{BufferedSink, Sink, SinkResult, open_sink};
+use crate::config::AudioFormat;
+use crate::convert::Converter;
+use crate::decoder::AudioPacket;
+use crate::dither::DithererBuilder;
+use std::time::Duration;
+
+// Audio for a secondary output is buffered for this long, and dropped when the output
+// doesn't keep up.
+const SECONDARY_BUFFER: Duration = Duration::from_millis(500);
+
+/// A secondary output of a [`TeeSink`].
+#[derive(Clone, Debug)]
+pub struct TeeOutput {
+    /// The name of the backend, as in [`BACKENDS`](super::BACKENDS).
+    pub backend: String,
+    pub device: Option<String>,
+    pub format: AudioFormat,
+    /// The ditherer for the format of this output.
+    pub ditherer: Option<DithererBuilder>,
+}
+
+struct Secondary {
+    sink: BufferedSink,
+    failed: bool,
+}
+
+/// Plays to a primary sink and any number of secondary outputs at once.
+///
+/// Errors of the primary sink are returned as usual. A secondary output that fails is
+/// reported and skipped until the next `start`, so it never interrupts the primary.
+///
+/// The primary sink is written to first. Each secondary output plays from its own
+/// non-blocking [`BufferedSink`], which converts the samples with its own ditherer, so an
+/// output that blocks, for instance a pipe that nobody reads, only drops its own audio.
+pub struct TeeSink {
+    primary: Box<dyn Sink>,
+    secondaries: Vec<Secondary>,
+}
+
+impl TeeSink {
+    pub const NAME: &'static str = "tee";
+
+    /// Plays to `primary` and the `outputs`, which are opened with
+    /// [`open_sink`](super::open_sink) at the output sample rate.
+    pub fn new(primary: Box<dyn Sink>, outputs: &[TeeOutput], sample_rate: u32) -> Self {
+        info!("Using TeeSink with {} secondary output(s)", outputs.len());
+
+        let secondaries = outputs
+            .iter()
+            .map(|output| {
+                let TeeOutput {
+                    backend,
+                    device,
+                    format,
+                    ditherer,
+                } = output.clone();
+
+                let sink = BufferedSink::non_blocking(
+                    move || open_sink(&backend, device, format, sample_rate),
+                    ditherer,
+                    sample_rate,
+                    SECONDARY_BUFFER,
+                );
+
+                Secondary {
+                    sink,
+                    failed: false,
+                }
+            })
+            .collect();
+
+        Self {
+            primary,
+            secondaries,
+        }
+    }
+
+    fn for_each_secondary(&mut self, mut f: impl FnMut(&mut BufferedSink) -> SinkResult<()>) {
+        for (index, output) in self.secondaries.iter_mut().enumerate() {
+            if output.failed {
+                continue;
+            }
+
+            if let Err(e) = f(&mut output.sink) {
+                warn!(
+                    "<TeeSink> Secondary output {index} failed, skipping it until restarted: {e}"
+                );
+                output.failed = true;
+            }
+        }
+    }
+}
+
+impl Sink for TeeSink {
+    fn start(&mut self) -> SinkResult<()> {
+        self.primary.start()?;
+
+        for output in self.secondaries.iter_mut() {
+            output.failed = false;
+        }
+        self.for_each_secondary(|sink| sink.start());
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        let result = self.primary.stop();
+        self.for_each_secondary(|sink| sink.stop());
+        result
+    }
+
+    fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()> {
+        let copy = (!self.secondaries.is_empty()).then(|| packet.clone());
+        self.primary.write(packet, converter)?;
+
+        // The buffered sinks convert with their own converters, this one goes unused.
+        if let Some(packet) = copy {
+            self.for_each_secondary(|sink| sink.write(packet.clone(), converter));
+        }
+
+        Ok(())
+    }
+
+    fn discard(&mut self) {
+        self.primary.discard();
+        for output in self.secondaries.iter_mut() {
+            output.sink.discard();
+        }
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        self.primary.delay()
+    }
+}
@@ -1,13 +1,18 @@
-use std::{mem, str::FromStr, time::Duration};
+use std::{mem, path::PathBuf, str::FromStr, time::Duration};
 
 pub use crate::dither::{DithererBuilder, TriangularDitherer, mk_ditherer};
-use crate::{convert::i24, player::duration_to_coefficient};
+pub use crate::filter::{AudioFilterBuilder, EqualizerBand, EqualizerBandType};
+use crate::{
+    SAMPLE_RATE, audio_backend::TeeOutput, convert::i24, player::duration_to_coefficient,
+    resampler::Resampler,
+};
 
 #[derive(Clone, Copy, Debug, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub enum Bitrate {
     Bitrate96,
     Bitrate160,
     Bitrate320,
+    Lossless,
 }
 
 impl FromStr for Bitrate {
@@ -17,6 +22,7 @@ impl FromStr for Bitrate {
             "96" => Ok(Self::Bitrate96),
             "160" => Ok(Self::Bitrate160),
             "320" => Ok(Self::Bitrate320),
+            "lossless" => Ok(Self::Lossless),
             _ => Err(()),
         }
     }
@@ -121,12 +127,82 @@ impl Default for NormalisationMethod {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrossfadeCurve {
+    Linear,
+    EqualPower,
+}
+
+impl FromStr for CrossfadeCurve {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "linear" => Ok(Self::Linear),
+            "equal-power" => Ok(Self::EqualPower),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for CrossfadeCurve {
+    fn default() -> Self {
+        Self::EqualPower
+    }
+}
+
+/// When to play the 30-second MP3 previews of tracks instead of their full files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewMode {
+    Disabled,
+    /// Play the preview when the full file fails to load.
+    Fallback,
+    Always,
+}
+
+impl FromStr for PreviewMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "disabled" => Ok(Self::Disabled),
+            "fallback" => Ok(Self::Fallback),
+            "always" => Ok(Self::Always),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for PreviewMode {
+    fn default() -> Self {
+        Self::Disabled
+    }
+}
+
 #[derive(Clone)]
 pub struct PlayerConfig {
     pub bitrate: Bitrate,
+    pub preview_mode: PreviewMode,
     pub gapless: bool,
     pub passthrough: bool,
 
+    /// The sample rate the sink is opened at. Audio is resampled from `SAMPLE_RATE` when this
+    /// differs. Positions and durations are always reported in track time.
+    ///
+    /// Rates that share too few factors with `SAMPLE_RATE`, such as 44101 Hz, can't be
+    /// resampled to. Check with [`supports_sample_rate`](Self::supports_sample_rate) before
+    /// opening the sink, as `Player::new` logs an error and plays at `SAMPLE_RATE` instead.
+    /// Common rates are supported.
+    pub sample_rate: u32,
+
+    /// Setting this will start the preloaded next track this long before the end of the current
+    /// one and mix both. Requires gapless playback and is ignored when passthrough is enabled.
+    pub crossfade: Option<Duration>,
+    pub crossfade_curve: CrossfadeCurve,
+
+    /// Setting this will fade out before pausing, seeking and stopping, and fade in afterwards,
+    /// to avoid clicks. Fades are limited to 500 ms, as the fade out is played before the
+    /// command returns. Ignored when passthrough is enabled. Disabled by default.
+    pub fade_duration: Option<Duration>,
+
     pub normalisation: bool,
     pub normalisation_type: NormalisationType,
     pub normalisation_method: NormalisationMethod,
@@ -136,19 +212,55 @@ pub struct PlayerConfig {
     pub normalisation_release_cf: f64,
     pub normalisation_knee_db: f64,
 
+    /// Setting this will skip silence below `silence_trim_threshold_dbfs` once it lasted longer
+    /// than `silence_trim_min_duration`, both within and at the boundaries of tracks.
+    pub silence_trim: bool,
+    pub silence_trim_threshold_dbfs: f64,
+    pub silence_trim_min_duration: Duration,
+
     // pass function pointers so they can be lazily instantiated *after* spawning a thread
     // (thereby circumventing Send bounds that they might not satisfy)
     pub ditherer: Option<DithererBuilder>,
+    /// Filters that are applied in order to the decoded audio, before normalisation and volume
+    /// control. See `librespot_playback::filter` for the included filters.
+    pub filters: Vec<AudioFilterBuilder>,
+    /// Bands of the built-in parametric equalizer, applied after `filters`. Leave empty to disable.
+    pub equalizer: Vec<EqualizerBand>,
     /// Setting this will enable periodically sending events during playback informing about the playback position
     /// To consume the PlayerEvent::PositionChanged event, listen to events via `Player::get_player_event_channel()``
     pub position_update_interval: Option<Duration>,
+    /// Setting this will periodically send `PlayerEvent::Levels` with the peak and RMS levels and
+    /// a coarse spectrum of the audio that is played, measured over this interval.
+    pub level_meter_interval: Option<Duration>,
+    /// Directory that is searched for the files of `spotify:local:` tracks. Leave unset to skip
+    /// local tracks.
+    pub local_files_dir: Option<PathBuf>,
+    /// Setting this plays to the sink from a separate output thread, through a buffer of this
+    /// duration. Underruns and overruns of the buffer are sent as `PlayerEvent::OutputBufferXrun`.
+    /// Not available with passthrough.
+    pub output_buffer: Option<Duration>,
+    /// Secondary outputs that play along with the sink through a `TeeSink`, also after switching
+    /// the sink with `Player::set_sink`. Leave empty to only play to the sink.
+    pub tee: Vec<TeeOutput>,
+}
+
+impl PlayerConfig {
+    /// Whether audio can be played at `sample_rate`, see [`sample_rate`](Self::sample_rate).
+    pub fn supports_sample_rate(sample_rate: u32) -> bool {
+        sample_rate == SAMPLE_RATE || Resampler::new(sample_rate).is_some()
+    }
 }
 
 impl Default for PlayerConfig {
     fn default() -> Self {
         Self {
             bitrate: Bitrate::default(),
+            preview_mode: PreviewMode::default(),
             gapless: true,
+            sample_rate: SAMPLE_RATE,
+            crossfade: None,
+            crossfade_curve: CrossfadeCurve::default(),
+            fade_duration: None,
             normalisation: false,
             normalisation_type: NormalisationType::default(),
             normalisation_method: NormalisationMethod::default(),
@@ -157,20 +269,30 @@ impl Default for PlayerConfig {
             normalisation_attack_cf: duration_to_coefficient(Duration::from_millis(5)),
             normalisation_release_cf: duration_to_coefficient(Duration::from_millis(100)),
             normalisation_knee_db: 5.0,
+            silence_trim: false,
+            silence_trim_threshold_dbfs: -60.0,
+            silence_trim_min_duration: Duration::from_millis(500),
             passthrough: false,
             ditherer: Some(mk_ditherer::<TriangularDitherer>),
+            filters: Vec::new(),
+            equalizer: Vec::new(),
             position_update_interval: None,
+            level_meter_interval: None,
+            local_files_dir: None,
+            output_buffer: None,
+            tee: Vec::new(),
         }
     }
 }
 
-// fields are intended for volume control range in dB
-#[derive(Clone, Copy, Debug)]
+// fields are intended for volume control range in dB, or the custom volume curve
+#[derive(Clone, Debug)]
 pub enum VolumeCtrl {
     Cubic(f64),
     Fixed,
     Linear,
     Log(f64),
+    Custom(VolumeCurve),
 }
 
 impl FromStr for VolumeCtrl {
@@ -203,3 +325,135 @@ impl VolumeCtrl {
         }
     }
 }
+
+/// A volume curve through (volume %, dB) points, between which the dB is interpolated
+/// linearly. The dB are relative to the loudest point, which plays at full volume.
+///
+/// Volumes below the first point are held at its dB, and those above the last point at
+/// full volume. Volume 0 is always mute.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VolumeCurve {
+    // Normalized volume and relative dB, sorted by volume.
+    points: Vec<(f64, f64)>,
+}
+
+impl FromStr for VolumeCurve {
+    type Err = ();
+
+    /// Parses `VOLUME:DB` points, separated by commas, whitespace or newlines. Anything
+    /// after a `#` on a line is a comment.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let points = s
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|point| !point.is_empty())
+            .map(|point| {
+                let (volume, db) = point.split_once(':').ok_or(())?;
+                let volume = volume.parse::<f64>().map_err(|_| ())?;
+                let db = db.parse::<f64>().map_err(|_| ())?;
+                Ok((volume, db))
+            })
+            .collect::<Result<Vec<_>, ()>>()?;
+
+        Self::new(points).ok_or(())
+    }
+}
+
+impl VolumeCurve {
+    /// Makes a curve through at least two (volume %, dB) points. The volumes must
+    /// increase from point to point within 0 - 100, and the dB must not decrease.
+    pub fn new(points: Vec<(f64, f64)>) -> Option<Self> {
+        let (first, last) = (points.first()?, points.last()?);
+
+        let is_valid = points.len() >= 2
+            && points
+                .iter()
+                .all(|(volume, db)| (0.0..=100.0).contains(volume) && db.is_finite())
+            && points
+                .windows(2)
+                .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 <= pair[1].1)
+            && first.1 < last.1;
+
+        if !is_valid {
+            return None;
+        }
+
+        let max_db = last.1;
+        let points = points
+            .iter()
+            .map(|(volume, db)| (volume / 100.0, db - max_db))
+            .collect();
+
+        Some(Self { points })
+    }
+
+    pub(crate) fn points(&self) -> &[(f64, f64)] {
+        &self.points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supports_sample_rates() {
+        for rate in [SAMPLE_RATE, 48000, 96000, 192000] {
+            assert!(PlayerConfig::supports_sample_rate(rate), "{rate}");
+        }
+        for rate in [0, 44101] {
+            assert!(!PlayerConfig::supports_sample_rate(rate), "{rate}");
+        }
+    }
+
+    #[test]
+    fn volume_curve_from_str() {
+        let expected = VolumeCurve {
+            points: vec![(0.0, -60.0), (0.2, -30.0), (0.6, -8.0), (1.0, 0.0)],
+        };
+
+        for points in [
+            "0:-60,20:-30,60:-8,100:0",
+            "0:-60, 20:-30  60:-8,,100:0",
+            "# volume:dB\n0:-60\n20:-30 # quiet\n\n60:-8\n100:0\n",
+        ] {
+            assert_eq!(
+                VolumeCurve::from_str(points),
+                Ok(expected.clone()),
+                "{points:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn volume_curve_is_relative_to_loudest_point() {
+        let curve = VolumeCurve::from_str("10:-50,50:5,100:10").unwrap();
+        assert_eq!(curve.points(), [(0.1, -60.0), (0.5, -5.0), (1.0, 0.0)]);
+    }
+
+    #[test]
+    fn volume_curve_from_str_invalid() {
+        for points in [
+            "",
+            "# no points",
+            "100:0",
+            "0:-60,100",
+            "0:-60,100:",
+            "0-60,100:0",
+            "a:-60,100:0",
+            "0:-60,100:zero",
+            "0:-60;100:0",
+            "0:-inf,100:0",
+            "0:NaN,100:0",
+            "-10:-60,100:0",
+            "0:-60,101:0",
+            "0:-60,50:-30,50:-20,100:0",
+            "50:-30,0:-60,100:0",
+            "0:-60,50:-20,60:-30,100:0",
+            "0:-20,100:-20",
+        ] {
+            assert_eq!(VolumeCurve::from_str(points), Err(()), "{points:?}");
+        }
+    }
+}
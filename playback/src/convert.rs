@@ -64,10 +64,15 @@ impl Converter {
         match self.ditherer.as_mut() {
             Some(d) => {
                 // With dithering: Apply noise at 16-bit level to address original quantization,
-                // then scale up to target format while preserving sub-LSB information
-                let dithered_16bit = sample * (1_u64 << Self::SHIFT_S16) as f64 + d.noise();
-                let scaled = dithered_16bit * (1_u64 << shift) as f64;
-                scaled.round()
+                // scaled up to the target format so that sub-LSB information is preserved.
+                // Noise shaping ditherers get the error of each sample fed back, in LSB of the
+                // target format.
+                let lsb_16bit = (1_u64 << shift) as f64;
+                let shaped =
+                    sample * (1_u64 << (Self::SHIFT_S16 + shift)) as f64 + d.error_feedback();
+                let scaled = (shaped + d.noise() * lsb_16bit).round();
+                d.record_error(scaled - shaped);
+                scaled
             }
             None => {
                 // No dithering: Scale directly from normalized float to target format
@@ -131,3 +136,99 @@ impl Converter {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dither::mk_ditherer;
+
+    // Shapes the error with a first order filter and adds no noise, so the output stays
+    // within 1 LSB of the input when the error is fed back at the right scale.
+    struct FirstOrderShaper {
+        error: f64,
+    }
+
+    impl Ditherer for FirstOrderShaper {
+        fn new() -> Self {
+            Self { error: 0.0 }
+        }
+
+        fn name(&self) -> &'static str {
+            "first_order"
+        }
+
+        fn noise(&mut self) -> f64 {
+            0.0
+        }
+
+        fn error_feedback(&mut self) -> f64 {
+            -self.error
+        }
+
+        fn record_error(&mut self, error: f64) {
+            self.error = error;
+        }
+    }
+
+    fn samples() -> Vec<f64> {
+        (0..10_000)
+            .map(|i| 0.9 * (i as f64 * 0.01).sin() + 1e-7 * (i % 7) as f64)
+            .collect()
+    }
+
+    // The largest and the mean difference between the output and the input, in LSB.
+    fn errors(samples: &[f64], output: &[i32], shift: u8) -> (f64, f64) {
+        let scale = (1_u64 << shift) as f64;
+        let errors: Vec<f64> = samples
+            .iter()
+            .zip(output)
+            .map(|(sample, output)| *output as f64 - sample * scale)
+            .collect();
+
+        let max = errors
+            .iter()
+            .fold(0.0, |max: f64, error| max.max(error.abs()));
+        let mean = errors.iter().sum::<f64>() / errors.len() as f64;
+        (max, mean)
+    }
+
+    #[test]
+    fn error_feedback_in_output_lsb() {
+        let samples = samples();
+
+        let mut converter = Converter::new(Some(mk_ditherer::<FirstOrderShaper>));
+        let output = converter.f64_to_s24(&samples);
+        let (max, mean) = errors(&samples, &output, Converter::SHIFT_S24);
+        assert!(max <= 1.0 + 1e-6, "S24 error of {max} LSB");
+        assert!(mean.abs() < 0.01, "S24 mean error of {mean} LSB");
+
+        let mut converter = Converter::new(Some(mk_ditherer::<FirstOrderShaper>));
+        let output: Vec<i32> = converter
+            .f64_to_s16(&samples)
+            .into_iter()
+            .map(i32::from)
+            .collect();
+        let (max, mean) = errors(&samples, &output, Converter::SHIFT_S16);
+        assert!(max <= 1.0 + 1e-6, "S16 error of {max} LSB");
+        assert!(mean.abs() < 0.01, "S16 mean error of {mean} LSB");
+    }
+
+    #[test]
+    fn rounds_without_ditherer() {
+        let samples = samples();
+
+        let mut converter = Converter::new(None);
+        let output = converter.f64_to_s24(&samples);
+        let (max, _) = errors(&samples, &output, Converter::SHIFT_S24);
+        assert!(max <= 0.5 + 1e-6, "S24 error of {max} LSB");
+    }
+
+    #[test]
+    fn clamps_s24() {
+        let mut converter = Converter::new(None);
+        assert_eq!(
+            converter.f64_to_s24(&[1.5, -1.5]),
+            [(1 << 23) - 1, -(1 << 23)]
+        );
+    }
+}
@@ -30,6 +30,7 @@ pub enum AudioPacketError {
 
 pub type AudioPacketResult<T> = Result<T, AudioPacketError>;
 
+#[derive(Clone)]
 pub enum AudioPacket {
     Samples(Vec<f64>),
     Raw(Vec<u8>),
@@ -135,6 +135,12 @@ impl SymphoniaDecoder {
         }
     }
 
+    /// The duration of the track, if the container tells.
+    pub fn duration_ms(&self) -> Option<u32> {
+        let n_frames = self.decoder.codec_params().n_frames?;
+        Some(self.ts_to_ms(n_frames))
+    }
+
     #[inline]
     fn ts_to_ms(&self, ts: u64) -> u32 {
         match self.decoder.codec_params().time_base {
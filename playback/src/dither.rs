@@ -23,6 +23,13 @@ use crate::NUM_CHANNELS;
 //    so unless you have a multibit / R2R DAC, or otherwise know what you are
 //    doing, this is not for you.
 //
+//  * On S16 with a DAC without noise shaping, noise-shaped dithering moves the
+//    dithering and requantization noise away from the frequencies where the ear
+//    is most sensitive, by feeding back previous quantization errors. The
+//    Lipshitz filter does so moderately, the F-weighted filter more
+//    aggressively at the cost of a higher total noise level. Both filters are
+//    designed for 44.1 kHz and shape the noise less well at other sample rates.
+//
 //  * Don't dither or shape noise on S32 or F32. On F32 it's not supported
 //    anyway (there are no integer conversions and so no rounding errors) and
 //    on S32 the noise level is so far down that it is simply inaudible even
@@ -34,6 +41,18 @@ pub trait Ditherer {
         Self: Sized;
     fn name(&self) -> &'static str;
     fn noise(&mut self) -> f64;
+
+    /// The error feedback to add to the next sample before dithering, in LSB of the output
+    /// format. Only noise shaping ditherers return something other than zero.
+    #[inline]
+    fn error_feedback(&mut self) -> f64 {
+        0.0
+    }
+
+    /// Records the quantization error of the last sample, in LSB of the output format: the
+    /// quantized sample minus the sample with error feedback but before dithering.
+    #[inline]
+    fn record_error(&mut self, _error: f64) {}
 }
 
 impl fmt::Display for dyn Ditherer {
@@ -146,6 +165,127 @@ impl HighPassDitherer {
     pub const NAME: &'static str = "tpdf_hp";
 }
 
+// Error feedback filter coefficients, most recent error first, from Wannamaker,
+// "Psychoacoustically Optimal Noise Shaping" (JAES, 1992).
+const LIPSHITZ_COEFFICIENTS: [f64; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+const F_WEIGHTED_COEFFICIENTS: [f64; 9] = [
+    2.412, -3.370, 3.937, -4.174, 3.353, -2.205, 1.281, -0.569, 0.0847,
+];
+
+// Keeps the most recent quantization errors of each channel for the error
+// feedback filter of a noise shaping ditherer.
+struct NoiseShaper {
+    active_channel: usize,
+    coefficients: &'static [f64],
+    previous_errors: [[f64; F_WEIGHTED_COEFFICIENTS.len()]; NUM_CHANNELS as usize],
+}
+
+impl NoiseShaper {
+    fn new(coefficients: &'static [f64]) -> Self {
+        Self {
+            active_channel: 0,
+            coefficients,
+            previous_errors: [[0.0; F_WEIGHTED_COEFFICIENTS.len()]; NUM_CHANNELS as usize],
+        }
+    }
+
+    #[inline]
+    fn feedback(&self) -> f64 {
+        -self
+            .coefficients
+            .iter()
+            .zip(&self.previous_errors[self.active_channel])
+            .map(|(coefficient, error)| coefficient * error)
+            .sum::<f64>()
+    }
+
+    #[inline]
+    fn record(&mut self, error: f64) {
+        let previous_errors = &mut self.previous_errors[self.active_channel];
+        previous_errors.rotate_right(1);
+        previous_errors[0] = error;
+        self.active_channel ^= 1;
+    }
+}
+
+pub struct LipshitzDitherer {
+    shaper: NoiseShaper,
+    cached_rng: SmallRng,
+    distribution: Triangular<f64>,
+}
+
+impl Ditherer for LipshitzDitherer {
+    fn new() -> Self {
+        Self {
+            shaper: NoiseShaper::new(&LIPSHITZ_COEFFICIENTS),
+            cached_rng: create_rng(),
+            distribution: Triangular::new(-1.0, 1.0, 0.0).unwrap(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    #[inline]
+    fn noise(&mut self) -> f64 {
+        self.distribution.sample(&mut self.cached_rng)
+    }
+
+    #[inline]
+    fn error_feedback(&mut self) -> f64 {
+        self.shaper.feedback()
+    }
+
+    #[inline]
+    fn record_error(&mut self, error: f64) {
+        self.shaper.record(error);
+    }
+}
+
+impl LipshitzDitherer {
+    pub const NAME: &'static str = "tpdf_lipshitz";
+}
+
+pub struct FWeightedDitherer {
+    shaper: NoiseShaper,
+    cached_rng: SmallRng,
+    distribution: Triangular<f64>,
+}
+
+impl Ditherer for FWeightedDitherer {
+    fn new() -> Self {
+        Self {
+            shaper: NoiseShaper::new(&F_WEIGHTED_COEFFICIENTS),
+            cached_rng: create_rng(),
+            distribution: Triangular::new(-1.0, 1.0, 0.0).unwrap(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    #[inline]
+    fn noise(&mut self) -> f64 {
+        self.distribution.sample(&mut self.cached_rng)
+    }
+
+    #[inline]
+    fn error_feedback(&mut self) -> f64 {
+        self.shaper.feedback()
+    }
+
+    #[inline]
+    fn record_error(&mut self, error: f64) {
+        self.shaper.record(error);
+    }
+}
+
+impl FWeightedDitherer {
+    pub const NAME: &'static str = "tpdf_fweighted";
+}
+
 pub fn mk_ditherer<D: Ditherer + 'static>() -> Box<dyn Ditherer> {
     Box::new(D::new())
 }
@@ -157,6 +297,138 @@ pub fn find_ditherer(name: Option<String>) -> Option<DithererBuilder> {
         Some(TriangularDitherer::NAME) => Some(mk_ditherer::<TriangularDitherer>),
         Some(GaussianDitherer::NAME) => Some(mk_ditherer::<GaussianDitherer>),
         Some(HighPassDitherer::NAME) => Some(mk_ditherer::<HighPassDitherer>),
+        Some(LipshitzDitherer::NAME) => Some(mk_ditherer::<LipshitzDitherer>),
+        Some(FWeightedDitherer::NAME) => Some(mk_ditherer::<FWeightedDitherer>),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SAMPLE_RATE;
+    use crate::convert::Converter;
+    use std::f64::consts::TAU;
+
+    const FRAMES: usize = 100_000;
+
+    // The S16 output minus the input in LSB, of the left channel.
+    fn errors(ditherer: DithererBuilder) -> Vec<f64> {
+        let samples: Vec<f64> = (0..FRAMES)
+            .flat_map(|frame| {
+                let sample = 100.0 / 32768.0 * (frame as f64 * 0.05).sin();
+                [sample, -sample]
+            })
+            .collect();
+
+        let output = Converter::new(Some(ditherer)).f64_to_s16(&samples);
+
+        samples
+            .iter()
+            .zip(output)
+            .step_by(NUM_CHANNELS as usize)
+            .map(|(sample, output)| output as f64 - sample * 32768.0)
+            .collect()
+    }
+
+    // Power of the error between 2 and 5 kHz, where the ear is most sensitive, from the
+    // Hann windowed spectra of blocks of errors.
+    fn sensitive_band_power(errors: &[f64]) -> f64 {
+        const BLOCK: usize = 1024;
+        let bins = 2000 * BLOCK / SAMPLE_RATE as usize..5000 * BLOCK / SAMPLE_RATE as usize;
+
+        errors
+            .chunks_exact(BLOCK)
+            .map(|block| {
+                bins.clone()
+                    .map(|k| {
+                        let (re, im) =
+                            block
+                                .iter()
+                                .enumerate()
+                                .fold((0.0, 0.0), |(re, im), (i, error)| {
+                                    let window = 0.5 - 0.5 * (TAU * i as f64 / BLOCK as f64).cos();
+                                    let angle = TAU * (k * i) as f64 / BLOCK as f64;
+                                    (
+                                        re + window * error * angle.cos(),
+                                        im - window * error * angle.sin(),
+                                    )
+                                });
+                        re * re + im * im
+                    })
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn noise_shapers_are_bounded() {
+        for (ditherer, coefficients) in [
+            (
+                mk_ditherer::<LipshitzDitherer> as DithererBuilder,
+                &LIPSHITZ_COEFFICIENTS[..],
+            ),
+            (
+                mk_ditherer::<FWeightedDitherer>,
+                &F_WEIGHTED_COEFFICIENTS[..],
+            ),
+        ] {
+            // Each recorded error is at most 1 LSB of noise plus 0.5 LSB of rounding, and
+            // is fed back through the coefficients.
+            let feedback_gain: f64 = coefficients.iter().map(|c| c.abs()).sum();
+            let bound = 1.5 * (1.0 + feedback_gain);
+
+            let errors = errors(ditherer);
+            let max = errors
+                .iter()
+                .fold(0.0, |max: f64, error| max.max(error.abs()));
+            assert!(max <= bound, "{}: error of {max} LSB", ditherer().name());
+
+            // The noise doesn't build up over time.
+            let (first, last) = errors.split_at(errors.len() / 2);
+            let power = |errors: &[f64]| {
+                errors.iter().map(|error| error * error).sum::<f64>() / errors.len() as f64
+            };
+            assert!(
+                (power(first) - power(last)).abs() < 0.1 * power(first),
+                "{}",
+                ditherer().name()
+            );
+        }
+    }
+
+    #[test]
+    fn noise_shapers_lower_audible_noise() {
+        let unshaped = sensitive_band_power(&errors(mk_ditherer::<TriangularDitherer>));
+
+        for ditherer in [
+            mk_ditherer::<LipshitzDitherer> as DithererBuilder,
+            mk_ditherer::<FWeightedDitherer>,
+        ] {
+            // Both filters lower the noise by more than 15 dB in this band.
+            let shaped = sensitive_band_power(&errors(ditherer));
+            assert!(
+                shaped < unshaped / 10.0,
+                "{}: {shaped} is not well below {unshaped}",
+                ditherer().name()
+            );
+        }
+    }
+
+    #[test]
+    fn find_ditherers() {
+        for name in [
+            TriangularDitherer::NAME,
+            GaussianDitherer::NAME,
+            HighPassDitherer::NAME,
+            LipshitzDitherer::NAME,
+            FWeightedDitherer::NAME,
+        ] {
+            let ditherer = find_ditherer(Some(name.to_string())).expect("ditherer should exist");
+            assert_eq!(ditherer().name(), name);
+        }
+
+        assert!(find_ditherer(Some("none".to_string())).is_none());
+        assert!(find_ditherer(None).is_none());
+    }
+}
@@ -0,0 +1,34 @@
+use super::AudioFilter;
+use crate::NUM_CHANNELS;
+
+pub struct BalanceFilter {
+    factors: [f64; NUM_CHANNELS as usize],
+}
+
+impl BalanceFilter {
+    pub const NAME: &'static str = "balance";
+
+    // -1.0 is fully left, 0.0 is centered and 1.0 is fully right. The louder
+    // channel is kept at unity gain so that the balance never causes clipping.
+    pub fn new(balance: f64) -> Self {
+        let balance = balance.clamp(-1.0, 1.0);
+
+        Self {
+            factors: [f64::min(1.0, 1.0 - balance), f64::min(1.0, 1.0 + balance)],
+        }
+    }
+}
+
+impl AudioFilter for BalanceFilter {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn process(&mut self, samples: &mut [f64]) {
+        for frame in samples.chunks_exact_mut(NUM_CHANNELS as usize) {
+            for (sample, factor) in frame.iter_mut().zip(self.factors) {
+                *sample *= factor;
+            }
+        }
+    }
+}
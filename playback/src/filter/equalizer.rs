@@ -0,0 +1,353 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use super::AudioFilter;
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EqualizerBandType {
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+}
+
+impl FromStr for EqualizerBandType {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "peak" | "peaking" => Ok(Self::Peaking),
+            "lowshelf" => Ok(Self::LowShelf),
+            "highshelf" => Ok(Self::HighShelf),
+            "lowpass" => Ok(Self::LowPass),
+            "highpass" => Ok(Self::HighPass),
+            _ => Err(()),
+        }
+    }
+}
+
+impl EqualizerBandType {
+    fn has_gain(&self) -> bool {
+        !matches!(self, Self::LowPass | Self::HighPass)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqualizerBand {
+    pub band_type: EqualizerBandType,
+    pub frequency: f64,
+    pub gain_db: f64,
+    pub q: f64,
+}
+
+impl EqualizerBand {
+    pub const MAX_GAIN_DB: f64 = 24.0;
+
+    fn is_valid(&self) -> bool {
+        self.frequency > 0.0
+            && self.frequency < SAMPLE_RATE as f64 / 2.0
+            && self.gain_db.abs() <= Self::MAX_GAIN_DB
+            && self.q > 0.0
+    }
+}
+
+// Parses `FREQ:GAIN:Q` as a peaking band, or `TYPE:FREQ:GAIN:Q` for the other
+// types. Low and high pass bands have no gain and are written as `TYPE:FREQ:Q`.
+impl FromStr for EqualizerBand {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields: Vec<&str> = s.trim().split(':').collect();
+
+        let band_type = match fields.first().map(|field| field.parse::<f64>()) {
+            Some(Ok(_)) => EqualizerBandType::Peaking,
+            Some(Err(_)) => EqualizerBandType::from_str(fields.remove(0))?,
+            None => return Err(()),
+        };
+
+        let values = fields
+            .iter()
+            .map(|field| field.trim().parse::<f64>().map_err(|_| ()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (frequency, gain_db, q) = match (band_type.has_gain(), values.as_slice()) {
+            (true, &[frequency, gain_db, q]) => (frequency, gain_db, q),
+            (false, &[frequency, q]) => (frequency, 0.0, q),
+            _ => return Err(()),
+        };
+
+        let band = Self {
+            band_type,
+            frequency,
+            gain_db,
+            q,
+        };
+
+        if band.is_valid() { Ok(band) } else { Err(()) }
+    }
+}
+
+// Second order IIR filter using the coefficients from the
+// "Audio EQ Cookbook" by Robert Bristow-Johnson.
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    // Transposed direct form II state, per channel.
+    state: [[f64; 2]; NUM_CHANNELS as usize],
+}
+
+impl Biquad {
+    fn new(band: &EqualizerBand) -> Self {
+        let a = 10.0_f64.powf(band.gain_db / 40.0);
+        let w0 = 2.0 * PI * band.frequency / SAMPLE_RATE as f64;
+        let (sin_w0, cos_w0) = w0.sin_cos();
+        let alpha = sin_w0 / (2.0 * band.q);
+
+        let (b0, b1, b2, a0, a1, a2) = match band.band_type {
+            EqualizerBandType::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            EqualizerBandType::LowShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 + beta),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 - beta),
+                    (a + 1.0) + (a - 1.0) * cos_w0 + beta,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    (a + 1.0) + (a - 1.0) * cos_w0 - beta,
+                )
+            }
+            EqualizerBandType::HighShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 + beta),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 - beta),
+                    (a + 1.0) - (a - 1.0) * cos_w0 + beta,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    (a + 1.0) - (a - 1.0) * cos_w0 - beta,
+                )
+            }
+            EqualizerBandType::LowPass => (
+                (1.0 - cos_w0) / 2.0,
+                1.0 - cos_w0,
+                (1.0 - cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            EqualizerBandType::HighPass => (
+                (1.0 + cos_w0) / 2.0,
+                -(1.0 + cos_w0),
+                (1.0 + cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            state: [[0.0; 2]; NUM_CHANNELS as usize],
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, channel: usize, input: f64) -> f64 {
+        let state = &mut self.state[channel];
+        let output = self.b0 * input + state[0];
+        state[0] = self.b1 * input - self.a1 * output + state[1];
+        state[1] = self.b2 * input - self.a2 * output;
+        output
+    }
+
+    fn reset(&mut self) {
+        self.state = [[0.0; 2]; NUM_CHANNELS as usize];
+    }
+}
+
+pub struct Equalizer {
+    filters: Vec<Biquad>,
+}
+
+impl Equalizer {
+    pub const NAME: &'static str = "equalizer";
+
+    pub fn new(bands: &[EqualizerBand]) -> Self {
+        for band in bands {
+            debug!(
+                "Equalizer band: {:?} at {} Hz, {} dB, Q {}",
+                band.band_type, band.frequency, band.gain_db, band.q
+            );
+        }
+
+        Self {
+            filters: bands.iter().map(Biquad::new).collect(),
+        }
+    }
+}
+
+impl AudioFilter for Equalizer {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn process(&mut self, samples: &mut [f64]) {
+        for frame in samples.chunks_exact_mut(NUM_CHANNELS as usize) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                for filter in &mut self.filters {
+                    *sample = filter.process(channel, *sample);
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in &mut self.filters {
+            filter.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One second of an interleaved sine at full scale.
+    fn sine(frequency: f64) -> Vec<f64> {
+        (0..SAMPLE_RATE as usize)
+            .flat_map(|frame| {
+                let sample = (2.0 * PI * frequency * frame as f64 / SAMPLE_RATE as f64).sin();
+                [sample; NUM_CHANNELS as usize]
+            })
+            .collect()
+    }
+
+    // The gain once the filter has settled, over the second half of the samples.
+    fn gain(bands: &[EqualizerBand], frequency: f64) -> f64 {
+        let mut samples = sine(frequency);
+        Equalizer::new(bands).process(&mut samples);
+
+        samples[samples.len() / 2..]
+            .iter()
+            .fold(0.0, |peak: f64, sample| peak.max(sample.abs()))
+    }
+
+    fn band(band_type: EqualizerBandType, frequency: f64, gain_db: f64) -> EqualizerBand {
+        EqualizerBand {
+            band_type,
+            frequency,
+            gain_db,
+            q: 1.0,
+        }
+    }
+
+    #[test]
+    fn unity_at_zero_db() {
+        let bands = [
+            band(EqualizerBandType::Peaking, 1000.0, 0.0),
+            band(EqualizerBandType::LowShelf, 100.0, 0.0),
+            band(EqualizerBandType::HighShelf, 8000.0, 0.0),
+        ];
+
+        let input = sine(440.0);
+        let mut samples = input.clone();
+        Equalizer::new(&bands).process(&mut samples);
+
+        for (output, input) in samples.iter().zip(&input) {
+            assert!((output - input).abs() < 1e-9, "{output} is not {input}");
+        }
+    }
+
+    #[test]
+    fn gain_at_centre_frequency() {
+        for gain_db in [-12.0, -6.0, 6.0, 12.0] {
+            let bands = [band(EqualizerBandType::Peaking, 1000.0, gain_db)];
+            let expected = 10.0_f64.powf(gain_db / 20.0);
+
+            let actual = gain(&bands, 1000.0);
+            assert!(
+                (actual - expected).abs() < 0.01 * expected,
+                "{gain_db} dB: {actual} is not {expected}"
+            );
+
+            // Far away from the centre the band has next to no effect.
+            assert!((gain(&bands, 50.0) - 1.0).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn shelves_and_passes() {
+        let low_shelf = [band(EqualizerBandType::LowShelf, 200.0, 6.0)];
+        assert!((gain(&low_shelf, 20.0) - 10.0_f64.powf(6.0 / 20.0)).abs() < 0.02);
+        assert!((gain(&low_shelf, 10000.0) - 1.0).abs() < 0.02);
+
+        let low_pass = [band(EqualizerBandType::LowPass, 1000.0, 0.0)];
+        assert!((gain(&low_pass, 50.0) - 1.0).abs() < 0.01);
+        assert!(gain(&low_pass, 10000.0) < 0.02);
+
+        let high_pass = [band(EqualizerBandType::HighPass, 1000.0, 0.0)];
+        assert!(gain(&high_pass, 50.0) < 0.01);
+        assert!((gain(&high_pass, 10000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn reset_clears_state() {
+        let bands = [band(EqualizerBandType::Peaking, 1000.0, 12.0)];
+        let mut equalizer = Equalizer::new(&bands);
+
+        let mut samples = sine(1000.0);
+        equalizer.process(&mut samples);
+        equalizer.reset();
+
+        let mut silence = vec![0.0; 64];
+        equalizer.process(&mut silence);
+        assert!(silence.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn band_from_str() {
+        assert_eq!(
+            EqualizerBand::from_str("1000:-3:1.4"),
+            Ok(EqualizerBand {
+                band_type: EqualizerBandType::Peaking,
+                frequency: 1000.0,
+                gain_db: -3.0,
+                q: 1.4,
+            })
+        );
+        assert_eq!(
+            EqualizerBand::from_str("lowpass:8000:0.7"),
+            Ok(EqualizerBand {
+                band_type: EqualizerBandType::LowPass,
+                frequency: 8000.0,
+                gain_db: 0.0,
+                q: 0.7,
+            })
+        );
+
+        for band in [
+            "",
+            "1000:-3",
+            "lowpass:8000:0:0.7",
+            "notch:1000:-3:1",
+            "30000:-3:1",
+            "1000:-30:1",
+            "1000:-3:0",
+        ] {
+            assert_eq!(EqualizerBand::from_str(band), Err(()), "{band:?}");
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use super::AudioFilter;
+use crate::player::db_to_ratio;
+
+pub struct GainFilter {
+    factor: f64,
+}
+
+impl GainFilter {
+    pub const NAME: &'static str = "gain";
+
+    pub fn new(gain_db: f64) -> Self {
+        Self {
+            factor: db_to_ratio(gain_db),
+        }
+    }
+}
+
+impl AudioFilter for GainFilter {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn process(&mut self, samples: &mut [f64]) {
+        for sample in samples.iter_mut() {
+            *sample *= self.factor;
+        }
+    }
+}
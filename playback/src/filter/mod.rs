@@ -0,0 +1,75 @@
+use std::{fmt, sync::Arc};
+
+mod balance;
+mod equalizer;
+mod gain;
+
+pub use balance::BalanceFilter;
+pub use equalizer::{Equalizer, EqualizerBand, EqualizerBandType};
+pub use gain::GainFilter;
+
+// Filters process the decoded audio before normalisation, volume control and
+// conversion to the output format. Samples are interleaved f64 in the range
+// -1.0..=1.0 with `NUM_CHANNELS` channels at `SAMPLE_RATE`.
+pub trait AudioFilter {
+    fn name(&self) -> &'static str;
+    fn process(&mut self, samples: &mut [f64]);
+    // Called whenever the audio becomes discontinuous, like after a seek.
+    fn reset(&mut self) {}
+}
+
+impl fmt::Display for dyn AudioFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Like the ditherer, filters are lazily instantiated *after* spawning the player thread,
+// so they do not need to be Send themselves.
+pub type AudioFilterBuilder = Arc<dyn Fn() -> Box<dyn AudioFilter> + Send + Sync>;
+
+pub fn mk_filter<F, B>(build: B) -> AudioFilterBuilder
+where
+    F: AudioFilter + 'static,
+    B: Fn() -> F + Send + Sync + 'static,
+{
+    Arc::new(move || Box::new(build()))
+}
+
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn AudioFilter>>,
+}
+
+impl FilterChain {
+    pub fn new(builders: &[AudioFilterBuilder]) -> Self {
+        let filters: Vec<_> = builders.iter().map(|builder| builder()).collect();
+
+        for filter in &filters {
+            info!("Processing with filter: {filter}");
+        }
+
+        Self { filters }
+    }
+
+    pub fn push(&mut self, filter: Box<dyn AudioFilter>) {
+        info!("Processing with filter: {filter}");
+        self.filters.push(filter);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn process(&mut self, samples: &mut [f64]) {
+        for filter in &mut self.filters {
+            filter.process(samples);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for filter in &mut self.filters {
+            filter.reset();
+        }
+    }
+}
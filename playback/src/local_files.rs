@@ -0,0 +1,426 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use symphonia::core::{
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+};
+
+use crate::metadata::audio::AudioFileFormat;
+
+// Don't rescan the directory for every track that can't be found.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+const INDEX_POISON_MSG: &str = "local files index mutex should not be poisoned";
+const SCAN_POISON_MSG: &str = "local files scan mutex should not be poisoned";
+
+#[derive(Debug, Default)]
+struct LocalFileTags {
+    artist: Option<String>,
+    album: Option<String>,
+    title: Option<String>,
+}
+
+#[derive(Debug)]
+struct LocalFile {
+    path: PathBuf,
+    format: AudioFileFormat,
+    // Normalised for matching.
+    stem: String,
+    tags: LocalFileTags,
+}
+
+#[derive(Default)]
+struct LocalFilesIndex {
+    files: Vec<LocalFile>,
+    scanned_at: Option<Instant>,
+}
+
+/// An index of the audio files in a directory, to play `spotify:local:` URIs.
+///
+/// Files are matched by their artist, album and title tags, or by their filename
+/// when they are not tagged.
+///
+/// Only the tracks themselves are supported, for instance in a playlist. Playing the
+/// "Local Files" context of a phone is not, as its tracks are only known to that phone.
+pub struct LocalFiles {
+    dir: PathBuf,
+    index: Mutex<LocalFilesIndex>,
+    // Held while scanning, so that the directory is only scanned by one loader at a time,
+    // while the others can still use the index.
+    scanning: Mutex<()>,
+}
+
+impl LocalFiles {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            index: Mutex::new(LocalFilesIndex::default()),
+            scanning: Mutex::new(()),
+        }
+    }
+
+    /// Finds the file for a local track, scanning the directory if it wasn't yet or the track
+    /// is not in the index.
+    pub fn find(
+        &self,
+        artist: &str,
+        album_title: &str,
+        track_title: &str,
+    ) -> Option<(PathBuf, AudioFileFormat)> {
+        let scanned_at = {
+            let index = self.lock_index();
+            let found = index.find(artist, album_title, track_title);
+            if found.is_some() {
+                return found;
+            }
+            index.scanned_at
+        };
+
+        if scanned_at.is_some_and(|scanned_at| scanned_at.elapsed() < RESCAN_INTERVAL) {
+            return None;
+        }
+
+        {
+            let _scanning = self.scanning.lock().expect(SCAN_POISON_MSG);
+
+            // Another loader may have scanned while this one was waiting.
+            if self.lock_index().scanned_at == scanned_at {
+                let files = scan(&self.dir);
+                *self.lock_index() = LocalFilesIndex {
+                    files,
+                    scanned_at: Some(Instant::now()),
+                };
+            }
+        }
+
+        self.lock_index().find(artist, album_title, track_title)
+    }
+
+    fn lock_index(&self) -> MutexGuard<'_, LocalFilesIndex> {
+        self.index.lock().expect(INDEX_POISON_MSG)
+    }
+}
+
+impl LocalFilesIndex {
+    fn find(
+        &self,
+        artist: &str,
+        album_title: &str,
+        track_title: &str,
+    ) -> Option<(PathBuf, AudioFileFormat)> {
+        let artist = normalise(artist);
+        let album_title = normalise(album_title);
+        let track_title = normalise(track_title);
+
+        if track_title.is_empty() {
+            return None;
+        }
+
+        let matches = |tag: &Option<String>, value: &str| {
+            !value.is_empty() && tag.as_deref().map(normalise).as_deref() == Some(value)
+        };
+
+        self.files
+            .iter()
+            .filter_map(|file| {
+                let score = if file.tags.title.is_some() {
+                    if !matches(&file.tags.title, &track_title) {
+                        return None;
+                    }
+                    4 + 2 * matches(&file.tags.artist, &artist) as u8
+                        + matches(&file.tags.album, &album_title) as u8
+                } else {
+                    // Filenames like "01 - Artist - Title" are common for untagged files.
+                    if !file.stem.ends_with(&track_title) {
+                        return None;
+                    }
+                    1 + (!artist.is_empty() && file.stem.contains(&artist)) as u8
+                };
+
+                Some((score, file))
+            })
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, file)| (file.path.clone(), file.format))
+    }
+}
+
+fn scan(dir: &Path) -> Vec<LocalFile> {
+    let started = Instant::now();
+
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    // Directories are only read once, so that symlinks can't make the scan loop forever.
+    let mut visited = HashSet::new();
+
+    while let Some(dir) = dirs.pop() {
+        if let Ok(canonical_dir) = fs::canonicalize(&dir) {
+            if !visited.insert(canonical_dir) {
+                continue;
+            }
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(
+                    "Unable to read local files directory {}: {e}",
+                    dir.display()
+                );
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Some(format) = local_file_format(&path) {
+                let stem = path
+                    .file_stem()
+                    .map(|stem| normalise(&stem.to_string_lossy()))
+                    .unwrap_or_default();
+                let tags = read_tags(&path).unwrap_or_default();
+
+                files.push(LocalFile {
+                    path,
+                    format,
+                    stem,
+                    tags,
+                });
+            }
+        }
+    }
+
+    debug!(
+        "Indexed {} local files in {} ms",
+        files.len(),
+        started.elapsed().as_millis()
+    );
+
+    files
+}
+
+// The decoder picks its container and codec by format, so map the extensions that it
+// supports to a format of the same kind.
+fn local_file_format(path: &Path) -> Option<AudioFileFormat> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    match extension.as_str() {
+        "mp3" => Some(AudioFileFormat::MP3_320),
+        "ogg" | "oga" => Some(AudioFileFormat::OGG_VORBIS_320),
+        "flac" => Some(AudioFileFormat::FLAC_FLAC),
+        _ => None,
+    }
+}
+
+fn read_tags(path: &Path) -> Option<LocalFileTags> {
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+
+    let mut tags = LocalFileTags::default();
+
+    // ID3 tags are read while probing, Vorbis comments by the format reader.
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            read_revision(revision, &mut tags);
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        read_revision(revision, &mut tags);
+    }
+
+    Some(tags)
+}
+
+fn read_revision(revision: &MetadataRevision, tags: &mut LocalFileTags) {
+    for tag in revision.tags() {
+        let field = match tag.std_key {
+            Some(StandardTagKey::Artist) => &mut tags.artist,
+            Some(StandardTagKey::Album) => &mut tags.album,
+            Some(StandardTagKey::TrackTitle) => &mut tags.title,
+            _ => continue,
+        };
+
+        if field.is_none() {
+            *field = Some(tag.value.to_string());
+        }
+    }
+}
+
+// Ignore case, punctuation and whitespace, which often differ between tags and filenames.
+fn normalise(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SpotifyUri;
+
+    fn find_uri(
+        find: impl Fn(&str, &str, &str) -> Option<(PathBuf, AudioFileFormat)>,
+        uri: &str,
+    ) -> Option<PathBuf> {
+        let Ok(SpotifyUri::Local {
+            artist,
+            album_title,
+            track_title,
+            ..
+        }) = SpotifyUri::from_uri(uri)
+        else {
+            panic!("{uri} should be a local URI");
+        };
+
+        find(&artist, &album_title, &track_title).map(|(path, _)| path)
+    }
+
+    fn file(path: &str, tags: [Option<&str>; 3]) -> LocalFile {
+        let path = PathBuf::from(path);
+        let [artist, album, title] = tags.map(|tag| tag.map(str::to_string));
+
+        LocalFile {
+            stem: normalise(&path.file_stem().unwrap().to_string_lossy()),
+            format: local_file_format(&path).unwrap(),
+            path,
+            tags: LocalFileTags {
+                artist,
+                album,
+                title,
+            },
+        }
+    }
+
+    fn index_of(files: Vec<LocalFile>) -> LocalFilesIndex {
+        LocalFilesIndex {
+            files,
+            scanned_at: Some(Instant::now()),
+        }
+    }
+
+    #[test]
+    fn normalises_case_punctuation_and_whitespace() {
+        assert_eq!(normalise("AC/DC - Back In Black!"), "acdcbackinblack");
+        assert_eq!(normalise("  Sigur Rós "), "sigurrós");
+        assert_eq!(normalise("Ärzte"), normalise("ärzte"));
+        assert_eq!(normalise("..."), "");
+    }
+
+    #[test]
+    fn matches_tags() {
+        let index = index_of(vec![
+            file("cover.mp3", [Some("Other Band"), None, Some("Song")]),
+            file("live.mp3", [Some("AC/DC"), Some("Live"), Some("Song")]),
+            file("studio.flac", [Some("AC/DC"), Some("Studio"), Some("Song")]),
+            file(
+                "AC DC - Song.ogg",
+                [Some("AC/DC"), Some("Studio"), Some("Other")],
+            ),
+        ]);
+        let find = |artist: &str, album: &str, title: &str| index.find(artist, album, title);
+
+        assert_eq!(
+            find_uri(find, "spotify:local:AC%2FDC:Studio:Song:200"),
+            Some(PathBuf::from("studio.flac"))
+        );
+        assert_eq!(
+            find_uri(find, "spotify:local:ac+dc:live:SONG:200"),
+            Some(PathBuf::from("live.mp3"))
+        );
+        assert_eq!(
+            find_uri(find, "spotify:local:Other+Band::Song:200"),
+            Some(PathBuf::from("cover.mp3"))
+        );
+
+        // The title has to match, the filename isn't used for tagged files.
+        assert_eq!(
+            find_uri(find, "spotify:local:AC%2FDC:Studio:Missing:200"),
+            None
+        );
+        assert_eq!(find_uri(find, "spotify:local:AC%2FDC:Studio::200"), None);
+    }
+
+    #[test]
+    fn falls_back_to_filenames() {
+        let index = index_of(vec![
+            file("01 - Artist - Title.mp3", [None, None, None]),
+            file("Title.ogg", [None, None, None]),
+            file("Title - Remix.flac", [None, None, None]),
+        ]);
+        let find = |artist: &str, album: &str, title: &str| index.find(artist, album, title);
+
+        assert_eq!(
+            find_uri(find, "spotify:local:Artist:Album:Title:200"),
+            Some(PathBuf::from("01 - Artist - Title.mp3"))
+        );
+        assert!(find_uri(find, "spotify:local:Someone:Album:Title:200").is_some());
+        assert_eq!(
+            find_uri(find, "spotify:local:::title+%28remix%29:200"),
+            Some(PathBuf::from("Title - Remix.flac"))
+        );
+        assert_eq!(
+            find_uri(find, "spotify:local:Artist:Album:Remix+Title:200"),
+            None
+        );
+
+        // A tagged file is preferred over a filename.
+        let mut files = index.files;
+        files.push(file("track.mp3", [None, None, Some("Title")]));
+        let index = index_of(files);
+        let find = |artist: &str, album: &str, title: &str| index.find(artist, album, title);
+        assert_eq!(
+            find_uri(find, "spotify:local:Artist:Album:Title:200"),
+            Some(PathBuf::from("track.mp3"))
+        );
+    }
+
+    #[test]
+    fn finds_untagged_files_in_the_directory() {
+        let dir =
+            std::env::temp_dir().join(format!("librespot-local-files-{}", std::process::id()));
+        fs::create_dir_all(dir.join("Album")).unwrap();
+        // Empty files have no tags, so they are matched by their filename.
+        for name in ["Album/02 - Artist - Title.FLAC", "Title.txt", "Other.mp3"] {
+            File::create(dir.join(name)).unwrap();
+        }
+
+        let local_files = LocalFiles::new(&dir);
+        let found = local_files.find("Artist", "Album", "Title");
+        let missing = local_files.find("Artist", "Album", "Missing");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            found,
+            Some((
+                dir.join("Album/02 - Artist - Title.FLAC"),
+                AudioFileFormat::FLAC_FLAC
+            ))
+        );
+        assert_eq!(missing, None);
+    }
+}
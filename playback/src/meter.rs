@@ -0,0 +1,263 @@
+use std::{f64::consts::PI, time::Duration};
+
+use crate::{NUM_CHANNELS, player::ratio_to_db};
+
+/// Edges (Hz) of the bands that the spectrum is summarised in, from low to high.
+pub const LEVEL_METER_BAND_EDGES_HZ: [f64; 9] = [
+    20.0, 60.0, 150.0, 400.0, 1000.0, 2400.0, 6000.0, 12000.0, 20000.0,
+];
+
+/// Levels below this are reported as this, so silence doesn't show up as -inf.
+pub const LEVEL_METER_FLOOR_DB: f64 = -96.0;
+
+// Number of frames the spectrum is calculated over. A power of two for the FFT, which is about
+// 23 ms at 44.1 kHz and resolves bins of about 43 Hz.
+const FFT_SIZE: usize = 1024;
+
+/// The levels of the audio over one interval of a [`LevelMeter`].
+#[derive(Debug, Clone)]
+pub struct Levels {
+    /// Peak level (dBFS) of the left and right channel.
+    pub peak_db: [f64; 2],
+    /// RMS level (dBFS) of the left and right channel.
+    pub rms_db: [f64; 2],
+    /// Level (dB relative to a full scale sine) of the bands between `LEVEL_METER_BAND_EDGES_HZ`,
+    /// over the most recent frames.
+    pub bands_db: Vec<f64>,
+}
+
+/// Measures peak and RMS levels of interleaved stereo audio, and summarises its spectrum
+/// in a few bands, once every interval.
+pub struct LevelMeter {
+    sample_rate: u32,
+    interval_frames: usize,
+    frames: usize,
+    peaks: [f64; 2],
+    squares: [f64; 2],
+    // The most recent frames mixed to mono, as a ring buffer starting at `history_index`.
+    history: Vec<f64>,
+    history_index: usize,
+    window: Vec<f64>,
+}
+
+impl LevelMeter {
+    pub fn new(interval: Duration, sample_rate: u32) -> Self {
+        let interval_frames = (interval.as_secs_f64() * sample_rate as f64).max(1.0) as usize;
+
+        // Hann window, to keep leakage between the bands down.
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FFT_SIZE as f64).cos())
+            .collect();
+
+        Self {
+            sample_rate,
+            interval_frames,
+            frames: 0,
+            peaks: [0.0; 2],
+            squares: [0.0; 2],
+            history: vec![0.0; FFT_SIZE],
+            history_index: 0,
+            window,
+        }
+    }
+
+    /// Returns the levels once a full interval was measured.
+    pub fn process(&mut self, samples: &[f64]) -> Option<Levels> {
+        for frame in samples.chunks_exact(NUM_CHANNELS as usize) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.peaks[channel] = self.peaks[channel].max(sample.abs());
+                self.squares[channel] += sample * sample;
+            }
+
+            self.history[self.history_index] = (frame[0] + frame[1]) / 2.0;
+            self.history_index = (self.history_index + 1) % FFT_SIZE;
+        }
+
+        self.frames += samples.len() / NUM_CHANNELS as usize;
+        if self.frames < self.interval_frames {
+            return None;
+        }
+
+        let levels = Levels {
+            peak_db: self.peaks.map(to_db),
+            rms_db: self
+                .squares
+                .map(|squares| to_db((squares / self.frames as f64).sqrt())),
+            bands_db: self.bands(),
+        };
+
+        self.frames = 0;
+        self.peaks = [0.0; 2];
+        self.squares = [0.0; 2];
+
+        Some(levels)
+    }
+
+    fn bands(&self) -> Vec<f64> {
+        let mut re: Vec<f64> = (0..FFT_SIZE)
+            .map(|i| self.history[(self.history_index + i) % FFT_SIZE] * self.window[i])
+            .collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+
+        // A full scale sine peaks at a magnitude of N/4 with the Hann window.
+        let full_scale = (FFT_SIZE as f64 / 4.0).powi(2);
+        let hz_per_bin = self.sample_rate as f64 / FFT_SIZE as f64;
+        let nyquist_bin = FFT_SIZE / 2;
+
+        LEVEL_METER_BAND_EDGES_HZ
+            .windows(2)
+            .map(|edges| {
+                let start = ((edges[0] / hz_per_bin).ceil() as usize).clamp(1, nyquist_bin);
+                let end =
+                    ((edges[1] / hz_per_bin).ceil() as usize).clamp(start + 1, nyquist_bin + 1);
+
+                let energy: f64 = (start..end).map(|k| re[k] * re[k] + im[k] * im[k]).sum();
+                to_db((energy / full_scale).sqrt())
+            })
+            .collect()
+    }
+}
+
+fn to_db(ratio: f64) -> f64 {
+    if ratio > 0.0 {
+        ratio_to_db(ratio).max(LEVEL_METER_FLOOR_DB)
+    } else {
+        LEVEL_METER_FLOOR_DB
+    }
+}
+
+// In-place iterative radix-2 FFT. The length must be a power of two.
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j ^= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SAMPLE_RATE;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn sine(frequency: f64, amplitude: [f64; 2], frames: usize) -> Vec<f64> {
+        (0..frames)
+            .flat_map(|frame| {
+                let sample = (2.0 * PI * frequency * frame as f64 / SAMPLE_RATE as f64).sin();
+                amplitude.map(|amplitude| amplitude * sample)
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[f64]) -> Levels {
+        LevelMeter::new(INTERVAL, SAMPLE_RATE)
+            .process(samples)
+            .expect("a full interval should be measured")
+    }
+
+    #[test]
+    fn fft_matches_dft() {
+        let input: Vec<f64> = (0..64).map(|i| ((i * 7919) % 23) as f64 - 11.0).collect();
+
+        let mut re = input.clone();
+        let mut im = vec![0.0; input.len()];
+        fft(&mut re, &mut im);
+
+        for k in 0..input.len() {
+            let (dft_re, dft_im) =
+                input
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(dft_re, dft_im), (i, sample)| {
+                        let angle = -2.0 * PI * (k * i) as f64 / input.len() as f64;
+                        (dft_re + sample * angle.cos(), dft_im + sample * angle.sin())
+                    });
+            assert!((re[k] - dft_re).abs() < 1e-9 && (im[k] - dft_im).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn sine_lands_in_its_band() {
+        for frequency in [250.0, 700.0, 1700.0, 4000.0, 9000.0, 15000.0] {
+            let levels = measure(&sine(frequency, [1.0, 1.0], SAMPLE_RATE as usize / 10));
+
+            let band = LEVEL_METER_BAND_EDGES_HZ
+                .windows(2)
+                .position(|edges| (edges[0]..edges[1]).contains(&frequency))
+                .unwrap();
+            let loudest = (0..levels.bands_db.len())
+                .max_by(|a, b| levels.bands_db[*a].total_cmp(&levels.bands_db[*b]))
+                .unwrap();
+
+            assert_eq!(loudest, band, "{frequency} Hz: {:?}", levels.bands_db);
+            assert!(
+                levels.bands_db[band].abs() < 3.0,
+                "{frequency} Hz: {:?}",
+                levels.bands_db
+            );
+        }
+    }
+
+    #[test]
+    fn peak_and_rms() {
+        let levels = measure(&sine(1000.0, [0.5, 0.0], SAMPLE_RATE as usize / 10));
+
+        assert!((levels.peak_db[0] - ratio_to_db(0.5)).abs() < 0.01);
+        assert!((levels.rms_db[0] - ratio_to_db(0.5 / 2.0_f64.sqrt())).abs() < 0.01);
+        assert_eq!(levels.peak_db[1], LEVEL_METER_FLOOR_DB);
+        assert_eq!(levels.rms_db[1], LEVEL_METER_FLOOR_DB);
+    }
+
+    #[test]
+    fn reports_once_per_interval() {
+        let mut meter = LevelMeter::new(INTERVAL, SAMPLE_RATE);
+        let packet = sine(1000.0, [1.0, 1.0], 1000);
+
+        // 100 ms are 4410 frames, which are complete after the fifth packet.
+        let reports: Vec<bool> = (0..10).map(|_| meter.process(&packet).is_some()).collect();
+        assert_eq!(
+            reports,
+            [
+                false, false, false, false, true, false, false, false, false, true
+            ]
+        );
+
+        let silence = vec![0.0; 5000 * NUM_CHANNELS as usize];
+        let levels = meter.process(&silence).unwrap();
+        assert_eq!(levels.peak_db, [LEVEL_METER_FLOOR_DB; 2]);
+    }
+}
@@ -5,12 +5,16 @@ use super::{Mixer, MixerConfig, VolumeCtrl};
 
 use alsa::Error as AlsaError;
 use alsa::ctl::{ElemId, ElemIface};
-use alsa::mixer::{MilliBel, SelemChannelId, SelemId};
+use alsa::mixer::{MilliBel, Selem, SelemChannelId, SelemId};
+use alsa::poll::{self, Descriptors};
 use alsa::{Ctl, Round};
 
 use librespot_core::Error;
 use std::ffi::{CString, NulError};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use thiserror::Error;
+use tokio::sync::mpsc;
 
 #[derive(Clone)]
 #[allow(dead_code)]
@@ -25,6 +29,8 @@ pub struct AlsaMixer {
     has_switch: bool,
     is_softvol: bool,
     use_linear_in_db: bool,
+    // The volume of the control as last set or reported, to tell changes by others apart.
+    known_volume: Arc<Mutex<Option<u16>>>,
 }
 
 // min_db cannot be depended on to be mute. Also note that contrary to
@@ -32,6 +38,11 @@ pub struct AlsaMixer {
 const SND_CTL_TLV_DB_GAIN_MUTE: MilliBel = MilliBel(-9999999);
 const ZERO_DB: MilliBel = MilliBel(0);
 
+// How often the thread that follows volume changes checks if it is still needed.
+const VOLUME_WATCH_TIMEOUT_MS: i32 = 500;
+
+const KNOWN_VOLUME_POISON_MSG: &str = "Alsa mixer known volume mutex should not be poisoned";
+
 #[derive(Debug, Error)]
 enum AlsaMixerError {
     #[error("Could not open Alsa mixer. {0}")]
@@ -136,8 +147,11 @@ impl Mixer for AlsaMixer {
         let reported_db_range = f64::abs(max_db - min_db);
 
         // Synchronize the volume control dB range with the mixer control,
-        // unless it was already set with a command line option.
-        let db_range = if config.volume_ctrl.range_ok() {
+        // unless it was already set with a command line option. A custom
+        // curve has its own range, that needs the one of the control.
+        let db_range = if let VolumeCtrl::Custom(_) = config.volume_ctrl {
+            reported_db_range
+        } else if config.volume_ctrl.range_ok() {
             let db_range_override = config.volume_ctrl.db_range();
             if db_range_override.is_normal() {
                 db_range_override
@@ -163,9 +177,11 @@ impl Mixer for AlsaMixer {
         }
 
         // For hardware controls with a small range (24 dB or less),
-        // force using the dB API with a linear mapping.
+        // force using the dB API with a linear mapping, unless the
+        // mapping is a custom curve.
         let mut use_linear_in_db = false;
-        if !is_softvol && db_range <= 24.0 {
+        let is_custom = matches!(config.volume_ctrl, VolumeCtrl::Custom(_));
+        if !is_softvol && !is_custom && db_range <= 24.0 {
             use_linear_in_db = true;
             config.volume_ctrl = VolumeCtrl::Linear;
         }
@@ -190,6 +206,7 @@ impl Mixer for AlsaMixer {
             has_switch,
             is_softvol,
             use_linear_in_db,
+            known_volume: Arc::new(Mutex::new(None)),
         })
     }
 
@@ -200,40 +217,38 @@ impl Mixer for AlsaMixer {
             .find_selem(&SelemId::new(&self.config.control, self.config.index))
             .expect("Could not find Alsa mixer control");
 
-        if self.switched_off() {
-            return 0;
-        }
+        self.read_volume(&simple_element)
+            .expect("Could not get Alsa volume")
+    }
 
-        let mut mapped_volume = if self.is_softvol {
-            let raw_volume = simple_element
-                .get_playback_volume(SelemChannelId::mono())
-                .expect("Could not get raw Alsa volume");
-            raw_volume as f64 / self.range as f64 - self.min as f64
-        } else {
-            let db_volume = simple_element
-                .get_playback_vol_db(SelemChannelId::mono())
-                .expect("Could not get Alsa dB volume")
-                .to_db() as f64;
+    fn set_volume(&self, volume: u16) {
+        // Held while the control is changed, so that the watch thread can't take our own
+        // change for one made by someone else.
+        let mut known_volume = self.known_volume.lock().expect(KNOWN_VOLUME_POISON_MSG);
+        self.apply_volume(volume);
 
-            if self.use_linear_in_db {
-                (db_volume - self.min_db) / self.db_range
-            } else if f64::abs(db_volume - SND_CTL_TLV_DB_GAIN_MUTE.to_db() as f64) <= f64::EPSILON
-            {
-                0.0
-            } else {
-                db_to_ratio(db_volume - self.max_db)
-            }
-        };
+        // The control may not take the exact volume.
+        *known_volume = Some(self.volume());
+    }
 
-        // see comment in `set_volume` why we are handling an antilog volume
-        if mapped_volume > 0.0 && self.is_some_linear() {
-            mapped_volume = LogMapping::linear_to_mapped(mapped_volume, self.db_range);
-        }
+    fn volume_changes(&self) -> Option<mpsc::UnboundedReceiver<u16>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mixer = self.clone();
 
-        self.config.volume_ctrl.as_unmapped(mapped_volume)
+        thread::spawn(move || {
+            if let Err(e) = mixer.watch_volume(&sender) {
+                error!("Could not follow Alsa volume changes: {e}");
+            }
+        });
+
+        Some(receiver)
     }
+}
 
-    fn set_volume(&self, volume: u16) {
+impl AlsaMixer {
+    pub const NAME: &'static str = "alsa";
+
+    fn apply_volume(&self, volume: u16) {
         let mixer =
             alsa::mixer::Mixer::new(&self.config.device, false).expect("Could not open Alsa mixer");
         let simple_element = mixer
@@ -246,7 +261,7 @@ impl Mixer for AlsaMixer {
                 simple_element
                     .set_playback_switch_all(0)
                     .expect("Could not disable playback (set mute) on Alsa");
-            } else if self.switched_off() {
+            } else if self.switched_off(&simple_element) {
                 debug!("Enabling playback (unsetting mute) on Alsa");
                 simple_element
                     .set_playback_switch_all(1)
@@ -289,22 +304,79 @@ impl Mixer for AlsaMixer {
             .set_playback_db_all(MilliBel::from_db(db_volume as f32), Round::Floor)
             .expect("Could not set Alsa dB volume");
     }
-}
 
-impl AlsaMixer {
-    pub const NAME: &'static str = "alsa";
+    // Sends the volume whenever the control is changed by someone else, for instance
+    // with `alsamixer` or a hardware knob, until the receiver is dropped.
+    fn watch_volume(&self, changes: &mpsc::UnboundedSender<u16>) -> Result<(), AlsaError> {
+        let mixer = alsa::mixer::Mixer::new(&self.config.device, false)?;
+        let simple_element = mixer
+            .find_selem(&SelemId::new(&self.config.control, self.config.index))
+            .ok_or_else(|| AlsaError::unsupported("snd_mixer_find_selem"))?;
+        let mut fds = mixer.get()?;
+
+        {
+            let mut known_volume = self.known_volume.lock().expect(KNOWN_VOLUME_POISON_MSG);
+            if known_volume.is_none() {
+                *known_volume = Some(self.read_volume(&simple_element)?);
+            }
+        }
+
+        while !changes.is_closed() {
+            if poll::poll(&mut fds, VOLUME_WATCH_TIMEOUT_MS)? == 0 {
+                continue;
+            }
+            mixer.handle_events()?;
+
+            let mut known_volume = self.known_volume.lock().expect(KNOWN_VOLUME_POISON_MSG);
+            let volume = self.read_volume(&simple_element)?;
+            if *known_volume != Some(volume) {
+                debug!("Alsa volume was changed to {volume}");
+                *known_volume = Some(volume);
+                if changes.send(volume).is_err() {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_volume(&self, simple_element: &Selem) -> Result<u16, AlsaError> {
+        if self.switched_off(simple_element) {
+            return Ok(0);
+        }
 
-    fn switched_off(&self) -> bool {
+        let mut mapped_volume = if self.is_softvol {
+            let raw_volume = simple_element.get_playback_volume(SelemChannelId::mono())?;
+            raw_volume as f64 / self.range as f64 - self.min as f64
+        } else {
+            let db_volume = simple_element
+                .get_playback_vol_db(SelemChannelId::mono())?
+                .to_db() as f64;
+
+            if self.use_linear_in_db {
+                (db_volume - self.min_db) / self.db_range
+            } else if f64::abs(db_volume - SND_CTL_TLV_DB_GAIN_MUTE.to_db() as f64) <= f64::EPSILON
+            {
+                0.0
+            } else {
+                db_to_ratio(db_volume - self.max_db)
+            }
+        };
+
+        // see comment in `set_volume` why we are handling an antilog volume
+        if mapped_volume > 0.0 && self.is_some_linear() {
+            mapped_volume = LogMapping::linear_to_mapped(mapped_volume, self.db_range);
+        }
+
+        Ok(self.config.volume_ctrl.as_unmapped(mapped_volume))
+    }
+
+    fn switched_off(&self, simple_element: &Selem) -> bool {
         if !self.has_switch {
             return false;
         }
 
-        let mixer =
-            alsa::mixer::Mixer::new(&self.config.device, false).expect("Could not open Alsa mixer");
-        let simple_element = mixer
-            .find_selem(&SelemId::new(&self.config.control, self.config.index))
-            .expect("Could not find Alsa mixer control");
-
         simple_element
             .get_playback_switch(SelemChannelId::mono())
             .map(|playback| playback == 0)
@@ -0,0 +1,332 @@
+use super::{MappedCtrl, VolumeCtrl};
+use super::{Mixer, MixerConfig};
+use librespot_core::Error;
+use shell_words::split;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+// Changes of the volume that are smaller than this are our own changes coming back.
+const VOLUME_EPSILON: f64 = 1e-4;
+
+// How long opening the mixer waits for the command to reply to `get`.
+const GET_VOLUME_TIMEOUT: Duration = Duration::from_millis(500);
+
+const STATE_POISON_MSG: &str = "command mixer state mutex should not be poisoned";
+const HELPER_POISON_MSG: &str = "command mixer helper mutex should not be poisoned";
+
+#[derive(Debug, Error)]
+enum CommandMixerError {
+    #[error("<CommandMixer> Missing Required Shell Command")]
+    MissingCommand,
+
+    #[error("<CommandMixer> Failed to Parse Command args for {command}, {e}")]
+    InvalidArgs {
+        command: String,
+        e: shell_words::ParseError,
+    },
+
+    #[error("<CommandMixer> Command {command} Can Not be Executed, {e}")]
+    SpawnFailure { command: String, e: std::io::Error },
+
+    #[error("<CommandMixer> {0}")]
+    OnWrite(std::io::Error),
+
+    #[error("<CommandMixer> The Subprocess's stdin or stdout is None")]
+    NoPipe,
+}
+
+impl From<CommandMixerError> for Error {
+    fn from(e: CommandMixerError) -> Self {
+        use CommandMixerError::*;
+        match e {
+            MissingCommand | InvalidArgs { .. } => Error::invalid_argument(e),
+            SpawnFailure { .. } | OnWrite(_) | NoPipe => Error::unavailable(e),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MixerState {
+    // Linear, only set once the volume is known.
+    volume: Option<f64>,
+    listeners: Vec<mpsc::UnboundedSender<u16>>,
+}
+
+struct Shared {
+    state: Mutex<MixerState>,
+    reported: Condvar,
+    volume_ctrl: VolumeCtrl,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, MixerState> {
+        self.state.lock().expect(STATE_POISON_MSG)
+    }
+
+    // Called with the volume reported by the command.
+    fn volume_reported(&self, volume: f64) {
+        let mut state = self.lock();
+        let is_known = state
+            .volume
+            .is_some_and(|current| (current - volume).abs() <= VOLUME_EPSILON);
+
+        if !is_known {
+            state.volume = Some(volume);
+            self.reported.notify_all();
+
+            let unmapped_volume = self.volume_ctrl.as_unmapped(volume);
+            state
+                .listeners
+                .retain(|listener| listener.send(unmapped_volume).is_ok());
+        }
+    }
+}
+
+struct Helper {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+/// Controls the volume through a user-supplied shell command, for instance to reach an
+/// amplifier that librespot can't talk to directly.
+///
+/// The command keeps running, and uses a line protocol on its stdin and stdout. The
+/// volume is mapped by the volume control to 0.0 - 1.0.
+///
+/// - `set <volume>` is written when the volume is changed.
+/// - `get` is written when the mixer is opened, which waits up to 500 ms for the reply.
+/// - `volume <volume>` is read in reply to `get`, and whenever the volume was changed
+///   on the device, for instance with a knob on the amplifier. It should not be written
+///   in reply to `set`.
+///
+/// A command that exits is started again for the next line, so a script that handles a
+/// single line works as well.
+pub struct CommandMixer {
+    command: String,
+    args: Vec<String>,
+    shared: Arc<Shared>,
+    helper: Mutex<Option<Helper>>,
+}
+
+impl Mixer for CommandMixer {
+    fn open(config: MixerConfig) -> Result<Self, Error> {
+        let command = config.command.ok_or(CommandMixerError::MissingCommand)?;
+        let args = split(&command).map_err(|e| CommandMixerError::InvalidArgs {
+            command: command.clone(),
+            e,
+        })?;
+
+        if args.is_empty() {
+            return Err(CommandMixerError::MissingCommand.into());
+        }
+
+        info!(
+            "Mixing with command: {command} and volume control: {:?}",
+            config.volume_ctrl
+        );
+
+        let mixer = Self {
+            command,
+            args,
+            shared: Arc::new(Shared {
+                state: Mutex::new(MixerState::default()),
+                reported: Condvar::new(),
+                volume_ctrl: config.volume_ctrl,
+            }),
+            helper: Mutex::new(None),
+        };
+
+        mixer.send("get")?;
+
+        let state = mixer.shared.lock();
+        let (state, timeout) = mixer
+            .shared
+            .reported
+            .wait_timeout_while(state, GET_VOLUME_TIMEOUT, |state| state.volume.is_none())
+            .expect(STATE_POISON_MSG);
+        if timeout.timed_out() {
+            warn!("<CommandMixer> The command didn't reply to get, assuming full volume");
+        }
+        drop(state);
+
+        Ok(mixer)
+    }
+
+    fn volume(&self) -> u16 {
+        let mapped_volume = self.shared.lock().volume.unwrap_or(1.0);
+        self.shared.volume_ctrl.as_unmapped(mapped_volume)
+    }
+
+    fn set_volume(&self, volume: u16) {
+        let mapped_volume = self.shared.volume_ctrl.to_mapped(volume);
+        self.shared.lock().volume = Some(mapped_volume);
+
+        if let Err(e) = self.send(&format!("set {mapped_volume}")) {
+            error!("{e}");
+        }
+    }
+
+    fn volume_changes(&self) -> Option<mpsc::UnboundedReceiver<u16>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.shared.lock().listeners.push(sender);
+        Some(receiver)
+    }
+}
+
+impl Drop for CommandMixer {
+    fn drop(&mut self) {
+        let helper = match self.helper.get_mut() {
+            Ok(helper) => helper.take(),
+            Err(_) => None,
+        };
+
+        if let Some(mut helper) = helper {
+            // Closing stdin lets the command exit by itself.
+            drop(helper.stdin);
+
+            if let Ok(None) = helper.child.try_wait() {
+                let _ = helper.child.kill();
+                let _ = helper.child.wait();
+            }
+        }
+    }
+}
+
+impl CommandMixer {
+    pub const NAME: &'static str = "command";
+
+    fn send(&self, line: &str) -> Result<(), CommandMixerError> {
+        let mut helper = self.helper.lock().expect(HELPER_POISON_MSG);
+
+        // A command that has exited is started again, also when it exits while writing.
+        for retry in [false, true] {
+            if let Some(mut exited) =
+                helper.take_if(|running| !matches!(running.child.try_wait(), Ok(None)))
+            {
+                let _ = exited.child.wait();
+            }
+
+            let running = match helper.as_mut() {
+                Some(running) => running,
+                None => helper.insert(self.spawn()?),
+            };
+
+            match writeln!(running.stdin, "{line}").and_then(|_| running.stdin.flush()) {
+                Ok(()) => break,
+                Err(e) if retry => return Err(CommandMixerError::OnWrite(e)),
+                Err(e) => {
+                    debug!("<CommandMixer> Starting the command again after {e}");
+                    if let Some(mut exited) = helper.take() {
+                        let _ = exited.child.kill();
+                        let _ = exited.child.wait();
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn spawn(&self) -> Result<Helper, CommandMixerError> {
+        let mut child = Command::new(&self.args[0])
+            .args(&self.args[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| CommandMixerError::SpawnFailure {
+                command: self.command.clone(),
+                e,
+            })?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CommandMixerError::NoPipe);
+        };
+
+        let shared = self.shared.clone();
+        thread::spawn(move || read_volumes(&shared, stdout));
+
+        Ok(Helper { child, stdin })
+    }
+}
+
+// Reads the volumes reported by the command until it exits.
+fn read_volumes(shared: &Shared, stdout: ChildStdout) {
+    for line in BufReader::new(stdout).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("<CommandMixer> Unable to read from the command: {e}");
+                break;
+            }
+        };
+
+        match parse_volume(&line) {
+            Some(volume) => shared.volume_reported(volume),
+            None => debug!("<CommandMixer> Ignoring output of the command: {line}"),
+        }
+    }
+}
+
+// Parses a `volume <volume>` line of the command.
+fn parse_volume(line: &str) -> Option<f64> {
+    line.trim()
+        .strip_prefix("volume ")
+        .and_then(|volume| volume.trim().parse::<f64>().ok())
+        .filter(|volume| (0.0..=1.0).contains(volume))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_volume_lines() {
+        assert_eq!(parse_volume("volume 0.5"), Some(0.5));
+        assert_eq!(parse_volume("  volume   1 \r"), Some(1.0));
+        assert_eq!(parse_volume("volume 0"), Some(0.0));
+
+        for line in [
+            "",
+            "volume",
+            "volume ",
+            "volume loud",
+            "volume 1.5",
+            "volume -0.1",
+            "volume NaN",
+            "Volume 0.5",
+            "set 0.5",
+            "volume0.5",
+        ] {
+            assert_eq!(parse_volume(line), None, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn reports_only_changed_volumes() {
+        let shared = Shared {
+            state: Mutex::new(MixerState::default()),
+            reported: Condvar::new(),
+            volume_ctrl: VolumeCtrl::Linear,
+        };
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        shared.lock().listeners.push(sender);
+
+        shared.volume_reported(0.5);
+        assert_eq!(receiver.try_recv(), Ok(VolumeCtrl::MAX_VOLUME / 2));
+
+        // Our own change coming back, within the epsilon.
+        shared.volume_reported(0.5 + VOLUME_EPSILON / 2.0);
+        assert!(receiver.try_recv().is_err());
+
+        shared.volume_reported(1.0);
+        assert_eq!(receiver.try_recv(), Ok(VolumeCtrl::MAX_VOLUME));
+        assert_eq!(shared.lock().volume, Some(1.0));
+    }
+}
@@ -1,5 +1,6 @@
 use super::VolumeCtrl;
-use crate::player::db_to_ratio;
+use crate::config::VolumeCurve;
+use crate::player::{db_to_ratio, ratio_to_db};
 
 pub trait MappedCtrl {
     fn to_mapped(&self, volume: u16) -> f64;
@@ -24,11 +25,12 @@ impl MappedCtrl for VolumeCtrl {
 
         let normalized_volume = volume as f64 / Self::MAX_VOLUME as f64;
         let mapped_volume = if self.range_ok() {
-            match *self {
+            match self {
                 Self::Cubic(db_range) => {
-                    CubicMapping::linear_to_mapped(normalized_volume, db_range)
+                    CubicMapping::linear_to_mapped(normalized_volume, *db_range)
                 }
-                Self::Log(db_range) => LogMapping::linear_to_mapped(normalized_volume, db_range),
+                Self::Log(db_range) => LogMapping::linear_to_mapped(normalized_volume, *db_range),
+                Self::Custom(curve) => curve.linear_to_mapped(normalized_volume),
                 _ => normalized_volume,
             }
         } else {
@@ -57,9 +59,10 @@ impl MappedCtrl for VolumeCtrl {
         }
 
         let unmapped_volume = if self.range_ok() {
-            match *self {
-                Self::Cubic(db_range) => CubicMapping::mapped_to_linear(mapped_volume, db_range),
-                Self::Log(db_range) => LogMapping::mapped_to_linear(mapped_volume, db_range),
+            match self {
+                Self::Cubic(db_range) => CubicMapping::mapped_to_linear(mapped_volume, *db_range),
+                Self::Log(db_range) => LogMapping::mapped_to_linear(mapped_volume, *db_range),
+                Self::Custom(curve) => curve.mapped_to_linear(mapped_volume),
                 _ => mapped_volume,
             }
         } else {
@@ -76,6 +79,7 @@ impl MappedCtrl for VolumeCtrl {
             Self::Fixed => 0.0,
             Self::Linear => Self::DEFAULT_DB_RANGE, // arbitrary, could be anything > 0
             Self::Log(db_range) | Self::Cubic(db_range) => db_range,
+            Self::Custom(ref curve) => curve.db_range(),
         }
     }
 
@@ -89,7 +93,7 @@ impl MappedCtrl for VolumeCtrl {
     }
 
     fn range_ok(&self) -> bool {
-        self.db_range() > 0.0 || matches!(self, Self::Fixed | Self::Linear)
+        self.db_range() > 0.0 || matches!(self, Self::Fixed | Self::Linear | Self::Custom(_))
     }
 }
 
@@ -123,6 +127,50 @@ impl LogMapping {
     }
 }
 
+// Interpolates the dB between the points of a user-defined curve, for speakers that
+// none of the other mappings suit.
+impl VolumeCurve {
+    fn linear_to_mapped(&self, normalized_volume: f64) -> f64 {
+        db_to_ratio(self.db_at(normalized_volume))
+    }
+
+    fn mapped_to_linear(&self, mapped_volume: f64) -> f64 {
+        let points = self.points();
+        let db = ratio_to_db(mapped_volume);
+
+        let (first, last) = (points[0], points[points.len() - 1]);
+        if db <= first.1 {
+            return first.0;
+        } else if db >= last.1 {
+            return last.0;
+        }
+
+        let index = points.partition_point(|point| point.1 < db);
+        let ((volume_0, db_0), (volume_1, db_1)) = (points[index - 1], points[index]);
+        volume_0 + (volume_1 - volume_0) * (db - db_0) / (db_1 - db_0)
+    }
+
+    fn db_at(&self, normalized_volume: f64) -> f64 {
+        let points = self.points();
+
+        let (first, last) = (points[0], points[points.len() - 1]);
+        if normalized_volume <= first.0 {
+            return first.1;
+        } else if normalized_volume >= last.0 {
+            return last.1;
+        }
+
+        let index = points.partition_point(|point| point.0 < normalized_volume);
+        let ((volume_0, db_0), (volume_1, db_1)) = (points[index - 1], points[index]);
+        db_0 + (db_1 - db_0) * (normalized_volume - volume_0) / (volume_1 - volume_0)
+    }
+
+    fn db_range(&self) -> f64 {
+        // The loudest point is at 0 dB.
+        -self.points()[0].1
+    }
+}
+
 // Ported from: https://github.com/alsa-project/alsa-utils/blob/master/alsamixer/volume_mapping.c
 // which in turn was inspired by: https://www.robotplanet.dk/audio/audio_gui_design/
 //
@@ -155,3 +203,98 @@ impl CubicMapping {
         f64::powf(10.0, -db_range / 60.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn curve(points: &str) -> VolumeCurve {
+        VolumeCurve::from_str(points).expect("curve should be valid")
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < EPSILON,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn db_at_points_and_between() {
+        let curve = curve("0:-60,20:-30,60:-8,100:0");
+
+        for (volume, db) in [(0.0, -60.0), (0.2, -30.0), (0.6, -8.0), (1.0, 0.0)] {
+            assert_close(curve.db_at(volume), db);
+        }
+
+        assert_close(curve.db_at(0.1), -45.0);
+        assert_close(curve.db_at(0.4), -19.0);
+        assert_close(curve.db_at(0.8), -4.0);
+    }
+
+    #[test]
+    fn db_at_is_held_outside_points() {
+        let curve = curve("10:-40,80:-10");
+
+        assert_close(curve.db_at(0.0), -30.0);
+        assert_close(curve.db_at(0.05), -30.0);
+        assert_close(curve.db_at(0.9), 0.0);
+        assert_close(curve.db_at(1.0), 0.0);
+    }
+
+    #[test]
+    fn mapped_to_linear_round_trips() {
+        let curve = curve("0:-60,20:-30,60:-8,100:0");
+
+        for step in 0..=100 {
+            let volume = step as f64 / 100.0;
+            let mapped_volume = curve.linear_to_mapped(volume);
+            assert_close(curve.mapped_to_linear(mapped_volume), volume);
+        }
+    }
+
+    #[test]
+    fn mapped_to_linear_is_clamped() {
+        let curve = curve("10:-40,80:-10");
+
+        assert_close(curve.mapped_to_linear(0.0), 0.1);
+        assert_close(curve.mapped_to_linear(db_to_ratio(-50.0)), 0.1);
+        assert_close(curve.mapped_to_linear(1.0), 0.8);
+    }
+
+    #[test]
+    fn flat_segments() {
+        let curve = curve("0:-40,50:-20,70:-20,100:0");
+
+        assert_close(curve.db_at(0.5), -20.0);
+        assert_close(curve.db_at(0.6), -20.0);
+        assert_close(curve.db_at(0.7), -20.0);
+
+        // A dB of a flat segment maps back to where the segment starts.
+        assert_close(curve.mapped_to_linear(db_to_ratio(-20.0)), 0.5);
+        assert_close(curve.mapped_to_linear(db_to_ratio(-30.0)), 0.25);
+        assert_close(curve.mapped_to_linear(db_to_ratio(-10.0)), 0.85);
+    }
+
+    #[test]
+    fn custom_volume_ctrl_round_trips() {
+        let volume_ctrl = VolumeCtrl::Custom(curve("10:-50,50:-20,100:0"));
+
+        assert_close(volume_ctrl.db_range(), 50.0);
+        assert_close(volume_ctrl.to_mapped(0), 0.0);
+        assert_close(volume_ctrl.to_mapped(VolumeCtrl::MAX_VOLUME), 1.0);
+        assert_eq!(volume_ctrl.as_unmapped(0.0), 0);
+        assert_eq!(volume_ctrl.as_unmapped(1.0), VolumeCtrl::MAX_VOLUME);
+
+        for volume in (VolumeCtrl::MAX_VOLUME / 10..VolumeCtrl::MAX_VOLUME).step_by(1000) {
+            let unmapped_volume = volume_ctrl.as_unmapped(volume_ctrl.to_mapped(volume));
+            assert!(
+                unmapped_volume.abs_diff(volume) <= 1,
+                "{unmapped_volume} is not {volume}"
+            );
+        }
+    }
+}
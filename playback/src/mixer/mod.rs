@@ -1,6 +1,7 @@
 use crate::config::VolumeCtrl;
 use librespot_core::Error;
 use std::sync::Arc;
+use tokio::sync::mpsc;
 
 pub mod mappings;
 use self::mappings::MappedCtrl;
@@ -18,6 +19,12 @@ pub trait Mixer: Send + Sync {
     fn get_soft_volume(&self) -> Box<dyn VolumeGetter + Send> {
         Box::new(NoOpVolume)
     }
+
+    /// Volume changes that were made outside of librespot, for mixers that can follow
+    /// them. Each call returns a new receiver.
+    fn volume_changes(&self) -> Option<mpsc::UnboundedReceiver<u16>> {
+        None
+    }
 }
 
 pub trait VolumeGetter {
@@ -34,17 +41,35 @@ impl VolumeGetter for NoOpVolume {
 pub mod softmixer;
 use self::softmixer::SoftMixer;
 
+pub mod commandmixer;
+use self::commandmixer::CommandMixer;
+
 #[cfg(feature = "alsa-backend")]
 pub mod alsamixer;
 #[cfg(feature = "alsa-backend")]
 use self::alsamixer::AlsaMixer;
 
+#[cfg(feature = "pipewire-backend")]
+pub mod pipewiremixer;
+#[cfg(feature = "pipewire-backend")]
+use self::pipewiremixer::{PipeWireMixer, PipeWireVolume};
+
+#[cfg(feature = "pulseaudio-backend")]
+pub mod pulseaudiomixer;
+#[cfg(feature = "pulseaudio-backend")]
+use self::pulseaudiomixer::PulseAudioMixer;
+
 #[derive(Debug, Clone)]
 pub struct MixerConfig {
     pub device: String,
     pub control: String,
     pub index: u32,
     pub volume_ctrl: VolumeCtrl,
+    /// The shell command of the `command` mixer.
+    pub command: Option<String>,
+    /// The stream volume of the `pipewire` mixer, to share with the `pipewire` sink.
+    #[cfg(feature = "pipewire-backend")]
+    pub pipewire_volume: PipeWireVolume,
 }
 
 impl Default for MixerConfig {
@@ -54,6 +79,9 @@ impl Default for MixerConfig {
             control: String::from("PCM"),
             index: 0,
             volume_ctrl: VolumeCtrl::default(),
+            command: None,
+            #[cfg(feature = "pipewire-backend")]
+            pipewire_volume: PipeWireVolume::default(),
         }
     }
 }
@@ -68,6 +96,11 @@ pub const MIXERS: &[(&str, MixerFn)] = &[
     (SoftMixer::NAME, mk_sink::<SoftMixer>), // default goes first
     #[cfg(feature = "alsa-backend")]
     (AlsaMixer::NAME, mk_sink::<AlsaMixer>),
+    #[cfg(feature = "pipewire-backend")]
+    (PipeWireMixer::NAME, mk_sink::<PipeWireMixer>),
+    #[cfg(feature = "pulseaudio-backend")]
+    (PulseAudioMixer::NAME, mk_sink::<PulseAudioMixer>),
+    (CommandMixer::NAME, mk_sink::<CommandMixer>),
 ];
 
 pub fn find(name: Option<&str>) -> Option<MixerFn> {
@@ -0,0 +1,131 @@
+use super::{MappedCtrl, VolumeCtrl};
+use super::{Mixer, MixerConfig};
+use librespot_core::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::mpsc;
+
+const STREAM_VOLUME_POISON_MSG: &str = "PipeWire stream volume mutex should not be poisoned";
+
+// Changes of the volume that are smaller than this are our own changes coming back.
+const VOLUME_EPSILON: f32 = 1e-4;
+
+type VolumeListener = Box<dyn Fn(f32) -> bool + Send>;
+
+#[derive(Default)]
+struct StreamVolumeState {
+    // Linear, only set once the mixer is in use.
+    volume: Option<f32>,
+    stream: Option<pipewire::channel::Sender<f32>>,
+    listeners: Vec<VolumeListener>,
+}
+
+/// The volume of a PipeWire stream, shared by a [`PipeWireMixer`] and the `pipewire` sink
+/// that are created separately.
+///
+/// The mixer controls the stream of the sink that was opened with a clone of its
+/// [`MixerConfig::pipewire_volume`], see
+/// [`open_pipewire`](crate::audio_backend::open_pipewire). Sinks that are opened by name,
+/// such as secondary outputs, have a volume of their own.
+#[derive(Clone, Default)]
+pub struct PipeWireVolume {
+    state: Arc<Mutex<StreamVolumeState>>,
+}
+
+impl fmt::Debug for PipeWireVolume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PipeWireVolume")
+            .field("volume", &self.volume())
+            .finish_non_exhaustive()
+    }
+}
+
+impl PipeWireVolume {
+    fn lock(&self) -> MutexGuard<'_, StreamVolumeState> {
+        self.state.lock().expect(STREAM_VOLUME_POISON_MSG)
+    }
+
+    pub(crate) fn volume(&self) -> Option<f32> {
+        self.lock().volume
+    }
+
+    fn set_volume(&self, volume: f32) {
+        let mut state = self.lock();
+        state.volume = Some(volume);
+        if let Some(stream) = &state.stream {
+            let _ = stream.send(volume);
+        }
+    }
+
+    fn add_listener(&self, listener: VolumeListener) {
+        self.lock().listeners.push(listener);
+    }
+
+    /// Registers the stream that volume changes are sent to.
+    pub(crate) fn attach(&self, stream: pipewire::channel::Sender<f32>) {
+        self.lock().stream = Some(stream);
+    }
+
+    pub(crate) fn detach(&self) {
+        self.lock().stream = None;
+    }
+
+    /// Called with the volume of the stream, which was possibly changed by someone else.
+    pub(crate) fn stream_volume_changed(&self, volume: f32) {
+        let mut state = self.lock();
+        match state.volume {
+            Some(current) if (current - volume).abs() > VOLUME_EPSILON => {
+                state.volume = Some(volume);
+                state.listeners.retain(|listener| listener(volume));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Controls the volume of the PipeWire stream of the `pipewire` backend, and follows
+/// changes to it from other applications.
+pub struct PipeWireMixer {
+    volume_ctrl: VolumeCtrl,
+    stream_volume: PipeWireVolume,
+}
+
+impl Mixer for PipeWireMixer {
+    fn open(config: MixerConfig) -> Result<Self, Error> {
+        let volume_ctrl = config.volume_ctrl;
+        info!("Mixing with PipeWire stream volume and volume control: {volume_ctrl:?}");
+
+        Ok(Self {
+            volume_ctrl,
+            stream_volume: config.pipewire_volume,
+        })
+    }
+
+    fn volume(&self) -> u16 {
+        let mapped_volume = self.stream_volume.volume().unwrap_or(1.0);
+        self.volume_ctrl.as_unmapped(mapped_volume as f64)
+    }
+
+    fn set_volume(&self, volume: u16) {
+        let mapped_volume = self.volume_ctrl.to_mapped(volume);
+        self.stream_volume.set_volume(mapped_volume as f32);
+    }
+
+    fn volume_changes(&self) -> Option<mpsc::UnboundedReceiver<u16>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let volume_ctrl = self.volume_ctrl.clone();
+
+        self.stream_volume
+            .add_listener(Box::new(move |mapped_volume| {
+                sender
+                    .send(volume_ctrl.as_unmapped(mapped_volume as f64))
+                    .is_ok()
+            }));
+
+        Some(receiver)
+    }
+}
+
+impl PipeWireMixer {
+    pub const NAME: &'static str = "pipewire";
+}
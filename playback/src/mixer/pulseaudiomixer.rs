@@ -0,0 +1,409 @@
+use super::{MappedCtrl, VolumeCtrl};
+use super::{Mixer, MixerConfig};
+use libpulse_binding::{
+    callbacks::ListResult,
+    context::{
+        self, Context, FlagSet,
+        subscribe::{Facility, InterestMaskSet, Operation},
+    },
+    error::PAErr,
+    mainloop::standard::{IterateResult, Mainloop},
+    proplist::properties::APPLICATION_PROCESS_ID,
+    time::MicroSeconds,
+    volume::{ChannelVolumes, Volume, VolumeLinear},
+};
+use librespot_core::Error;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::process;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard, mpsc as std_mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+// How long the mixer thread waits for PulseAudio before it looks for new commands.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// After the connection is lost, connecting again is retried with a delay that doubles up to
+// the maximum.
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+// Changes of the volume that are smaller than this are our own changes coming back.
+const VOLUME_EPSILON: f64 = 1e-3;
+
+const CLIENT_NAME: &str = "librespot";
+
+const STATE_POISON_MSG: &str = "PulseAudio mixer state mutex should not be poisoned";
+
+#[derive(Debug, Error)]
+enum PulseAudioMixerError {
+    #[error("<PulseAudioMixer> Unable to Connect to PulseAudio, {0}")]
+    ConnectionRefused(PAErr),
+
+    #[error("<PulseAudioMixer> Unable to Create the PulseAudio Mainloop")]
+    NoMainloop,
+
+    #[error("<PulseAudioMixer> Mixer Thread Stopped")]
+    ThreadStopped,
+}
+
+impl From<PulseAudioMixerError> for Error {
+    fn from(value: PulseAudioMixerError) -> Self {
+        Error::unavailable(value)
+    }
+}
+
+enum Command {
+    SetVolume(f64),
+    Quit,
+}
+
+#[derive(Default)]
+struct MixerState {
+    // Linear, only set once the volume is known.
+    volume: Option<f64>,
+    listeners: Vec<mpsc::UnboundedSender<u16>>,
+}
+
+struct Shared {
+    state: Mutex<MixerState>,
+    volume_ctrl: VolumeCtrl,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, MixerState> {
+        self.state.lock().expect(STATE_POISON_MSG)
+    }
+
+    fn volume_changed(&self, volume: f64) {
+        let mut state = self.lock();
+        state.volume = Some(volume);
+
+        let unmapped_volume = self.volume_ctrl.as_unmapped(volume);
+        state
+            .listeners
+            .retain(|listener| listener.send(unmapped_volume).is_ok());
+    }
+}
+
+/// Controls the volume of the sink inputs of librespot, i.e. the streams of the
+/// `pulseaudio` backend, and follows changes to it from other applications.
+///
+/// When the connection to PulseAudio is lost, for instance because it restarted, the
+/// mixer connects again and sets the volume of the streams it finds.
+pub struct PulseAudioMixer {
+    shared: Arc<Shared>,
+    commands: std_mpsc::Sender<Command>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Mixer for PulseAudioMixer {
+    fn open(config: MixerConfig) -> Result<Self, Error> {
+        let volume_ctrl = config.volume_ctrl;
+        info!("Mixing with PulseAudio stream volume and volume control: {volume_ctrl:?}");
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(MixerState::default()),
+            volume_ctrl,
+        });
+
+        let (ready_tx, ready_rx) = std_mpsc::channel();
+        let (commands, commands_rx) = std_mpsc::channel();
+        let thread_shared = shared.clone();
+
+        let thread = thread::spawn(move || {
+            let mut ready_tx = Some(ready_tx);
+            let mut delay = RECONNECT_INITIAL_DELAY;
+
+            loop {
+                let result = run_mixer(&thread_shared, &commands_rx, &mut || {
+                    delay = RECONNECT_INITIAL_DELAY;
+                    if let Some(ready_tx) = ready_tx.take() {
+                        let _ = ready_tx.send(Ok(()));
+                    }
+                });
+
+                let e = match result {
+                    Ok(()) => return,
+                    Err(e) => e,
+                };
+
+                // Opening the mixer fails when there is no connection at all.
+                if let Some(ready_tx) = ready_tx.take() {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+
+                warn!("{e}, connecting again in {} s", delay.as_secs());
+
+                // The volume is kept by `set_volume`, and set on the streams once connected.
+                let deadline = Instant::now() + delay;
+                loop {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match commands_rx.recv_timeout(timeout) {
+                        Ok(Command::SetVolume(_)) => (),
+                        Ok(Command::Quit) | Err(std_mpsc::RecvTimeoutError::Disconnected) => {
+                            return;
+                        }
+                        Err(std_mpsc::RecvTimeoutError::Timeout) => break,
+                    }
+                }
+
+                delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+            }
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                shared,
+                commands,
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(PulseAudioMixerError::ThreadStopped.into()),
+        }
+    }
+
+    fn volume(&self) -> u16 {
+        let mapped_volume = self.shared.lock().volume.unwrap_or(1.0);
+        self.shared.volume_ctrl.as_unmapped(mapped_volume)
+    }
+
+    fn set_volume(&self, volume: u16) {
+        let mapped_volume = self.shared.volume_ctrl.to_mapped(volume);
+        self.shared.lock().volume = Some(mapped_volume);
+
+        if self
+            .commands
+            .send(Command::SetVolume(mapped_volume))
+            .is_err()
+        {
+            warn!("<PulseAudioMixer> Unable to set the volume, the mixer thread has stopped");
+        }
+    }
+
+    fn volume_changes(&self) -> Option<mpsc::UnboundedReceiver<u16>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.shared.lock().listeners.push(sender);
+        Some(receiver)
+    }
+}
+
+impl Drop for PulseAudioMixer {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("<PulseAudioMixer> Mixer thread panicked");
+            }
+        }
+    }
+}
+
+impl PulseAudioMixer {
+    pub const NAME: &'static str = "pulseaudio";
+}
+
+enum SinkInputEvent {
+    Changed(u32),
+    Removed(u32),
+}
+
+struct SinkInput {
+    index: u32,
+    channels: u8,
+    volume: f64,
+    // The volume generation when the info was requested.
+    generation: u64,
+}
+
+// Runs the PulseAudio mainloop until told to quit, or until the connection is lost. Calls
+// `connected` once connected.
+fn run_mixer(
+    shared: &Shared,
+    commands: &std_mpsc::Receiver<Command>,
+    connected: &mut dyn FnMut(),
+) -> Result<(), PulseAudioMixerError> {
+    let mut mainloop = Mainloop::new().ok_or(PulseAudioMixerError::NoMainloop)?;
+    let mut context =
+        Context::new(&mainloop, CLIENT_NAME).ok_or(PulseAudioMixerError::NoMainloop)?;
+
+    context
+        .connect(None, FlagSet::NOFLAGS, None)
+        .map_err(PulseAudioMixerError::ConnectionRefused)?;
+
+    loop {
+        if let IterateResult::Err(e) = mainloop.iterate(true) {
+            return Err(PulseAudioMixerError::ConnectionRefused(e));
+        }
+
+        match context.get_state() {
+            context::State::Ready => break,
+            context::State::Failed | context::State::Terminated => {
+                return Err(PulseAudioMixerError::ConnectionRefused(context.errno()));
+            }
+            _ => (),
+        }
+    }
+
+    let events = Rc::new(RefCell::new(VecDeque::new()));
+    let subscribe_events = events.clone();
+    context.set_subscribe_callback(Some(Box::new(move |facility, operation, index| {
+        if facility != Some(Facility::SinkInput) {
+            return;
+        }
+
+        let event = match operation {
+            Some(Operation::New) | Some(Operation::Changed) => SinkInputEvent::Changed(index),
+            Some(Operation::Removed) => SinkInputEvent::Removed(index),
+            None => return,
+        };
+        subscribe_events.borrow_mut().push_back(event);
+    })));
+    context.subscribe(InterestMaskSet::SINK_INPUT, |_| ());
+
+    let infos = Rc::new(RefCell::new(VecDeque::new()));
+    let mut introspector = context.introspect();
+    let pid = process::id().to_string();
+
+    // Our streams, with their number of channels.
+    let mut sink_inputs: HashMap<u32, u8> = HashMap::new();
+    // Bumped whenever we set the volume, so that infos from before are not taken for
+    // changes made by someone else.
+    let mut generation = 0;
+
+    let list_infos = infos.clone();
+    let list_pid = pid.clone();
+    introspector.get_sink_input_info_list(move |result| {
+        if let Some(sink_input) = own_sink_input(result, &list_pid, 0) {
+            list_infos.borrow_mut().push_back(sink_input);
+        }
+    });
+
+    connected();
+
+    let timeout = MicroSeconds(COMMAND_POLL_INTERVAL.as_micros() as u64);
+    loop {
+        iterate(&mut mainloop, timeout).map_err(PulseAudioMixerError::ConnectionRefused)?;
+
+        match context.get_state() {
+            context::State::Failed | context::State::Terminated => {
+                return Err(PulseAudioMixerError::ConnectionRefused(context.errno()));
+            }
+            _ => (),
+        }
+
+        loop {
+            match commands.try_recv() {
+                Ok(Command::SetVolume(volume)) => {
+                    generation += 1;
+                    for (&index, &channels) in &sink_inputs {
+                        set_sink_input_volume(&mut introspector, index, channels, volume);
+                    }
+                }
+                Ok(Command::Quit) | Err(std_mpsc::TryRecvError::Disconnected) => return Ok(()),
+                Err(std_mpsc::TryRecvError::Empty) => break,
+            }
+        }
+
+        while let Some(event) = events.borrow_mut().pop_front() {
+            match event {
+                SinkInputEvent::Changed(index) => {
+                    let info_infos = infos.clone();
+                    let info_pid = pid.clone();
+                    let info_generation = generation;
+                    introspector.get_sink_input_info(index, move |result| {
+                        if let Some(sink_input) = own_sink_input(result, &info_pid, info_generation)
+                        {
+                            info_infos.borrow_mut().push_back(sink_input);
+                        }
+                    });
+                }
+                SinkInputEvent::Removed(index) => {
+                    sink_inputs.remove(&index);
+                }
+            }
+        }
+
+        while let Some(sink_input) = infos.borrow_mut().pop_front() {
+            let volume = shared.lock().volume;
+            let is_new = sink_inputs
+                .insert(sink_input.index, sink_input.channels)
+                .is_none();
+
+            match volume {
+                // A new stream starts with the volume of the mixer.
+                Some(volume) if is_new => {
+                    generation += 1;
+                    set_sink_input_volume(
+                        &mut introspector,
+                        sink_input.index,
+                        sink_input.channels,
+                        volume,
+                    );
+                }
+                Some(volume)
+                    if sink_input.generation == generation
+                        && (volume - sink_input.volume).abs() > VOLUME_EPSILON =>
+                {
+                    shared.volume_changed(sink_input.volume);
+                }
+                Some(_) => (),
+                None => shared.volume_changed(sink_input.volume),
+            }
+        }
+    }
+}
+
+fn iterate(mainloop: &mut Mainloop, timeout: MicroSeconds) -> Result<(), PAErr> {
+    mainloop.prepare(Some(timeout))?;
+    mainloop.poll()?;
+    mainloop.dispatch()?;
+    Ok(())
+}
+
+fn own_sink_input(
+    result: ListResult<&context::introspect::SinkInputInfo>,
+    pid: &str,
+    generation: u64,
+) -> Option<SinkInput> {
+    let ListResult::Item(info) = result else {
+        return None;
+    };
+
+    let is_own = info.proplist.get_str(APPLICATION_PROCESS_ID).as_deref() == Some(pid);
+    if !is_own || !info.volume_writable {
+        return None;
+    }
+
+    Some(SinkInput {
+        index: info.index,
+        channels: info.volume.len(),
+        volume: VolumeLinear::from(info.volume.avg()).0,
+        generation,
+    })
+}
+
+fn set_sink_input_volume(
+    introspector: &mut context::introspect::Introspector,
+    index: u32,
+    channels: u8,
+    volume: f64,
+) {
+    let mut volumes = ChannelVolumes::default();
+    volumes.set(channels, Volume::from(VolumeLinear(volume)));
+
+    introspector.set_sink_input_volume(
+        index,
+        &volumes,
+        Some(Box::new(move |success| {
+            if !success {
+                warn!("<PulseAudioMixer> Unable to set the volume of sink input {index}");
+            }
+        })),
+    );
+}
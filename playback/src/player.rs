@@ -1,12 +1,15 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt,
+    fs::File,
     future::Future,
     io::{self, Read, Seek, SeekFrom},
     mem,
     pin::Pin,
     process::exit,
+    str::FromStr,
     sync::Mutex,
+    sync::mpsc as std_mpsc,
     sync::{
         Arc,
         atomic::{AtomicUsize, Ordering},
@@ -20,13 +23,21 @@ use std::{
 use crate::decoder::PassthroughDecoder;
 use crate::{
     audio::{AudioDecrypt, AudioFetchParams, AudioFile, StreamLoaderController},
-    audio_backend::Sink,
-    config::{Bitrate, NormalisationMethod, NormalisationType, PlayerConfig},
+    audio_backend::{self, BufferedSink, BufferedSinkStats, Sink, SinkError, SinkResult, TeeSink},
+    config::{
+        AudioFormat, Bitrate, CrossfadeCurve, NormalisationMethod, NormalisationType, PlayerConfig,
+        PreviewMode,
+    },
     convert::Converter,
     core::{Error, Session, SpotifyId, SpotifyUri, util::SeqGenerator},
     decoder::{AudioDecoder, AudioPacket, AudioPacketPosition, SymphoniaDecoder},
+    filter::{Equalizer, FilterChain},
+    local_files::LocalFiles,
     metadata::audio::{AudioFileFormat, AudioFiles, AudioItem},
+    meter::{LevelMeter, Levels},
     mixer::VolumeGetter,
+    resampler::Resampler,
+    time_stretch::{TimeStretcher, VALID_PLAYBACK_SPEED_RANGE},
 };
 use futures_util::{
     StreamExt, TryFutureExt, future, future::FusedFuture,
@@ -36,7 +47,7 @@ use librespot_metadata::track::Tracks;
 use symphonia::core::io::MediaSource;
 use tokio::sync::{mpsc, oneshot};
 
-use crate::SAMPLES_PER_SECOND;
+use crate::{NUM_CHANNELS, SAMPLE_RATE, SAMPLES_PER_SECOND};
 
 const PRELOAD_NEXT_TRACK_BEFORE_END_DURATION_MS: u32 = 30000;
 pub const DB_VOLTAGE_RATIO: f64 = 20.0;
@@ -48,6 +59,24 @@ const SPOTIFY_OGG_HEADER_END: u64 = 0xa7;
 
 const LOAD_HANDLES_POISON_MSG: &str = "load handles mutex should not be poisoned";
 
+// The volume is ramped down over this time before the end set by `Player::set_sleep_fade`.
+const SLEEP_FADE_DURATION: Duration = Duration::from_secs(60);
+
+// Fades are decoded and played right away by the command that needs them, which blocks the
+// player thread, so they are kept short.
+const MAX_FADE_DURATION: Duration = Duration::from_millis(500);
+
+// Asking some sinks for their delay is expensive, so it is cached for this long, or for the
+// position update interval if that is set.
+const SINK_DELAY_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// How far the reported position may drift from what is heard, when the sink can tell its delay.
+const SYNCED_POSITION_TOLERANCE: Duration = Duration::from_millis(250);
+
+// After a sink error, starting the sink is retried with a delay that doubles up to the maximum.
+const SINK_RETRY_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const SINK_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
 pub type PlayerResult = Result<(), Error>;
 
 pub struct Player {
@@ -64,20 +93,59 @@ pub enum SinkStatus {
 
 pub type SinkEventCallback = Box<dyn Fn(SinkStatus) + Send>;
 
+// Playback that was paused by a sink error, to resume once the sink starts again.
+struct SinkRecovery {
+    play_request_id: u64,
+    attempts: u32,
+    retry: Pin<Box<tokio::time::Sleep>>,
+}
+
+/// When a sleep timer stops playback.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SleepTimer {
+    After(Duration),
+    EndOfTrack,
+    EndOfContext,
+}
+
+// Parses `end-of-track`, `end-of-context` or a number of minutes.
+impl FromStr for SleepTimer {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "end-of-track" => Ok(Self::EndOfTrack),
+            "end-of-context" => Ok(Self::EndOfContext),
+            minutes => match minutes.parse::<u64>() {
+                Ok(minutes) if minutes > 0 => Ok(Self::After(Duration::from_secs(minutes * 60))),
+                _ => Err(()),
+            },
+        }
+    }
+}
+
 struct PlayerInternal {
     session: Session,
     config: PlayerConfig,
     commands: mpsc::UnboundedReceiver<PlayerCommand>,
     load_handles: Arc<Mutex<HashMap<thread::ThreadId, thread::JoinHandle<()>>>>,
+    local_files: Option<Arc<LocalFiles>>,
 
     state: PlayerState,
     preload: PlayerPreload,
     sink: Box<dyn Sink>,
     sink_status: SinkStatus,
     sink_event_callback: Option<SinkEventCallback>,
+    sink_recovery: Option<SinkRecovery>,
+    output_buffer_stats: Option<Arc<BufferedSinkStats>>,
+    reported_xruns: (u64, u64),
     volume_getter: Box<dyn VolumeGetter + Send>,
     event_senders: Vec<mpsc::UnboundedSender<PlayerEvent>>,
+    pcm_taps: Vec<PcmTapSender>,
+    level_meter: Option<LevelMeter>,
     converter: Converter,
+    filters: FilterChain,
+    resampler: Option<Resampler>,
+    time_stretcher: Option<TimeStretcher>,
 
     normalisation_integrators: [f64; 2],
     normalisation_peaks: [f64; 2],
@@ -86,9 +154,20 @@ struct PlayerInternal {
 
     auto_normalise_as_album: bool,
 
+    crossfade: Option<PlayerCrossfade>,
+    crossfade_play_request_id: Option<u64>,
+
+    silent_samples: usize,
+    trimmed_samples: usize,
+
+    fade: Option<PlayerFade>,
+    sleep_fade: Option<Instant>,
+
     player_id: usize,
     play_request_id_generator: SeqGenerator<u64>,
     last_progress_update: Instant,
+    // The delay of the sink and when it was asked for, cleared when the sink changes.
+    sink_delay: Option<(Instant, Option<Duration>)>,
 }
 
 static PLAYER_COUNTER: AtomicUsize = AtomicUsize::new(0);
@@ -111,6 +190,15 @@ enum PlayerCommand {
     SetSinkEventCallback(Option<SinkEventCallback>),
     EmitVolumeChangedEvent(u16),
     SetAutoNormaliseAsAlbum(bool),
+    SetPlaybackSpeed(f64),
+    SetSleepFade(Option<Instant>),
+    GetStatus(oneshot::Sender<PlayerStatus>),
+    AddPcmTap(PcmTapSender),
+    SetSink {
+        backend: String,
+        device: Option<String>,
+        format: AudioFormat,
+    },
     EmitSessionDisconnectedEvent {
         connection_id: String,
         user_name: String,
@@ -132,6 +220,9 @@ enum PlayerCommand {
         track: bool,
     },
     EmitAutoPlayChangedEvent(bool),
+    EmitSleepTimerArmedEvent(SleepTimer),
+    EmitSleepTimerCancelledEvent,
+    EmitSleepTimerFiredEvent(SleepTimer),
 }
 
 #[derive(Debug, Clone)]
@@ -212,6 +303,28 @@ pub enum PlayerEvent {
         track_id: SpotifyUri,
         position_ms: u32,
     },
+    /// Requires `PlayerConfig::level_meter_interval` to be set to Some.
+    /// Sent periodically while playing with the levels of the audio over the last interval.
+    Levels {
+        play_request_id: u64,
+        track_id: SpotifyUri,
+        levels: Levels,
+    },
+    /// Requires `PlayerConfig::output_buffer` to be set to Some.
+    /// Sent when the output buffer ran empty or had to drop audio, with the totals since the
+    /// player was created.
+    OutputBufferXrun {
+        underruns: u64,
+        overruns: u64,
+    },
+    /// Requires `PlayerConfig::silence_trim` to be enabled.
+    /// Sent after silence was skipped, with the amount of time that was saved.
+    SilenceTrimmed {
+        play_request_id: u64,
+        track_id: SpotifyUri,
+        position_ms: u32,
+        trimmed_ms: u32,
+    },
     TrackChanged {
         audio_item: Box<AudioItem>,
     },
@@ -239,9 +352,25 @@ pub enum PlayerEvent {
     AutoPlayChanged {
         auto_play: bool,
     },
+    SleepTimerArmed {
+        timer: SleepTimer,
+    },
+    SleepTimerCancelled,
+    // Fired when the sleep timer stopped playback.
+    SleepTimerFired {
+        timer: SleepTimer,
+    },
     FilterExplicitContentChanged {
         filter: bool,
     },
+    /// Sent when the sink failed. When it failed while playing, playback is paused and resumed
+    /// once the sink can be started again, which is retried with an increasing delay.
+    ///
+    /// Also sent when the sink of `Player::set_sink` can't be opened, in which case the
+    /// previous sink is kept.
+    SinkError {
+        error: String,
+    },
 }
 
 impl PlayerEvent {
@@ -274,6 +403,9 @@ impl PlayerEvent {
             }
             | Seeked {
                 play_request_id, ..
+            }
+            | SilenceTrimmed {
+                play_request_id, ..
             } => Some(*play_request_id),
             _ => None,
         }
@@ -282,6 +414,64 @@ impl PlayerEvent {
 
 pub type PlayerEventChannel = mpsc::UnboundedReceiver<PlayerEvent>;
 
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum PlaybackState {
+    Stopped,
+    Loading,
+    Playing,
+    Paused,
+    EndOfTrack,
+}
+
+/// A copy of the audio that is written to the sink, as returned by `Player::add_pcm_tap`.
+///
+/// Packets hold interleaved stereo samples at the sample rate of the sink, after volume control.
+/// Packets that don't fit in the buffer are dropped rather than holding up playback.
+pub struct PcmTap {
+    receiver: std_mpsc::Receiver<Vec<f64>>,
+    dropped_frames: Arc<AtomicUsize>,
+}
+
+impl PcmTap {
+    /// Blocks until a packet is available. Returns `None` once the player has shut down.
+    pub fn recv(&self) -> Option<Vec<f64>> {
+        self.receiver.recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Vec<f64>> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    pub fn try_recv(&self) -> Option<Vec<f64>> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// The number of frames that were dropped because the buffer was full.
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+}
+
+struct PcmTapSender {
+    sender: std_mpsc::SyncSender<Vec<f64>>,
+    dropped_frames: Arc<AtomicUsize>,
+}
+
+/// A snapshot of the player, as returned by `Player::status`.
+#[derive(Debug, Clone)]
+pub struct PlayerStatus {
+    pub state: PlaybackState,
+    pub track_id: Option<SpotifyUri>,
+    pub play_request_id: Option<u64>,
+    /// The position of what is heard, if the sink can tell its delay. Otherwise the position
+    /// of the decoder, which may be slightly ahead.
+    pub position_ms: u32,
+    pub duration_ms: Option<u32>,
+    pub normalisation_factor: Option<f64>,
+    /// The format that is streamed, which also determines the bitrate.
+    pub format: Option<AudioFileFormat>,
+}
+
 #[inline]
 pub fn db_to_ratio(db: f64) -> f64 {
     f64::powf(10.0, db / DB_VOLTAGE_RATIO)
@@ -300,6 +490,12 @@ pub fn coefficient_to_duration(coefficient: f64) -> Duration {
     Duration::from_secs_f64(-1.0 / f64::ln(coefficient) / SAMPLES_PER_SECOND as f64)
 }
 
+// The instant at which playback would have started, had the track been played
+// from the beginning at the given playback speed.
+fn nominal_start_time(position: Duration, playback_speed: f64) -> Option<Instant> {
+    Instant::now().checked_sub(position.div_f64(playback_speed))
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct NormalisationData {
     // Spotify provides these as `f32`, but audio metadata can contain up to `f64`.
@@ -428,7 +624,7 @@ impl NormalisationData {
 
 impl Player {
     pub fn new<F>(
-        config: PlayerConfig,
+        mut config: PlayerConfig,
         session: Session,
         volume_getter: Box<dyn VolumeGetter + Send>,
         sink_builder: F,
@@ -464,27 +660,77 @@ impl Player {
             }
         }
 
+        // The sink is opened at the configured rate, so this plays at the wrong speed unless
+        // the caller checked the rate.
+        if !PlayerConfig::supports_sample_rate(config.sample_rate) {
+            error!(
+                "Unable to resample from {SAMPLE_RATE} Hz to {} Hz, playing at {SAMPLE_RATE} Hz instead",
+                config.sample_rate
+            );
+            config.sample_rate = SAMPLE_RATE;
+        }
+        let resampler = Resampler::new(config.sample_rate);
+
         let handle = thread::spawn(move || {
             let player_id = PLAYER_COUNTER.fetch_add(1, Ordering::AcqRel);
             debug!("new Player [{player_id}]");
 
             let converter = Converter::new(config.ditherer);
+            if let Some(ref resampler) = resampler {
+                info!(
+                    "Resampling from {SAMPLE_RATE} Hz to {} Hz",
+                    resampler.output_rate()
+                );
+            }
+            let mut filters = FilterChain::new(&config.filters);
+            if !config.equalizer.is_empty() {
+                filters.push(Box::new(Equalizer::new(&config.equalizer)));
+            }
             let normalisation_knee_factor = 1.0 / (8.0 * config.normalisation_knee_db);
 
+            let level_meter = config.level_meter_interval.map(|interval| {
+                let sample_rate = resampler
+                    .as_ref()
+                    .map_or(SAMPLE_RATE, Resampler::output_rate);
+                LevelMeter::new(interval, sample_rate)
+            });
+
+            let sample_rate = resampler
+                .as_ref()
+                .map_or(SAMPLE_RATE, Resampler::output_rate);
+            // Like without a buffer, a sink builder that fails takes down the player.
+            let (sink, output_buffer_stats) =
+                build_sink(&config, sample_rate, move || Ok(sink_builder()))
+                    .unwrap_or_else(|e| panic!("Unable to open the audio sink: {e}"));
+
+            let local_files = config
+                .local_files_dir
+                .as_ref()
+                .map(|dir| Arc::new(LocalFiles::new(dir)));
+
             let internal = PlayerInternal {
                 session,
                 config,
                 commands: cmd_rx,
                 load_handles: Arc::new(Mutex::new(HashMap::new())),
+                local_files,
 
                 state: PlayerState::Stopped,
                 preload: PlayerPreload::None,
-                sink: sink_builder(),
+                sink,
                 sink_status: SinkStatus::Closed,
                 sink_event_callback: None,
+                sink_recovery: None,
+                output_buffer_stats,
+                reported_xruns: (0, 0),
                 volume_getter,
                 event_senders: vec![],
+                pcm_taps: vec![],
+                level_meter,
                 converter,
+                filters,
+                resampler,
+                time_stretcher: None,
 
                 normalisation_peaks: [0.0; 2],
                 normalisation_integrators: [0.0; 2],
@@ -493,9 +739,19 @@ impl Player {
 
                 auto_normalise_as_album: false,
 
+                crossfade: None,
+                crossfade_play_request_id: None,
+
+                silent_samples: 0,
+                trimmed_samples: 0,
+
+                fade: None,
+                sleep_fade: None,
+
                 player_id,
                 play_request_id_generator: SeqGenerator::new(0),
                 last_progress_update: Instant::now(),
+                sink_delay: None,
             };
 
             // While PlayerInternal is written as a future, it still contains blocking code.
@@ -565,6 +821,49 @@ impl Player {
         event_receiver
     }
 
+    /// Returns a snapshot of the current state, or `None` if the player has shut down.
+    ///
+    /// This waits for the player thread to answer, so blocking on it from the player thread,
+    /// for instance in a [`SinkEventCallback`], never returns.
+    pub async fn status(&self) -> Option<PlayerStatus> {
+        let (status_sender, status_receiver) = oneshot::channel();
+        self.command(PlayerCommand::GetStatus(status_sender));
+        status_receiver.await.ok()
+    }
+
+    /// Switches to the backend with this name or another device, without interrupting the
+    /// session. Playback continues from the position that was heard on the new sink, which
+    /// gets the output buffer and tee outputs of the [`PlayerConfig`] like at startup.
+    ///
+    /// The sink is opened with [`open_sink`](crate::audio_backend::open_sink), so the device
+    /// should be one that [`list_devices`](crate::audio_backend::list_devices) returns for the
+    /// backend. When it can't be opened, a [`PlayerEvent::SinkError`] is sent and the current
+    /// sink is kept.
+    pub fn set_sink(&self, backend: &str, device: Option<String>, format: AudioFormat) {
+        self.command(PlayerCommand::SetSink {
+            backend: backend.to_string(),
+            device,
+            format,
+        });
+    }
+
+    /// Subscribes to a copy of the audio that is written to the sink, buffering up to
+    /// `capacity` packets. See [`PcmTap`].
+    pub fn add_pcm_tap(&self, capacity: usize) -> PcmTap {
+        let (sender, receiver) = std_mpsc::sync_channel(capacity);
+        let dropped_frames = Arc::new(AtomicUsize::new(0));
+
+        self.command(PlayerCommand::AddPcmTap(PcmTapSender {
+            sender,
+            dropped_frames: dropped_frames.clone(),
+        }));
+
+        PcmTap {
+            receiver,
+            dropped_frames,
+        }
+    }
+
     pub async fn await_end_of_track(&self) {
         let mut channel = self.get_player_event_channel();
         while let Some(event) = channel.recv().await {
@@ -589,6 +888,18 @@ impl Player {
         self.command(PlayerCommand::SetAutoNormaliseAsAlbum(setting));
     }
 
+    /// Changes the playback speed without affecting the pitch, from 0.5 to 3.0.
+    /// Reported positions stay in media time.
+    pub fn set_playback_speed(&self, speed: f64) {
+        self.command(PlayerCommand::SetPlaybackSpeed(speed));
+    }
+
+    /// Ramps the volume down over the final minute before `end`, reaching silence at `end`.
+    /// `None` restores the volume.
+    pub fn set_sleep_fade(&self, end: Option<Instant>) {
+        self.command(PlayerCommand::SetSleepFade(end));
+    }
+
     pub fn emit_filter_explicit_content_changed_event(&self, filter: bool) {
         self.command(PlayerCommand::EmitFilterExplicitContentChangedEvent(filter));
     }
@@ -633,6 +944,18 @@ impl Player {
     pub fn emit_auto_play_changed_event(&self, auto_play: bool) {
         self.command(PlayerCommand::EmitAutoPlayChangedEvent(auto_play));
     }
+
+    pub fn emit_sleep_timer_armed_event(&self, timer: SleepTimer) {
+        self.command(PlayerCommand::EmitSleepTimerArmedEvent(timer));
+    }
+
+    pub fn emit_sleep_timer_cancelled_event(&self) {
+        self.command(PlayerCommand::EmitSleepTimerCancelledEvent);
+    }
+
+    pub fn emit_sleep_timer_fired_event(&self, timer: SleepTimer) {
+        self.command(PlayerCommand::EmitSleepTimerFiredEvent(timer));
+    }
 }
 
 impl Drop for Player {
@@ -653,6 +976,7 @@ struct PlayerLoadedTrackData {
     stream_loader_controller: StreamLoaderController,
     audio_item: AudioItem,
     bytes_per_second: usize,
+    format: AudioFileFormat,
     duration_ms: u32,
     stream_position_ms: u32,
     is_explicit: bool,
@@ -672,6 +996,136 @@ enum PlayerPreload {
 
 type Decoder = Box<dyn AudioDecoder + Send>;
 
+// The tail of the previous track that is still being mixed into the current one.
+struct PlayerCrossfade {
+    decoder: Decoder,
+    normalisation_factor: f64,
+    buffer: VecDeque<f64>,
+    position: usize,
+    length: usize,
+    exhausted: bool,
+}
+
+impl PlayerCrossfade {
+    fn new(decoder: Decoder, normalisation_factor: f64, duration: Duration) -> Self {
+        let length = (duration.as_secs_f64() * SAMPLES_PER_SECOND as f64) as usize;
+
+        Self {
+            decoder,
+            normalisation_factor,
+            buffer: VecDeque::new(),
+            position: 0,
+            length: length.max(1),
+            exhausted: false,
+        }
+    }
+
+    // Mixes the tail into `samples` and returns whether the crossfade has completed.
+    fn mix(
+        &mut self,
+        samples: &mut [f64],
+        normalisation_factor: f64,
+        curve: CrossfadeCurve,
+    ) -> bool {
+        while self.buffer.len() < samples.len() && !self.exhausted {
+            match self.decoder.next_packet() {
+                Ok(Some((_, AudioPacket::Samples(tail)))) => self.buffer.extend(tail),
+                Ok(Some((_, AudioPacket::Raw(_)))) => (),
+                Ok(None) => self.exhausted = true,
+                Err(e) => {
+                    debug!("Ending crossfade early, unable to get next packet: {e}");
+                    self.exhausted = true;
+                }
+            }
+        }
+
+        // The tail keeps its own normalisation, as the normalisation factor of the current
+        // track will be applied to the mixed samples later on.
+        let tail_gain = self.normalisation_factor / normalisation_factor;
+
+        for sample in samples.iter_mut() {
+            let progress = f64::min(self.position as f64 / self.length as f64, 1.0);
+            let (fade_in, fade_out) = match curve {
+                CrossfadeCurve::Linear => (progress, 1.0 - progress),
+                CrossfadeCurve::EqualPower => {
+                    let angle = progress * std::f64::consts::FRAC_PI_2;
+                    (angle.sin(), angle.cos())
+                }
+            };
+
+            let tail = self.buffer.pop_front().unwrap_or_default();
+            *sample = *sample * fade_in + tail * fade_out * tail_gain;
+            self.position += 1;
+        }
+
+        self.position >= self.length
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FadeDirection {
+    In,
+    Out,
+}
+
+// A short ramp around discontinuities in the output, like pausing or seeking.
+#[derive(Clone, Copy, Debug)]
+struct PlayerFade {
+    direction: FadeDirection,
+    frame: usize,
+    frames: usize,
+}
+
+impl PlayerFade {
+    fn new(direction: FadeDirection, duration: Duration, sample_rate: u32) -> Self {
+        let frames = (duration.as_secs_f64() * sample_rate as f64) as usize;
+
+        Self {
+            direction,
+            frame: 0,
+            frames: frames.max(1),
+        }
+    }
+
+    // The fade-in that follows this fade-out.
+    fn reversed(&self) -> Self {
+        Self {
+            direction: FadeDirection::In,
+            frame: 0,
+            frames: self.frames,
+        }
+    }
+
+    // Applies the ramp to `samples` and returns whether the fade has completed. Anything
+    // after the end of a fade-out is cut off, as it would play at full volume again.
+    fn apply(&mut self, samples: &mut Vec<f64>) -> bool {
+        let channels = NUM_CHANNELS as usize;
+        let mut end = samples.len();
+
+        for (i, frame) in samples.chunks_exact_mut(channels).enumerate() {
+            if self.frame >= self.frames {
+                if self.direction == FadeDirection::Out {
+                    end = i * channels;
+                }
+                break;
+            }
+
+            // Raised cosine, which starts and ends without a kink.
+            let angle = std::f64::consts::PI * self.frame as f64 / self.frames as f64;
+            let gain = match self.direction {
+                FadeDirection::In => 0.5 - 0.5 * angle.cos(),
+                FadeDirection::Out => 0.5 + 0.5 * angle.cos(),
+            };
+
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+            self.frame += 1;
+        }
+
+        samples.truncate(end);
+        self.frame >= self.frames
+    }
+}
+
 enum PlayerState {
     Stopped,
     Loading {
@@ -689,6 +1143,7 @@ enum PlayerState {
         normalisation_factor: f64,
         stream_loader_controller: StreamLoaderController,
         bytes_per_second: usize,
+        format: AudioFileFormat,
         duration_ms: u32,
         stream_position_ms: u32,
         suggested_to_preload_next_track: bool,
@@ -703,6 +1158,7 @@ enum PlayerState {
         normalisation_factor: f64,
         stream_loader_controller: StreamLoaderController,
         bytes_per_second: usize,
+        format: AudioFileFormat,
         duration_ms: u32,
         stream_position_ms: u32,
         reported_nominal_start_time: Option<Instant>,
@@ -769,6 +1225,7 @@ impl PlayerState {
                 decoder,
                 duration_ms,
                 bytes_per_second,
+                format,
                 normalisation_data,
                 stream_loader_controller,
                 stream_position_ms,
@@ -785,6 +1242,7 @@ impl PlayerState {
                         stream_loader_controller,
                         audio_item,
                         bytes_per_second,
+                        format,
                         duration_ms,
                         stream_position_ms,
                         is_explicit,
@@ -798,7 +1256,7 @@ impl PlayerState {
         }
     }
 
-    fn paused_to_playing(&mut self) {
+    fn paused_to_playing(&mut self, playback_speed: f64) {
         use self::PlayerState::*;
         let new_state = mem::replace(self, Invalid);
         match new_state {
@@ -812,6 +1270,7 @@ impl PlayerState {
                 stream_loader_controller,
                 duration_ms,
                 bytes_per_second,
+                format,
                 stream_position_ms,
                 suggested_to_preload_next_track,
                 is_explicit,
@@ -826,9 +1285,12 @@ impl PlayerState {
                     stream_loader_controller,
                     duration_ms,
                     bytes_per_second,
+                    format,
                     stream_position_ms,
-                    reported_nominal_start_time: Instant::now()
-                        .checked_sub(Duration::from_millis(stream_position_ms as u64)),
+                    reported_nominal_start_time: nominal_start_time(
+                        Duration::from_millis(stream_position_ms as u64),
+                        playback_speed,
+                    ),
                     suggested_to_preload_next_track,
                     is_explicit,
                 };
@@ -854,6 +1316,7 @@ impl PlayerState {
                 stream_loader_controller,
                 duration_ms,
                 bytes_per_second,
+                format,
                 stream_position_ms,
                 suggested_to_preload_next_track,
                 is_explicit,
@@ -869,6 +1332,7 @@ impl PlayerState {
                     stream_loader_controller,
                     duration_ms,
                     bytes_per_second,
+                    format,
                     stream_position_ms,
                     suggested_to_preload_next_track,
                     is_explicit,
@@ -885,6 +1349,7 @@ impl PlayerState {
 struct PlayerTrackLoader {
     session: Session,
     config: PlayerConfig,
+    local_files: Option<Arc<LocalFiles>>,
 }
 
 impl PlayerTrackLoader {
@@ -933,7 +1398,7 @@ impl PlayerTrackLoader {
             AudioFileFormat::XHE_AAC_12 => 1.5,
             AudioFileFormat::XHE_AAC_16 => 2.,
             AudioFileFormat::XHE_AAC_24 => 3.,
-            AudioFileFormat::FLAC_FLAC_24BIT => 3.,
+            AudioFileFormat::FLAC_FLAC_24BIT => 175., // assume 1400 kbit/s on average
         };
         let data_rate: f32 = kbps * 1024.;
         Some(data_rate.ceil() as usize)
@@ -945,9 +1410,21 @@ impl PlayerTrackLoader {
         position_ms: u32,
     ) -> Option<PlayerLoadedTrackData> {
         match track_uri {
-            SpotifyUri::Track { .. } | SpotifyUri::Episode { .. } => {
-                self.load_remote_track(track_uri, position_ms).await
-            }
+            SpotifyUri::Track { .. } | SpotifyUri::Episode { .. } => match self.config.preview_mode
+            {
+                PreviewMode::Disabled => self.load_remote_track(track_uri, position_ms).await,
+                PreviewMode::Fallback => {
+                    match self.load_remote_track(track_uri.clone(), position_ms).await {
+                        Some(data) => Some(data),
+                        None => {
+                            warn!("<{track_uri}> failed to load, falling back to its preview");
+                            self.load_preview_track(track_uri, position_ms).await
+                        }
+                    }
+                }
+                PreviewMode::Always => self.load_preview_track(track_uri, position_ms).await,
+            },
+            SpotifyUri::Local { .. } => self.load_local_track(track_uri, position_ms),
             _ => {
                 error!("Cannot handle load of track with URI: <{track_uri}>",);
                 None
@@ -955,6 +1432,198 @@ impl PlayerTrackLoader {
         }
     }
 
+    async fn load_preview_track(
+        &self,
+        track_uri: SpotifyUri,
+        position_ms: u32,
+    ) -> Option<PlayerLoadedTrackData> {
+        let mut audio_item = match AudioItem::get_file(&self.session, track_uri).await {
+            Ok(audio_item) => audio_item,
+            Err(e) => {
+                error!("Unable to load audio item: {e:?}");
+                return None;
+            }
+        };
+
+        // Previews are always 96 kbps MP3.
+        let format = AudioFileFormat::MP3_96;
+        let Some(&file_id) = audio_item.previews.get(&format) else {
+            warn!("<{}> has no preview", audio_item.name);
+            return None;
+        };
+
+        info!(
+            "Loading preview of <{}> with Spotify URI <{}>",
+            audio_item.name, audio_item.uri
+        );
+
+        // Previews are small and unencrypted, so fetch them at once.
+        let data = match self.session.spclient().get_audio_preview(&file_id).await {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Unable to load preview: {e}");
+                return None;
+            }
+        };
+
+        let stream_loader_controller = StreamLoaderController::from_buffer(&data);
+        let bytes_per_second = self.stream_data_rate(format)?;
+        let file_size = data.len();
+
+        let mut decoder = match SymphoniaDecoder::new(io::Cursor::new(data), format) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                error!("Unable to decode preview: {e}");
+                return None;
+            }
+        };
+
+        let normalisation_data = decoder.normalisation_data().unwrap_or_default();
+
+        // MP3 files don't always tell their duration, so calculate it from the size.
+        let duration_ms = decoder
+            .duration_ms()
+            .unwrap_or((file_size as u64 * 8 / 96) as u32)
+            .min(audio_item.duration_ms);
+        audio_item.duration_ms = duration_ms;
+
+        let mut decoder = Box::new(decoder) as Decoder;
+
+        let position_ms = if position_ms > duration_ms {
+            warn!(
+                "Invalid start position of {position_ms} ms exceeds preview's duration of {duration_ms} ms, starting preview from the beginning"
+            );
+            0
+        } else {
+            position_ms
+        };
+
+        let stream_position_ms = match decoder.seek(position_ms) {
+            Ok(new_position_ms) => new_position_ms,
+            Err(e) => {
+                error!(
+                    "PlayerTrackLoader::load_preview_track error seeking to starting position {position_ms}: {e}"
+                );
+                return None;
+            }
+        };
+
+        let is_explicit = audio_item.is_explicit;
+
+        info!("<{}> ({} ms) preview loaded", audio_item.name, duration_ms);
+
+        Some(PlayerLoadedTrackData {
+            decoder,
+            normalisation_data,
+            stream_loader_controller,
+            audio_item,
+            bytes_per_second,
+            format,
+            duration_ms,
+            stream_position_ms,
+            is_explicit,
+        })
+    }
+
+    fn load_local_track(
+        &self,
+        track_uri: SpotifyUri,
+        position_ms: u32,
+    ) -> Option<PlayerLoadedTrackData> {
+        let SpotifyUri::Local {
+            ref artist,
+            ref album_title,
+            ref track_title,
+            duration,
+        } = track_uri
+        else {
+            return None;
+        };
+
+        let Some(ref local_files) = self.local_files else {
+            warn!("<{track_uri}> is a local file, but no local files directory is configured");
+            return None;
+        };
+
+        let (path, format) = match local_files.find(artist, album_title, track_title) {
+            Some(found) => found,
+            None => {
+                warn!("<{track_uri}> was not found in the local files directory");
+                return None;
+            }
+        };
+
+        info!("Loading <{track_title}> from {}", path.display());
+
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("Unable to open local file {}: {e}", path.display());
+                return None;
+            }
+        };
+
+        let stream_loader_controller = match StreamLoaderController::from_local_file(&file) {
+            Ok(controller) => controller,
+            Err(e) => {
+                error!("Unable to read local file {}: {e}", path.display());
+                return None;
+            }
+        };
+        let bytes_per_second = self.stream_data_rate(format)?;
+
+        let mut decoder = match SymphoniaDecoder::new(file, format) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                error!("Unable to decode local file {}: {e}", path.display());
+                return None;
+            }
+        };
+
+        let normalisation_data = decoder.normalisation_data().unwrap_or_else(|| {
+            warn!("Unable to get normalisation data, continuing with defaults.");
+            NormalisationData::default()
+        });
+        let mut decoder = Box::new(decoder) as Decoder;
+
+        // Local URIs only have the duration in seconds, which may be shorter than the file.
+        let duration_ms = duration.as_millis() as u32;
+        let position_ms = if position_ms > duration_ms {
+            warn!(
+                "Invalid start position of {position_ms} ms exceeds track's duration of {duration_ms} ms, starting track from the beginning"
+            );
+            0
+        } else {
+            position_ms
+        };
+
+        let stream_position_ms = match decoder.seek(position_ms) {
+            Ok(new_position_ms) => new_position_ms,
+            Err(e) => {
+                error!(
+                    "PlayerTrackLoader::load_local_track error seeking to starting position {position_ms}: {e}"
+                );
+                return None;
+            }
+        };
+
+        let audio_item = AudioItem::local(track_uri.clone()).ok()?;
+
+        info!("<{}> ({} ms) loaded", audio_item.name, duration_ms);
+
+        Some(PlayerLoadedTrackData {
+            decoder,
+            normalisation_data,
+            stream_loader_controller,
+            audio_item,
+            bytes_per_second,
+            format,
+            duration_ms,
+            stream_position_ms,
+            is_explicit: false,
+        })
+    }
+
     async fn load_remote_track(
         &self,
         track_uri: SpotifyUri,
@@ -991,8 +1660,8 @@ impl PlayerTrackLoader {
         );
 
         // (Most) podcasts seem to support only 96 kbps Ogg Vorbis, so fall back to it
-        let formats = match self.config.bitrate {
-            Bitrate::Bitrate96 => [
+        let formats: &[AudioFileFormat] = match self.config.bitrate {
+            Bitrate::Bitrate96 => &[
                 AudioFileFormat::OGG_VORBIS_96,
                 AudioFileFormat::MP3_96,
                 AudioFileFormat::OGG_VORBIS_160,
@@ -1001,7 +1670,7 @@ impl PlayerTrackLoader {
                 AudioFileFormat::OGG_VORBIS_320,
                 AudioFileFormat::MP3_320,
             ],
-            Bitrate::Bitrate160 => [
+            Bitrate::Bitrate160 => &[
                 AudioFileFormat::OGG_VORBIS_160,
                 AudioFileFormat::MP3_160,
                 AudioFileFormat::OGG_VORBIS_96,
@@ -1010,7 +1679,24 @@ impl PlayerTrackLoader {
                 AudioFileFormat::OGG_VORBIS_320,
                 AudioFileFormat::MP3_320,
             ],
-            Bitrate::Bitrate320 => [
+            Bitrate::Bitrate320 => &[
+                AudioFileFormat::OGG_VORBIS_320,
+                AudioFileFormat::MP3_320,
+                AudioFileFormat::MP3_256,
+                AudioFileFormat::OGG_VORBIS_160,
+                AudioFileFormat::MP3_160,
+                AudioFileFormat::OGG_VORBIS_96,
+                AudioFileFormat::MP3_96,
+            ],
+            // FLAC can't be passed through, so only offer it to the decoder
+            Bitrate::Lossless if self.config.passthrough => &[
+                AudioFileFormat::OGG_VORBIS_320,
+                AudioFileFormat::OGG_VORBIS_160,
+                AudioFileFormat::OGG_VORBIS_96,
+            ],
+            Bitrate::Lossless => &[
+                AudioFileFormat::FLAC_FLAC_24BIT,
+                AudioFileFormat::FLAC_FLAC,
                 AudioFileFormat::OGG_VORBIS_320,
                 AudioFileFormat::MP3_320,
                 AudioFileFormat::MP3_256,
@@ -1185,6 +1871,7 @@ impl PlayerTrackLoader {
                 stream_loader_controller,
                 audio_item,
                 bytes_per_second,
+                format,
                 duration_ms,
                 stream_position_ms,
                 is_explicit,
@@ -1193,11 +1880,37 @@ impl PlayerTrackLoader {
     }
 }
 
-impl Future for PlayerInternal {
-    type Output = ();
+// The sink, with the statistics of its output buffer if there is one.
+type BuiltSink = (Box<dyn Sink>, Option<Arc<BufferedSinkStats>>);
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
-        // While this is written as a future, it still contains blocking code.
+// Plays through a `BufferedSink` when an output buffer is configured, and to the tee outputs
+// as well. Used at startup and when switching the sink.
+fn build_sink<F>(config: &PlayerConfig, sample_rate: u32, sink_builder: F) -> SinkResult<BuiltSink>
+where
+    F: FnOnce() -> SinkResult<Box<dyn Sink>> + Send + 'static,
+{
+    let (sink, output_buffer_stats) = match config.output_buffer {
+        Some(size) if !config.passthrough => {
+            let sink = BufferedSink::try_new(sink_builder, config.ditherer, sample_rate, size)?;
+            let stats = sink.stats();
+            (Box::new(sink) as Box<dyn Sink>, Some(stats))
+        }
+        _ => (sink_builder()?, None),
+    };
+
+    if config.tee.is_empty() {
+        return Ok((sink, output_buffer_stats));
+    }
+
+    let sink = Box::new(TeeSink::new(sink, &config.tee, sample_rate));
+    Ok((sink, output_buffer_stats))
+}
+
+impl Future for PlayerInternal {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // While this is written as a future, it still contains blocking code.
         // It must be run on its own thread.
         let passthrough = self.config.passthrough;
 
@@ -1220,6 +1933,13 @@ impl Future for PlayerInternal {
                 }
             }
 
+            if let Some(recovery) = self.sink_recovery.as_mut() {
+                if recovery.retry.as_mut().poll(cx).is_ready() {
+                    all_futures_completed_or_not_ready = false;
+                    self.retry_sink();
+                }
+            }
+
             // Handle loading of a new track to play
             if let PlayerState::Loading {
                 ref mut loader,
@@ -1300,7 +2020,10 @@ impl Future for PlayerInternal {
 
             if self.state.is_playing() {
                 self.ensure_sink_running();
+                self.send_output_buffer_event();
 
+                let playback_speed = self.playback_speed();
+                let sink_delay = self.sink_delay();
                 if let PlayerState::Playing {
                     ref track_id,
                     play_request_id,
@@ -1328,11 +2051,22 @@ impl Future for PlayerInternal {
                                                 new_stream_position_ms as u64,
                                             );
 
+                                            // What is heard lags behind what is decoded by the delay
+                                            // of the sink, if it can tell us.
+                                            let audible_position = match sink_delay {
+                                                Some(delay) => new_stream_position
+                                                    .saturating_sub(delay.mul_f64(playback_speed)),
+                                                None => new_stream_position,
+                                            };
+                                            let audible_position_ms =
+                                                audible_position.as_millis() as u32;
+
                                             let now = Instant::now();
 
                                             // Only notify if we're skipped some packets *or* we are behind.
-                                            // If we're ahead it's probably due to a buffer of the backend
-                                            // and we're actually in time.
+                                            // If we're ahead and the sink can't tell its delay, it's
+                                            // probably due to a buffer of the backend and we're actually
+                                            // in time. With a known delay we can be more strict.
                                             let notify_about_position =
                                                 match *reported_nominal_start_time {
                                                     None => true,
@@ -1354,12 +2088,28 @@ impl Future for PlayerInternal {
                                                             .checked_duration_since(
                                                                 reported_nominal_start_time,
                                                             )
+                                                            .map(|elapsed| {
+                                                                elapsed.mul_f64(playback_speed)
+                                                            })
                                                         {
-                                                            if let Some(lag) =
-                                                                lag.checked_sub(new_stream_position)
-                                                            {
-                                                                notify |=
-                                                                    lag >= Duration::from_secs(1)
+                                                            match sink_delay {
+                                                                Some(_) => {
+                                                                    notify |= lag
+                                                                        .abs_diff(audible_position)
+                                                                        >= SYNCED_POSITION_TOLERANCE
+                                                                }
+                                                                None => {
+                                                                    if let Some(lag) = lag
+                                                                        .checked_sub(
+                                                                            audible_position,
+                                                                        )
+                                                                    {
+                                                                        notify |= lag
+                                                                            >= Duration::from_secs(
+                                                                                1,
+                                                                            )
+                                                                    }
+                                                                }
                                                             }
                                                         }
 
@@ -1368,12 +2118,13 @@ impl Future for PlayerInternal {
                                                 };
 
                                             if notify_about_position {
-                                                *reported_nominal_start_time =
-                                                    now.checked_sub(new_stream_position);
+                                                *reported_nominal_start_time = now.checked_sub(
+                                                    audible_position.div_f64(playback_speed),
+                                                );
                                                 self.send_event(PlayerEvent::PositionCorrection {
                                                     play_request_id,
                                                     track_id: track_id.clone(),
-                                                    position_ms: new_stream_position_ms,
+                                                    position_ms: audible_position_ms,
                                                 });
                                             }
 
@@ -1388,7 +2139,7 @@ impl Future for PlayerInternal {
                                                     self.send_event(PlayerEvent::PositionChanged {
                                                         play_request_id,
                                                         track_id,
-                                                        position_ms: new_stream_position_ms,
+                                                        position_ms: audible_position_ms,
                                                     });
                                                 }
                                             }
@@ -1458,6 +2209,38 @@ impl Future for PlayerInternal {
                 }
             }
 
+            // Ask for the next track ahead of time, so that it can be mixed into the end of
+            // the current one. This is only done once the next track is ready to be played,
+            // and not without gapless playback, which would stop the sink in between.
+            if let Some(crossfade) = self.config.crossfade.filter(|_| self.config.gapless) {
+                if let PlayerState::Playing {
+                    ref track_id,
+                    play_request_id,
+                    duration_ms,
+                    stream_position_ms,
+                    ..
+                } = self.state
+                {
+                    if !passthrough
+                        && self.crossfade_play_request_id != Some(play_request_id)
+                        && matches!(
+                            self.preload,
+                            PlayerPreload::Ready { track_id: ref next_track_id, .. } if next_track_id != track_id
+                        )
+                        && (duration_ms as i64 - stream_position_ms as i64)
+                            <= crossfade.as_millis() as i64
+                    {
+                        let track_id = track_id.clone();
+
+                        self.crossfade_play_request_id = Some(play_request_id);
+                        self.send_event(PlayerEvent::EndOfTrack {
+                            track_id,
+                            play_request_id,
+                        });
+                    }
+                }
+            }
+
             if (!self.state.is_playing()) && all_futures_completed_or_not_ready {
                 return Poll::Pending;
             }
@@ -1473,11 +2256,12 @@ impl PlayerInternal {
                 callback(SinkStatus::Running);
             }
             match self.sink.start() {
-                Ok(()) => self.sink_status = SinkStatus::Running,
-                Err(e) => {
-                    error!("{e}");
-                    self.handle_pause();
+                Ok(()) => {
+                    self.sink_status = SinkStatus::Running;
+                    self.sink_recovery = None;
+                    self.sink_delay = None;
                 }
+                Err(e) => self.handle_sink_error(e),
             }
         }
     }
@@ -1485,6 +2269,11 @@ impl PlayerInternal {
     fn ensure_sink_stopped(&mut self, temporarily: bool) {
         match self.sink_status {
             SinkStatus::Running => {
+                // The rest of the last track is still played, anything else is dropped.
+                if !matches!(self.state, PlayerState::EndOfTrack { .. }) {
+                    self.discard_sink();
+                }
+
                 trace!("== Stopping sink ==");
                 match self.sink.stop() {
                     Ok(()) => {
@@ -1498,8 +2287,15 @@ impl PlayerInternal {
                         }
                     }
                     Err(e) => {
+                        // Stopping was the intent anyway, the next start opens the sink again.
                         error!("{e}");
-                        exit(1);
+                        self.sink_status = SinkStatus::Closed;
+                        if let Some(callback) = &mut self.sink_event_callback {
+                            callback(SinkStatus::Closed);
+                        }
+                        self.send_event(PlayerEvent::SinkError {
+                            error: e.to_string(),
+                        });
                     }
                 }
             }
@@ -1515,7 +2311,15 @@ impl PlayerInternal {
         }
     }
 
+    // Drops what the sink has not played yet, before playback jumps elsewhere.
+    fn discard_sink(&mut self) {
+        self.sink.discard();
+        self.sink_delay = None;
+    }
+
     fn handle_player_stop(&mut self) {
+        self.fade_out();
+
         match self.state {
             PlayerState::Playing {
                 ref track_id,
@@ -1539,6 +2343,14 @@ impl PlayerInternal {
             } => {
                 let track_id = track_id.clone();
 
+                self.crossfade = None;
+                self.silent_samples = 0;
+                self.trimmed_samples = 0;
+                self.filters.reset();
+                if let Some(ref mut resampler) = self.resampler {
+                    resampler.reset();
+                }
+                self.reset_time_stretcher();
                 self.ensure_sink_stopped(false);
                 self.send_event(PlayerEvent::Stopped {
                     track_id,
@@ -1564,7 +2376,8 @@ impl PlayerInternal {
             } => {
                 let track_id = track_id.clone();
 
-                self.state.paused_to_playing();
+                self.fade_in();
+                self.state.paused_to_playing(self.playback_speed());
                 self.send_event(PlayerEvent::Playing {
                     track_id,
                     play_request_id,
@@ -1583,6 +2396,8 @@ impl PlayerInternal {
     }
 
     fn handle_pause(&mut self) {
+        self.fade_out();
+
         match self.state {
             PlayerState::Paused { .. } => self.ensure_sink_stopped(false),
             PlayerState::Playing {
@@ -1612,6 +2427,192 @@ impl PlayerInternal {
         }
     }
 
+    fn handle_sink_error(&mut self, e: SinkError) {
+        error!("{e}");
+
+        // Release what is left of the device, so that starting the sink opens it again.
+        if self.sink_status == SinkStatus::Running {
+            if let Err(e) = self.sink.stop() {
+                debug!("Unable to stop the failed sink: {e}");
+            }
+        }
+        self.sink_status = SinkStatus::Closed;
+        if let Some(callback) = &mut self.sink_event_callback {
+            callback(SinkStatus::Closed);
+        }
+        self.fade = None;
+
+        let play_request_id = match self.state {
+            PlayerState::Playing {
+                play_request_id, ..
+            }
+            | PlayerState::Paused {
+                play_request_id, ..
+            } => Some(play_request_id),
+            _ => None,
+        };
+
+        self.handle_pause();
+        self.send_event(PlayerEvent::SinkError {
+            error: e.to_string(),
+        });
+
+        let attempts = self
+            .sink_recovery
+            .take()
+            .map_or(0, |recovery| recovery.attempts);
+        self.sink_recovery = play_request_id.map(|play_request_id| {
+            let delay = SINK_RETRY_INITIAL_DELAY
+                .saturating_mul(1 << attempts.min(u32::BITS - 1))
+                .min(SINK_RETRY_MAX_DELAY);
+            info!("Retrying to start the audio sink in {} s", delay.as_secs());
+
+            SinkRecovery {
+                play_request_id,
+                attempts: attempts + 1,
+                retry: Box::pin(tokio::time::sleep(delay)),
+            }
+        });
+    }
+
+    fn retry_sink(&mut self) {
+        let Some(recovery) = self.sink_recovery.take() else {
+            return;
+        };
+
+        // Only resume what was paused by the sink error.
+        match self.state {
+            PlayerState::Paused {
+                play_request_id, ..
+            } if play_request_id == recovery.play_request_id => (),
+            _ => return,
+        }
+
+        trace!("== Retrying sink ==");
+        if let Some(callback) = &mut self.sink_event_callback {
+            callback(SinkStatus::Running);
+        }
+        match self.sink.start() {
+            Ok(()) => {
+                info!(
+                    "Audio sink recovered after {} attempt(s), resuming playback",
+                    recovery.attempts
+                );
+                self.sink_status = SinkStatus::Running;
+                self.sink_delay = None;
+                self.handle_play();
+            }
+            Err(e) => {
+                self.sink_recovery = Some(recovery);
+                self.handle_sink_error(e);
+            }
+        }
+    }
+
+    fn handle_set_sink(
+        &mut self,
+        backend: String,
+        device: Option<String>,
+        format: AudioFormat,
+    ) -> PlayerResult {
+        info!(
+            "Switching audio sink to device {} with format {format:?}",
+            device.as_deref().unwrap_or("default")
+        );
+
+        // The fade in after the fade out happens on the new sink. The old sink is stopped
+        // first, as the new one may well be on the same device. What it had not played yet
+        // is dropped, so playback goes back to the position that was heard.
+        self.fade_out();
+        let discarded = match self.state {
+            PlayerState::Playing {
+                stream_position_ms, ..
+            } if self.sink_status == SinkStatus::Running => {
+                self.sink.delay().map(|delay| (stream_position_ms, delay))
+            }
+            _ => None,
+        };
+        self.ensure_sink_stopped(false);
+
+        let sample_rate = self.output_sample_rate();
+        let opened = build_sink(&self.config, sample_rate, move || {
+            audio_backend::open_sink(&backend, device, format, sample_rate)
+        });
+
+        match opened {
+            Ok((sink, output_buffer_stats)) => {
+                self.sink = sink;
+                self.output_buffer_stats = output_buffer_stats;
+                self.reported_xruns = (0, 0);
+                self.sink_delay = None;
+            }
+            Err(e) => {
+                // The old sink is started again as usual, when playing.
+                error!("Unable to switch the audio sink, keeping the current one: {e}");
+                self.send_event(PlayerEvent::SinkError {
+                    error: e.to_string(),
+                });
+            }
+        }
+
+        if let Some((position_ms, delay)) = discarded {
+            let delay_ms = (delay.as_secs_f64() * self.playback_speed() * 1000.0) as u32;
+            self.handle_command_seek(position_ms.saturating_sub(delay_ms))?;
+        }
+
+        // The new sink is started while playing, or right away when the old one had failed.
+        if self.sink_recovery.is_some() {
+            self.retry_sink();
+        }
+
+        Ok(())
+    }
+
+    fn send_output_buffer_event(&mut self) {
+        let Some(stats) = &self.output_buffer_stats else {
+            return;
+        };
+
+        let xruns = (stats.underruns(), stats.overruns());
+        if xruns != self.reported_xruns {
+            self.reported_xruns = xruns;
+            self.send_event(PlayerEvent::OutputBufferXrun {
+                underruns: xruns.0,
+                overruns: xruns.1,
+            });
+        }
+    }
+
+    fn send_levels_event(&mut self, levels: Levels) {
+        if let PlayerState::Playing {
+            ref track_id,
+            play_request_id,
+            ..
+        } = self.state
+        {
+            let track_id = track_id.clone();
+            self.send_event(PlayerEvent::Levels {
+                play_request_id,
+                track_id,
+                levels,
+            });
+        }
+    }
+
+    fn send_to_pcm_taps(&mut self, samples: &[f64]) {
+        // Never block the audio thread on a slow consumer, and forget the ones that are gone.
+        self.pcm_taps
+            .retain(|tap| match tap.sender.try_send(samples.to_vec()) {
+                Ok(()) => true,
+                Err(std_mpsc::TrySendError::Full(_)) => {
+                    tap.dropped_frames
+                        .fetch_add(samples.len() / NUM_CHANNELS as usize, Ordering::Relaxed);
+                    true
+                }
+                Err(std_mpsc::TrySendError::Disconnected(_)) => false,
+            });
+    }
+
     fn handle_packet(
         &mut self,
         packet: Option<(AudioPacketPosition, AudioPacket)>,
@@ -1621,9 +2622,29 @@ impl PlayerInternal {
             Some((_, mut packet)) => {
                 if !packet.is_empty() {
                     if let AudioPacket::Samples(ref mut data) = packet {
+                        if self.config.silence_trim
+                            && self.crossfade.is_none()
+                            && self.trim_silence(data)
+                        {
+                            return;
+                        }
+
+                        if let Some(ref mut crossfade) = self.crossfade {
+                            if crossfade.mix(
+                                data,
+                                normalisation_factor,
+                                self.config.crossfade_curve,
+                            ) {
+                                self.crossfade = None;
+                            }
+                        }
+
+                        self.filters.process(data);
+
                         // Get the volume for the packet. In the case of hardware volume control
                         // this will always be 1.0 (no change).
-                        let volume = self.volume_getter.attenuation_factor();
+                        let volume =
+                            self.volume_getter.attenuation_factor() * self.sleep_fade_factor();
 
                         // For the basic normalisation method, a normalisation factor of 1.0
                         // indicates that there is nothing to normalise (all samples should pass
@@ -1711,16 +2732,43 @@ impl PlayerInternal {
                                 }
                             }
                         }
+
+                        if let Some(ref mut time_stretcher) = self.time_stretcher {
+                            *data = time_stretcher.process(data);
+                        }
+
+                        if let Some(ref mut resampler) = self.resampler {
+                            *data = resampler.process(data);
+                        }
+
+                        if let Some(ref mut fade) = self.fade {
+                            if fade.apply(data) {
+                                self.fade = match fade.direction {
+                                    FadeDirection::Out => Some(fade.reversed()),
+                                    FadeDirection::In => None,
+                                };
+                            }
+                        }
+
+                        self.send_to_pcm_taps(data);
+
+                        if let Some(levels) = self
+                            .level_meter
+                            .as_mut()
+                            .and_then(|meter| meter.process(data))
+                        {
+                            self.send_levels_event(levels);
+                        }
                     }
 
                     if let Err(e) = self.sink.write(packet, &mut self.converter) {
-                        error!("{e}");
-                        self.handle_pause();
+                        self.handle_sink_error(e);
                     }
                 }
             }
 
             None => {
+                self.finish_silence_trim();
                 self.state.playing_to_end_of_track();
                 if let PlayerState::EndOfTrack {
                     ref track_id,
@@ -1728,10 +2776,13 @@ impl PlayerInternal {
                     ..
                 } = self.state
                 {
-                    self.send_event(PlayerEvent::EndOfTrack {
-                        track_id: track_id.clone(),
-                        play_request_id,
-                    })
+                    // Already sent when starting to crossfade into the next track.
+                    if self.crossfade_play_request_id != Some(play_request_id) {
+                        self.send_event(PlayerEvent::EndOfTrack {
+                            track_id: track_id.clone(),
+                            play_request_id,
+                        })
+                    }
                 } else {
                     error!("PlayerInternal handle_packet: Invalid PlayerState");
                     exit(1);
@@ -1740,6 +2791,281 @@ impl PlayerInternal {
         }
     }
 
+    fn status(&mut self) -> PlayerStatus {
+        let mut status = PlayerStatus {
+            state: PlaybackState::Stopped,
+            track_id: None,
+            play_request_id: None,
+            position_ms: 0,
+            duration_ms: None,
+            normalisation_factor: None,
+            format: None,
+        };
+
+        match self.state {
+            PlayerState::Stopped | PlayerState::Invalid => (),
+            PlayerState::Loading {
+                ref track_id,
+                play_request_id,
+                ..
+            } => {
+                status.state = PlaybackState::Loading;
+                status.track_id = Some(track_id.clone());
+                status.play_request_id = Some(play_request_id);
+            }
+            PlayerState::Playing {
+                ref track_id,
+                play_request_id,
+                stream_position_ms,
+                duration_ms,
+                normalisation_factor,
+                format,
+                ..
+            }
+            | PlayerState::Paused {
+                ref track_id,
+                play_request_id,
+                stream_position_ms,
+                duration_ms,
+                normalisation_factor,
+                format,
+                ..
+            } => {
+                status.state = if self.state.is_playing() {
+                    PlaybackState::Playing
+                } else {
+                    PlaybackState::Paused
+                };
+                status.track_id = Some(track_id.clone());
+                status.play_request_id = Some(play_request_id);
+                status.position_ms = stream_position_ms;
+                if self.state.is_playing() {
+                    if let Some(delay) = self.sink_delay() {
+                        let delay = delay.mul_f64(self.playback_speed());
+                        status.position_ms =
+                            stream_position_ms.saturating_sub(delay.as_millis() as u32);
+                    }
+                }
+                status.duration_ms = Some(duration_ms);
+                status.normalisation_factor = Some(normalisation_factor);
+                status.format = Some(format);
+            }
+            PlayerState::EndOfTrack {
+                ref track_id,
+                play_request_id,
+                ref loaded_track,
+            } => {
+                status.state = PlaybackState::EndOfTrack;
+                status.track_id = Some(track_id.clone());
+                status.play_request_id = Some(play_request_id);
+                status.position_ms = loaded_track.duration_ms;
+                status.duration_ms = Some(loaded_track.duration_ms);
+                status.format = Some(loaded_track.format);
+            }
+        }
+
+        status
+    }
+
+    fn sleep_fade_factor(&self) -> f64 {
+        match self.sleep_fade {
+            Some(end) => {
+                let remaining = end.saturating_duration_since(Instant::now());
+                let ratio = f64::min(
+                    remaining.as_secs_f64() / SLEEP_FADE_DURATION.as_secs_f64(),
+                    1.0,
+                );
+
+                // Squared, so that the volume seems to decrease at an even pace.
+                ratio * ratio
+            }
+            None => 1.0,
+        }
+    }
+
+    fn fade_duration(&self) -> Option<Duration> {
+        if self.config.passthrough {
+            return None;
+        }
+
+        self.config
+            .fade_duration
+            .filter(|duration| !duration.is_zero())
+            .map(|duration| duration.min(MAX_FADE_DURATION))
+    }
+
+    fn sink_delay(&mut self) -> Option<Duration> {
+        let update_interval = self
+            .config
+            .position_update_interval
+            .unwrap_or(SINK_DELAY_UPDATE_INTERVAL);
+
+        match self.sink_delay {
+            Some((updated, delay)) if updated.elapsed() < update_interval => delay,
+            _ => {
+                let delay = self.sink.delay();
+                self.sink_delay = Some((Instant::now(), delay));
+                delay
+            }
+        }
+    }
+
+    fn output_sample_rate(&self) -> u32 {
+        self.resampler
+            .as_ref()
+            .map_or(SAMPLE_RATE, Resampler::output_rate)
+    }
+
+    fn fade_in(&mut self) {
+        if let Some(duration) = self.fade_duration() {
+            self.fade = Some(PlayerFade::new(
+                FadeDirection::In,
+                duration,
+                self.output_sample_rate(),
+            ));
+        }
+    }
+
+    // Plays a fade-out of the upcoming audio before a discontinuity, so that stopping the
+    // sink or jumping elsewhere does not produce a click. The next audio is faded in.
+    //
+    // This decodes and writes the fade before returning, so it blocks the calling command
+    // for about the fade duration. Skipped silence does not progress the fade, so the amount
+    // of audio decoded is limited as well.
+    fn fade_out(&mut self) {
+        let duration = match self.fade_duration() {
+            Some(duration) => duration,
+            None => return,
+        };
+
+        // A sink error while fading out pauses without another fade.
+        let is_fading_out = matches!(
+            self.fade,
+            Some(PlayerFade {
+                direction: FadeDirection::Out,
+                ..
+            })
+        );
+
+        if is_fading_out
+            || self.sink_status != SinkStatus::Running
+            || !matches!(self.state, PlayerState::Playing { .. })
+        {
+            return;
+        }
+
+        let fade = PlayerFade::new(FadeDirection::Out, duration, self.output_sample_rate());
+        self.fade = Some(fade);
+
+        let max_decoded_ms =
+            (duration.as_secs_f64() * VALID_PLAYBACK_SPEED_RANGE.end() * 1000.0) as u32;
+        let mut first_position_ms = None;
+
+        while matches!(
+            self.fade,
+            Some(PlayerFade {
+                direction: FadeDirection::Out,
+                ..
+            })
+        ) {
+            let (result, normalisation_factor) = if let PlayerState::Playing {
+                ref mut decoder,
+                normalisation_factor,
+                ..
+            } = self.state
+            {
+                (decoder.next_packet(), normalisation_factor)
+            } else {
+                break;
+            };
+
+            match result {
+                Ok(Some((packet_position, packet))) => {
+                    let position_ms = packet_position.position_ms;
+                    if let PlayerState::Playing {
+                        ref mut stream_position_ms,
+                        ..
+                    } = self.state
+                    {
+                        *stream_position_ms = position_ms;
+                    }
+
+                    self.handle_packet(Some((packet_position, packet)), normalisation_factor);
+
+                    let first_position_ms = *first_position_ms.get_or_insert(position_ms);
+                    if position_ms.saturating_sub(first_position_ms) > max_decoded_ms {
+                        break;
+                    }
+                }
+                // The end of the track or any error is handled by the next poll, as usual.
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        self.fade = Some(fade.reversed());
+    }
+
+    // Returns whether the packet is part of a silence that should be skipped.
+    fn trim_silence(&mut self, samples: &[f64]) -> bool {
+        let threshold = db_to_ratio(self.config.silence_trim_threshold_dbfs);
+
+        if samples.iter().all(|sample| sample.abs() <= threshold) {
+            let min_silent_samples = (self.config.silence_trim_min_duration.as_secs_f64()
+                * SAMPLES_PER_SECOND as f64) as usize;
+
+            self.silent_samples += samples.len();
+            if self.silent_samples > min_silent_samples {
+                self.trimmed_samples += samples.len();
+                return true;
+            }
+        } else {
+            self.silent_samples = 0;
+            self.finish_silence_trim();
+        }
+
+        false
+    }
+
+    fn finish_silence_trim(&mut self) {
+        if self.trimmed_samples == 0 {
+            return;
+        }
+
+        let trimmed_ms = (self.trimmed_samples as f64 * 1000.0 / SAMPLES_PER_SECOND as f64) as u32;
+        self.trimmed_samples = 0;
+
+        let playback_speed = self.playback_speed();
+        if let PlayerState::Playing {
+            ref track_id,
+            play_request_id,
+            stream_position_ms,
+            ref mut reported_nominal_start_time,
+            ..
+        } = self.state
+        {
+            let track_id = track_id.clone();
+            debug!("Trimmed {trimmed_ms} ms of silence from <{track_id}>");
+
+            // Skipping silence moved the position ahead of the wall clock.
+            *reported_nominal_start_time = nominal_start_time(
+                Duration::from_millis(stream_position_ms as u64),
+                playback_speed,
+            );
+
+            self.send_event(PlayerEvent::SilenceTrimmed {
+                play_request_id,
+                track_id: track_id.clone(),
+                position_ms: stream_position_ms,
+                trimmed_ms,
+            });
+            self.send_event(PlayerEvent::PositionCorrection {
+                play_request_id,
+                track_id,
+                position_ms: stream_position_ms,
+            });
+        }
+    }
+
     fn start_playback(
         &mut self,
         track_id: SpotifyUri,
@@ -1753,6 +3079,11 @@ impl PlayerInternal {
 
         let position_ms = loaded_track.stream_position_ms;
 
+        // Keep the filter state while crossfading, as the output is continuous.
+        if self.crossfade.is_none() {
+            self.filters.reset();
+        }
+
         let mut config = self.config.clone();
         if config.normalisation_type == NormalisationType::Auto {
             if self.auto_normalise_as_album {
@@ -1782,9 +3113,12 @@ impl PlayerInternal {
                 stream_loader_controller: loaded_track.stream_loader_controller,
                 duration_ms: loaded_track.duration_ms,
                 bytes_per_second: loaded_track.bytes_per_second,
+                format: loaded_track.format,
                 stream_position_ms: loaded_track.stream_position_ms,
-                reported_nominal_start_time: Instant::now()
-                    .checked_sub(Duration::from_millis(position_ms as u64)),
+                reported_nominal_start_time: nominal_start_time(
+                    Duration::from_millis(position_ms as u64),
+                    self.playback_speed(),
+                ),
                 suggested_to_preload_next_track: false,
                 is_explicit: loaded_track.is_explicit,
             };
@@ -1801,6 +3135,7 @@ impl PlayerInternal {
                 stream_loader_controller: loaded_track.stream_loader_controller,
                 duration_ms: loaded_track.duration_ms,
                 bytes_per_second: loaded_track.bytes_per_second,
+                format: loaded_track.format,
                 stream_position_ms: loaded_track.stream_position_ms,
                 suggested_to_preload_next_track: false,
                 is_explicit: loaded_track.is_explicit,
@@ -1826,6 +3161,18 @@ impl PlayerInternal {
 
         self.send_event(PlayerEvent::PlayRequestIdChanged { play_request_id });
 
+        // Skipping to another track while playing, unless it is crossfaded into.
+        if let PlayerState::Playing {
+            play_request_id: current_play_request_id,
+            ..
+        } = self.state
+        {
+            if self.crossfade_play_request_id != Some(current_play_request_id) {
+                self.fade_out();
+                self.discard_sink();
+            }
+        }
+
         if !self.config.gapless {
             self.ensure_sink_stopped(play);
         }
@@ -1837,6 +3184,8 @@ impl PlayerInternal {
             )));
         }
 
+        self.crossfade = self.take_crossfade_tail(&track_id);
+
         // Now we check at different positions whether we already have a pre-loaded version
         // of this track somewhere. If so, use it and return.
 
@@ -1905,6 +3254,7 @@ impl PlayerInternal {
                     audio_item,
                     stream_loader_controller,
                     bytes_per_second,
+                    format,
                     duration_ms,
                     normalisation_data,
                     is_explicit,
@@ -1916,6 +3266,7 @@ impl PlayerInternal {
                     audio_item,
                     stream_loader_controller,
                     bytes_per_second,
+                    format,
                     duration_ms,
                     normalisation_data,
                     is_explicit,
@@ -1928,6 +3279,7 @@ impl PlayerInternal {
                         stream_loader_controller,
                         audio_item,
                         bytes_per_second,
+                        format,
                         duration_ms,
                         stream_position_ms,
                         is_explicit,
@@ -2025,6 +3377,52 @@ impl PlayerInternal {
         Ok(())
     }
 
+    fn take_crossfade_tail(&mut self, track_id: &SpotifyUri) -> Option<PlayerCrossfade> {
+        let crossfade = self.config.crossfade.filter(|_| self.config.gapless)?;
+
+        let is_preloaded = matches!(
+            self.preload,
+            PlayerPreload::Ready { track_id: ref preloaded_track_id, .. } if preloaded_track_id == track_id
+        );
+
+        match self.state {
+            PlayerState::Playing {
+                track_id: ref current_track_id,
+                play_request_id,
+                ..
+            } if is_preloaded
+                && current_track_id != track_id
+                && self.crossfade_play_request_id == Some(play_request_id) => {}
+            _ => return None,
+        }
+
+        if let PlayerState::Playing {
+            decoder,
+            normalisation_factor,
+            duration_ms,
+            stream_position_ms,
+            ..
+        } = mem::replace(&mut self.state, PlayerState::Stopped)
+        {
+            let remaining =
+                Duration::from_millis(duration_ms.saturating_sub(stream_position_ms) as u64);
+            let duration = crossfade.min(remaining);
+
+            debug!(
+                "Crossfading into <{track_id}> over {} ms",
+                duration.as_millis()
+            );
+
+            Some(PlayerCrossfade::new(
+                decoder,
+                normalisation_factor,
+                duration,
+            ))
+        } else {
+            None
+        }
+    }
+
     fn handle_command_preload(&mut self, track_id: SpotifyUri) {
         debug!("Preloading track");
         let mut preload_track = true;
@@ -2077,6 +3475,18 @@ impl PlayerInternal {
     }
 
     fn handle_command_seek(&mut self, position_ms: u32) -> PlayerResult {
+        self.fade_out();
+        self.discard_sink();
+
+        self.crossfade = None;
+        self.silent_samples = 0;
+        self.trimmed_samples = 0;
+        self.filters.reset();
+        if let Some(ref mut resampler) = self.resampler {
+            resampler.reset();
+        }
+        self.reset_time_stretcher();
+
         // When we are still loading, the user may immediately ask to
         // seek to another position yet the decoder won't be ready for
         // that. In this case just restart the loading process but
@@ -2130,18 +3540,72 @@ impl PlayerInternal {
         // ensure we have a bit of a buffer of downloaded data
         self.preload_data_before_playback()?;
 
+        let playback_speed = self.playback_speed();
         if let PlayerState::Playing {
             ref mut reported_nominal_start_time,
             ..
         } = self.state
         {
             *reported_nominal_start_time =
-                Instant::now().checked_sub(Duration::from_millis(position_ms as u64));
+                nominal_start_time(Duration::from_millis(position_ms as u64), playback_speed);
         }
 
         Ok(())
     }
 
+    fn playback_speed(&self) -> f64 {
+        self.time_stretcher
+            .as_ref()
+            .map_or(1.0, TimeStretcher::speed)
+    }
+
+    // Discards the audio held by the stretcher, like after a seek.
+    fn reset_time_stretcher(&mut self) {
+        self.time_stretcher
+            .take_if(|time_stretcher| time_stretcher.speed() == 1.0);
+
+        if let Some(ref mut time_stretcher) = self.time_stretcher {
+            time_stretcher.reset();
+        }
+    }
+
+    fn handle_command_set_playback_speed(&mut self, speed: f64) {
+        if !VALID_PLAYBACK_SPEED_RANGE.contains(&speed) {
+            warn!(
+                "Ignoring playback speed {speed}, valid speeds are {} - {}",
+                VALID_PLAYBACK_SPEED_RANGE.start(),
+                VALID_PLAYBACK_SPEED_RANGE.end()
+            );
+            return;
+        }
+
+        if self.config.passthrough {
+            warn!("Changing the playback speed is not available with passthrough");
+            return;
+        }
+
+        debug!("Setting playback speed to {speed}");
+
+        // Back at normal speed the stretcher is kept, as it still holds some of the audio.
+        // It is removed once that audio is discarded anyway.
+        match self.time_stretcher {
+            Some(ref mut time_stretcher) => time_stretcher.set_speed(speed),
+            None if speed != 1.0 => self.time_stretcher = Some(TimeStretcher::new(speed)),
+            None => (),
+        }
+
+        // Re-base the nominal start time, so that the position stays in media time.
+        if let PlayerState::Playing {
+            stream_position_ms,
+            ref mut reported_nominal_start_time,
+            ..
+        } = self.state
+        {
+            *reported_nominal_start_time =
+                nominal_start_time(Duration::from_millis(stream_position_ms as u64), speed);
+        }
+    }
+
     fn handle_command(&mut self, cmd: PlayerCommand) -> PlayerResult {
         debug!("command={cmd:?}");
         match cmd {
@@ -2157,7 +3621,11 @@ impl PlayerInternal {
 
             PlayerCommand::Play => self.handle_play(),
 
-            PlayerCommand::Pause => self.handle_pause(),
+            PlayerCommand::Pause => {
+                self.handle_pause();
+                // Stay paused, also when the sink failed while fading out.
+                self.sink_recovery = None;
+            }
 
             PlayerCommand::Stop => self.handle_player_stop(),
 
@@ -2183,6 +3651,18 @@ impl PlayerInternal {
                 self.send_event(PlayerEvent::AutoPlayChanged { auto_play })
             }
 
+            PlayerCommand::EmitSleepTimerArmedEvent(timer) => {
+                self.send_event(PlayerEvent::SleepTimerArmed { timer })
+            }
+
+            PlayerCommand::EmitSleepTimerCancelledEvent => {
+                self.send_event(PlayerEvent::SleepTimerCancelled)
+            }
+
+            PlayerCommand::EmitSleepTimerFiredEvent(timer) => {
+                self.send_event(PlayerEvent::SleepTimerFired { timer })
+            }
+
             PlayerCommand::EmitSessionClientChangedEvent {
                 client_id,
                 client_name,
@@ -2215,6 +3695,22 @@ impl PlayerInternal {
                 self.auto_normalise_as_album = setting
             }
 
+            PlayerCommand::SetPlaybackSpeed(speed) => self.handle_command_set_playback_speed(speed),
+
+            PlayerCommand::SetSleepFade(end) => self.sleep_fade = end,
+
+            PlayerCommand::GetStatus(status_sender) => {
+                let _ = status_sender.send(self.status());
+            }
+
+            PlayerCommand::AddPcmTap(tap) => self.pcm_taps.push(tap),
+
+            PlayerCommand::SetSink {
+                backend,
+                device,
+                format,
+            } => self.handle_set_sink(backend, device, format)?,
+
             PlayerCommand::EmitFilterExplicitContentChangedEvent(filter) => {
                 self.send_event(PlayerEvent::FilterExplicitContentChanged { filter });
 
@@ -2270,6 +3766,7 @@ impl PlayerInternal {
         let loader = PlayerTrackLoader {
             session: self.session.clone(),
             config: self.config.clone(),
+            local_files: self.local_files.clone(),
         };
 
         let (result_tx, result_rx) = oneshot::channel();
@@ -2370,6 +3867,22 @@ impl fmt::Debug for PlayerCommand {
                 .debug_tuple("SetAutoNormaliseAsAlbum")
                 .field(&setting)
                 .finish(),
+            PlayerCommand::SetPlaybackSpeed(speed) => {
+                f.debug_tuple("SetPlaybackSpeed").field(&speed).finish()
+            }
+            PlayerCommand::SetSleepFade(end) => f.debug_tuple("SetSleepFade").field(&end).finish(),
+            PlayerCommand::GetStatus(_) => f.debug_tuple("GetStatus").finish(),
+            PlayerCommand::AddPcmTap(_) => f.debug_tuple("AddPcmTap").finish(),
+            PlayerCommand::SetSink {
+                backend,
+                device,
+                format,
+            } => f
+                .debug_struct("SetSink")
+                .field("backend", &backend)
+                .field("device", &device)
+                .field("format", &format)
+                .finish(),
             PlayerCommand::EmitFilterExplicitContentChangedEvent(filter) => f
                 .debug_tuple("EmitFilterExplicitContentChangedEvent")
                 .field(&filter)
@@ -2415,6 +3928,17 @@ impl fmt::Debug for PlayerCommand {
                 .debug_tuple("EmitAutoPlayChangedEvent")
                 .field(&auto_play)
                 .finish(),
+            PlayerCommand::EmitSleepTimerArmedEvent(timer) => f
+                .debug_tuple("EmitSleepTimerArmedEvent")
+                .field(&timer)
+                .finish(),
+            PlayerCommand::EmitSleepTimerCancelledEvent => {
+                f.debug_tuple("EmitSleepTimerCancelledEvent").finish()
+            }
+            PlayerCommand::EmitSleepTimerFiredEvent(timer) => f
+                .debug_tuple("EmitSleepTimerFiredEvent")
+                .field(&timer)
+                .finish(),
         }
     }
 }
@@ -0,0 +1,241 @@
+use std::f64::consts::PI;
+
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+
+// Number of input frames on each side of the interpolated position. More taps
+// give a steeper transition band at the cost of more CPU and latency.
+const HALF_TAPS: usize = 32;
+
+// Keep the passband slightly below Nyquist, so the transition band of the
+// windowed sinc does not fold back into the audible range.
+const CUTOFF: f64 = 0.95;
+
+// Polyphase windowed-sinc resampler converting from `SAMPLE_RATE` to the
+// output sample rate. As only rational ratios between common sample rates
+// are supported, every phase of the filter is precomputed.
+pub struct Resampler {
+    output_rate: u32,
+    // The ratio between input and output rate is `step / phases`.
+    step: usize,
+    phases: usize,
+    coefficients: Vec<[f64; 2 * HALF_TAPS]>,
+    // Interleaved input frames that are still needed for interpolation.
+    buffer: Vec<f64>,
+    // Position of the next output frame in `buffer`, in frames and phases.
+    frame: usize,
+    phase: usize,
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+    }
+}
+
+impl Resampler {
+    pub const MAX_PHASES: usize = 1024;
+
+    // Returns `None` if no conversion is needed, or if the ratio between the
+    // rates would need an unreasonable number of phases.
+    pub fn new(output_rate: u32) -> Option<Self> {
+        if output_rate == SAMPLE_RATE || output_rate == 0 {
+            return None;
+        }
+
+        let divisor = gcd(SAMPLE_RATE, output_rate);
+        let step = (SAMPLE_RATE / divisor) as usize;
+        let phases = (output_rate / divisor) as usize;
+
+        if phases > Self::MAX_PHASES {
+            return None;
+        }
+
+        // When downsampling, the cutoff must move down to the output's Nyquist frequency.
+        let cutoff = CUTOFF * f64::min(1.0, phases as f64 / step as f64);
+
+        let coefficients = (0..phases)
+            .map(|phase| {
+                let fraction = phase as f64 / phases as f64;
+                let mut taps = [0.0; 2 * HALF_TAPS];
+                for (tap, coefficient) in taps.iter_mut().enumerate() {
+                    let distance = fraction + (HALF_TAPS - 1) as f64 - tap as f64;
+                    *coefficient =
+                        cutoff * sinc(cutoff * distance) * blackman(distance / HALF_TAPS as f64);
+                }
+
+                // Normalise for unity gain at DC, so each phase has exactly the same level.
+                let sum: f64 = taps.iter().sum();
+                taps.iter_mut().for_each(|coefficient| *coefficient /= sum);
+                taps
+            })
+            .collect();
+
+        let mut resampler = Self {
+            output_rate,
+            step,
+            phases,
+            coefficients,
+            buffer: Vec::new(),
+            frame: 0,
+            phase: 0,
+        };
+        resampler.reset();
+
+        Some(resampler)
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    // Clears the history, like after a seek.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.buffer
+            .resize((HALF_TAPS - 1) * NUM_CHANNELS as usize, 0.0);
+        self.frame = HALF_TAPS - 1;
+        self.phase = 0;
+    }
+
+    pub fn process(&mut self, samples: &[f64]) -> Vec<f64> {
+        let channels = NUM_CHANNELS as usize;
+        self.buffer.extend_from_slice(samples);
+
+        let buffered_frames = self.buffer.len() / channels;
+        let capacity = (samples.len() * self.phases).div_ceil(self.step) + channels;
+        let mut output = Vec::with_capacity(capacity);
+
+        while self.frame + HALF_TAPS < buffered_frames {
+            let coefficients = &self.coefficients[self.phase];
+            let first = (self.frame + 1 - HALF_TAPS) * channels;
+            let window = &self.buffer[first..first + 2 * HALF_TAPS * channels];
+
+            for channel in 0..channels {
+                let sample = window
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .zip(coefficients)
+                    .map(|(sample, coefficient)| sample * coefficient)
+                    .sum();
+                output.push(sample);
+            }
+
+            self.phase += self.step;
+            self.frame += self.phase / self.phases;
+            self.phase %= self.phases;
+        }
+
+        // Drop the frames that are no longer needed.
+        let consumed = self.frame + 1 - HALF_TAPS;
+        self.buffer.drain(..consumed * channels);
+        self.frame -= consumed;
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNELS: usize = NUM_CHANNELS as usize;
+
+    // Processes the frames in packets, like the player does.
+    fn resample(resampler: &mut Resampler, samples: &[f64]) -> Vec<f64> {
+        samples
+            .chunks(1024 * CHANNELS)
+            .flat_map(|packet| resampler.process(packet))
+            .collect()
+    }
+
+    fn sine(frequency: f64, frames: usize) -> Vec<f64> {
+        (0..frames)
+            .flat_map(|frame| {
+                let sample = (2.0 * PI * frequency * frame as f64 / SAMPLE_RATE as f64).sin();
+                [sample; CHANNELS]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn supported_rates() {
+        assert!(Resampler::new(SAMPLE_RATE).is_none());
+        assert!(Resampler::new(0).is_none());
+        assert!(Resampler::new(44101).is_none());
+
+        for rate in [22050, 32000, 48000, 88200, 96000, 192000] {
+            let resampler = Resampler::new(rate).expect("rate should be supported");
+            assert_eq!(resampler.output_rate(), rate);
+        }
+    }
+
+    #[test]
+    fn unity_at_dc() {
+        for rate in [32000, 48000, 96000] {
+            let mut resampler = Resampler::new(rate).unwrap();
+            let output = resample(&mut resampler, &vec![0.5; SAMPLE_RATE as usize * CHANNELS]);
+
+            // Past the first frames, which are faded in from the silent history.
+            let warm_up = 2 * HALF_TAPS * rate as usize / SAMPLE_RATE as usize + 1;
+            for sample in &output[warm_up * CHANNELS..] {
+                assert!((sample - 0.5).abs() < 1e-9, "{rate} Hz: {sample}");
+            }
+        }
+    }
+
+    #[test]
+    fn output_length_follows_ratio() {
+        for rate in [22050, 32000, 48000, 88200, 96000] {
+            let mut resampler = Resampler::new(rate).unwrap();
+            let output = resample(&mut resampler, &sine(440.0, SAMPLE_RATE as usize));
+            assert_eq!(output.len() % CHANNELS, 0);
+
+            // One second in is one second out, less the frames held back for interpolation.
+            let frames = output.len() / CHANNELS;
+            let held_back = HALF_TAPS * rate as usize / SAMPLE_RATE as usize + 1;
+            assert!(
+                frames <= rate as usize && frames + held_back >= rate as usize,
+                "{rate} Hz: {frames} frames"
+            );
+        }
+    }
+
+    #[test]
+    fn passes_audible_frequencies() {
+        let mut resampler = Resampler::new(48000).unwrap();
+        let output = resample(&mut resampler, &sine(1000.0, SAMPLE_RATE as usize));
+
+        let peak = output[output.len() / 2..]
+            .iter()
+            .fold(0.0, |peak: f64, sample| peak.max(sample.abs()));
+        assert!((peak - 1.0).abs() < 0.01, "{peak}");
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let input = sine(440.0, 4096);
+
+        let mut fresh = Resampler::new(48000).unwrap();
+        let expected = resample(&mut fresh, &input);
+
+        let mut resampler = Resampler::new(48000).unwrap();
+        resample(&mut resampler, &sine(3000.0, 3000));
+        resampler.reset();
+        assert_eq!(resample(&mut resampler, &input), expected);
+    }
+}
@@ -0,0 +1,234 @@
+use std::ops::RangeInclusive;
+
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+
+pub const VALID_PLAYBACK_SPEED_RANGE: RangeInclusive<f64> = 0.5..=3.0;
+
+// Window of about 23 ms, which is long enough to contain a few periods of the
+// lowest voice frequencies and short enough not to smear transients.
+const WINDOW_FRAMES: usize = 1024;
+const HOP_FRAMES: usize = WINDOW_FRAMES / 2;
+// How far away from the nominal position a segment may be taken.
+const TOLERANCE_FRAMES: usize = SAMPLE_RATE as usize / 200;
+
+// Pitch-preserving time-stretching using waveform similarity overlap-add
+// (WSOLA). Segments are taken from the input at the playback speed, and
+// overlap-added at a fixed rate. Each segment is picked within a small
+// tolerance, so that it continues the waveform of the previous segment as
+// closely as possible.
+pub struct TimeStretcher {
+    speed: f64,
+    window: Vec<f64>,
+    // Interleaved input frames that are still needed.
+    input: Vec<f64>,
+    // Nominal position of the next segment in `input`, in frames.
+    position: f64,
+    // Position in `input` that naturally continues the previous segment.
+    continuation: Option<usize>,
+    // Windowed second half of the previous segment, to be overlap-added.
+    tail: Vec<f64>,
+}
+
+impl TimeStretcher {
+    pub fn new(speed: f64) -> Self {
+        // Periodic Hann window, which sums to unity at 50% overlap.
+        let window = (0..WINDOW_FRAMES)
+            .map(|i| {
+                let phase = std::f64::consts::TAU * i as f64 / WINDOW_FRAMES as f64;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+
+        Self {
+            speed,
+            window,
+            input: Vec::new(),
+            position: 0.0,
+            continuation: None,
+            tail: vec![0.0; HOP_FRAMES * NUM_CHANNELS as usize],
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    // Clears the history, like after a seek.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.continuation = None;
+        self.tail.iter_mut().for_each(|sample| *sample = 0.0);
+    }
+
+    pub fn process(&mut self, samples: &[f64]) -> Vec<f64> {
+        let channels = NUM_CHANNELS as usize;
+        self.input.extend_from_slice(samples);
+
+        let mut output =
+            Vec::with_capacity((samples.len() as f64 / self.speed) as usize + self.tail.len());
+
+        loop {
+            let nominal = self.position as usize;
+            let search = nominal.saturating_sub(TOLERANCE_FRAMES)..=nominal + TOLERANCE_FRAMES;
+
+            if (search.end() + WINDOW_FRAMES) * channels > self.input.len() {
+                break;
+            }
+
+            let start = match self.continuation {
+                Some(continuation) => self.best_match(continuation, search),
+                None => nominal,
+            };
+
+            let segment = &self.input[start * channels..(start + WINDOW_FRAMES) * channels];
+            let (head, tail) = segment.split_at(HOP_FRAMES * channels);
+
+            for (i, (sample, previous)) in head.iter().zip(&self.tail).enumerate() {
+                output.push(previous + sample * self.window[i / channels]);
+            }
+
+            for (i, (sample, previous)) in tail.iter().zip(self.tail.iter_mut()).enumerate() {
+                *previous = sample * self.window[HOP_FRAMES + i / channels];
+            }
+
+            self.position += HOP_FRAMES as f64 * self.speed;
+
+            // Drop the frames that can no longer be part of a segment.
+            let consumed = usize::min(
+                start + HOP_FRAMES,
+                (self.position as usize).saturating_sub(TOLERANCE_FRAMES),
+            );
+            self.input.drain(..consumed * channels);
+            self.position -= consumed as f64;
+            self.continuation = Some(start + HOP_FRAMES - consumed);
+        }
+
+        output
+    }
+
+    // Finds the segment start within `search` whose beginning is most similar
+    // to the natural continuation of the previous segment.
+    fn best_match(&self, continuation: usize, search: RangeInclusive<usize>) -> usize {
+        let channels = NUM_CHANNELS as usize;
+        let mono = |frame: usize| -> f64 {
+            self.input[frame * channels..(frame + 1) * channels]
+                .iter()
+                .sum()
+        };
+
+        let template: Vec<f64> = (continuation..continuation + HOP_FRAMES)
+            .map(mono)
+            .collect();
+        let candidates: Vec<f64> = (*search.start()..search.end() + HOP_FRAMES)
+            .map(mono)
+            .collect();
+
+        let mut best_start = *search.start();
+        let mut best_similarity = f64::MIN;
+
+        for offset in 0..=(search.end() - search.start()) {
+            let candidate = &candidates[offset..offset + HOP_FRAMES];
+
+            let (correlation, energy) = candidate.iter().zip(&template).fold(
+                (0.0, 0.0),
+                |(correlation, energy), (sample, reference)| {
+                    (correlation + sample * reference, energy + sample * sample)
+                },
+            );
+
+            let similarity = correlation / (energy.sqrt() + f64::EPSILON);
+            if similarity > best_similarity {
+                best_similarity = similarity;
+                best_start = search.start() + offset;
+            }
+        }
+
+        best_start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    const CHANNELS: usize = NUM_CHANNELS as usize;
+
+    fn sine(frequency: f64, frames: usize) -> Vec<f64> {
+        (0..frames)
+            .flat_map(|frame| {
+                let sample = 0.5 * (TAU * frequency * frame as f64 / SAMPLE_RATE as f64).sin();
+                [sample; CHANNELS]
+            })
+            .collect()
+    }
+
+    // Processes the frames in packets, like the player does.
+    fn stretch(stretcher: &mut TimeStretcher, samples: &[f64]) -> Vec<f64> {
+        samples
+            .chunks(1024 * CHANNELS)
+            .flat_map(|packet| stretcher.process(packet))
+            .collect()
+    }
+
+    #[test]
+    fn output_length_follows_speed() {
+        let frames = SAMPLE_RATE as usize;
+        let input = sine(440.0, frames);
+
+        for speed in [0.5, 1.0, 1.5, 3.0] {
+            let output = stretch(&mut TimeStretcher::new(speed), &input);
+            assert_eq!(output.len() % CHANNELS, 0);
+
+            // Less what is held back for the next segments.
+            let expected = frames as f64 / speed;
+            let held_back = (WINDOW_FRAMES + TOLERANCE_FRAMES) as f64 / speed;
+            let actual = (output.len() / CHANNELS) as f64;
+            assert!(
+                actual <= expected && actual + held_back >= expected,
+                "speed {speed}: {actual} frames instead of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn preserves_pitch_and_level() {
+        let input = sine(440.0, SAMPLE_RATE as usize);
+
+        for speed in [0.5, 2.0] {
+            let output = stretch(&mut TimeStretcher::new(speed), &input);
+            let left: Vec<f64> = output.iter().step_by(CHANNELS).copied().collect();
+            let settled = &left[WINDOW_FRAMES..];
+
+            let crossings = settled
+                .windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count();
+            let frequency = crossings as f64 * SAMPLE_RATE as f64 / settled.len() as f64;
+            assert!(
+                (frequency - 440.0).abs() < 440.0 * 0.02,
+                "speed {speed}: {frequency} Hz"
+            );
+
+            let peak = settled.iter().fold(0.0, |peak: f64, s| peak.max(s.abs()));
+            assert!((peak - 0.5).abs() < 0.05, "speed {speed}: peak {peak}");
+        }
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let input = sine(440.0, 8192);
+
+        let expected = stretch(&mut TimeStretcher::new(1.5), &input);
+
+        let mut stretcher = TimeStretcher::new(1.5);
+        stretch(&mut stretcher, &sine(3000.0, 5000));
+        stretcher.reset();
+        assert_eq!(stretch(&mut stretcher, &input), expected);
+    }
+}
@@ -2,6 +2,7 @@ use data_encoding::HEXLOWER;
 use futures_util::StreamExt;
 #[cfg(feature = "alsa-backend")]
 use librespot::playback::mixer::alsamixer::AlsaMixer;
+use librespot::playback::mixer::commandmixer::CommandMixer;
 use librespot::{
     connect::{ConnectConfig, Spirc},
     core::{
@@ -10,13 +11,14 @@ use librespot::{
     },
     discovery::DnsSdServiceBuilder,
     playback::{
-        audio_backend::{self, BACKENDS, SinkBuilder},
+        audio_backend::{self, BACKENDS, SinkBuilder, TeeOutput},
         config::{
-            AudioFormat, Bitrate, NormalisationMethod, NormalisationType, PlayerConfig, VolumeCtrl,
+            AudioFormat, Bitrate, CrossfadeCurve, EqualizerBand, NormalisationMethod,
+            NormalisationType, PlayerConfig, PreviewMode, VolumeCtrl, VolumeCurve,
         },
         dither,
         mixer::{self, MixerConfig, MixerFn},
-        player::{Player, coefficient_to_duration, duration_to_coefficient},
+        player::{Player, SleepTimer, coefficient_to_duration, duration_to_coefficient},
     },
 };
 use librespot_oauth::OAuthClientBuilder;
@@ -25,7 +27,7 @@ use sha1::{Digest, Sha1};
 use std::{
     env,
     ffi::OsStr,
-    fs::create_dir_all,
+    fs::{create_dir_all, read_to_string},
     ops::RangeInclusive,
     path::{Path, PathBuf},
     pin::Pin,
@@ -206,6 +208,9 @@ static OAUTH_SCOPES: &[&str] = &[
 struct Setup {
     format: AudioFormat,
     backend: SinkBuilder,
+    // The pipewire sink shares its stream volume with the pipewire mixer.
+    #[cfg(feature = "pipewire-backend")]
+    pipewire_backend: bool,
     device: Option<String>,
     mixer: MixerFn,
     cache: Option<Cache>,
@@ -221,6 +226,8 @@ struct Setup {
     emit_sink_events: bool,
     zeroconf_ip: Vec<std::net::IpAddr>,
     zeroconf_backend: Option<DnsSdServiceBuilder>,
+    sleep_timer: Option<SleepTimer>,
+    sleep_timer_fade: bool,
 }
 
 async fn get_setup() -> Setup {
@@ -231,6 +238,13 @@ async fn get_setup() -> Setup {
     const VALID_NORMALISATION_THRESHOLD_RANGE: RangeInclusive<f64> = -10.0..=0.0;
     const VALID_NORMALISATION_ATTACK_RANGE: RangeInclusive<u64> = 1..=500;
     const VALID_NORMALISATION_RELEASE_RANGE: RangeInclusive<u64> = 1..=1000;
+    const VALID_CROSSFADE_RANGE: RangeInclusive<u64> = 1..=12;
+    const VALID_FADE_DURATION_RANGE: RangeInclusive<u64> = 0..=500;
+    const VALID_LEVEL_METER_RANGE: RangeInclusive<u64> = 50..=10000;
+    const VALID_OUTPUT_BUFFER_RANGE: RangeInclusive<u64> = 20..=5000;
+    const VALID_TRIM_SILENCE_THRESHOLD_RANGE: RangeInclusive<f64> = -90.0..=-20.0;
+    const VALID_TRIM_SILENCE_DURATION_RANGE: RangeInclusive<u64> = 100..=10000;
+    const VALID_SAMPLE_RATES: [u32; 6] = [44100, 48000, 88200, 96000, 176400, 192000];
 
     const ACCESS_TOKEN: &str = "access-token";
     const AP_PORT: &str = "ap-port";
@@ -239,6 +253,8 @@ async fn get_setup() -> Setup {
     const BITRATE: &str = "bitrate";
     const CACHE: &str = "cache";
     const CACHE_SIZE_LIMIT: &str = "cache-size-limit";
+    const CROSSFADE: &str = "crossfade";
+    const CROSSFADE_CURVE: &str = "crossfade-curve";
     const DEVICE: &str = "device";
     const DEVICE_TYPE: &str = "device-type";
     const DEVICE_IS_GROUP: &str = "group";
@@ -250,10 +266,15 @@ async fn get_setup() -> Setup {
     const EMIT_SINK_EVENTS: &str = "emit-sink-events";
     const ENABLE_OAUTH: &str = "enable-oauth";
     const ENABLE_VOLUME_NORMALISATION: &str = "enable-volume-normalisation";
+    const EQUALIZER: &str = "eq";
+    const FADE_DURATION: &str = "fade-duration";
     const FORMAT: &str = "format";
     const HELP: &str = "help";
     const INITIAL_VOLUME: &str = "initial-volume";
+    const LEVEL_METER: &str = "level-meter";
+    const LOCAL_FILES_DIR: &str = "local-files-dir";
     const MIXER_TYPE: &str = "mixer";
+    const MIXER_COMMAND: &str = "mixer-command";
     const ALSA_MIXER_DEVICE: &str = "alsa-mixer-device";
     const ALSA_MIXER_INDEX: &str = "alsa-mixer-index";
     const ALSA_MIXER_CONTROL: &str = "alsa-mixer-control";
@@ -267,17 +288,27 @@ async fn get_setup() -> Setup {
     const NORMALISATION_THRESHOLD: &str = "normalisation-threshold";
     const OAUTH_PORT: &str = "oauth-port";
     const ONEVENT: &str = "onevent";
+    const OUTPUT_BUFFER: &str = "output-buffer";
     #[cfg(feature = "passthrough-decoder")]
     const PASSTHROUGH: &str = "passthrough";
     const PASSWORD: &str = "password";
+    const PREVIEW: &str = "preview";
     const PROXY: &str = "proxy";
     const QUIET: &str = "quiet";
+    const SAMPLE_RATE: &str = "sample-rate";
+    const SLEEP_TIMER: &str = "sleep-timer";
+    const SLEEP_TIMER_FADE: &str = "sleep-timer-fade";
     const SYSTEM_CACHE: &str = "system-cache";
+    const TEE: &str = "tee";
     const TEMP_DIR: &str = "tmp";
+    const TRIM_SILENCE: &str = "trim-silence";
+    const TRIM_SILENCE_DURATION: &str = "trim-silence-duration";
+    const TRIM_SILENCE_THRESHOLD: &str = "trim-silence-threshold";
     const USERNAME: &str = "username";
     const VERBOSE: &str = "verbose";
     const VERSION: &str = "version";
     const VOLUME_CTRL: &str = "volume-ctrl";
+    const VOLUME_CURVE: &str = "volume-curve";
     const VOLUME_RANGE: &str = "volume-range";
     const VOLUME_STEPS: &str = "volume-steps";
     const ZEROCONF_PORT: &str = "zeroconf-port";
@@ -335,21 +366,36 @@ async fn get_setup() -> Setup {
     const NORMALISATION_THRESHOLD_SHORT: &str = "Z";
     const ZEROCONF_PORT_SHORT: &str = "z";
     const ZEROCONF_BACKEND_SHORT: &str = ""; // no short flag
+    const CROSSFADE_SHORT: &str = ""; // no short flag
+    const CROSSFADE_CURVE_SHORT: &str = ""; // no short flag
+    const EQUALIZER_SHORT: &str = ""; // no short flag
+    const FADE_DURATION_SHORT: &str = ""; // no short flag
+    const LEVEL_METER_SHORT: &str = ""; // no short flag
+    const LOCAL_FILES_DIR_SHORT: &str = ""; // no short flag
+    const MIXER_COMMAND_SHORT: &str = ""; // no short flag
+    const OUTPUT_BUFFER_SHORT: &str = ""; // no short flag
+    const PREVIEW_SHORT: &str = ""; // no short flag
+    const SAMPLE_RATE_SHORT: &str = ""; // no short flag
+    const SLEEP_TIMER_SHORT: &str = ""; // no short flag
+    const SLEEP_TIMER_FADE_SHORT: &str = ""; // no short flag
+    const TEE_SHORT: &str = ""; // no short flag
+    const TRIM_SILENCE_SHORT: &str = ""; // no short flag
+    const TRIM_SILENCE_DURATION_SHORT: &str = ""; // no short flag
+    const TRIM_SILENCE_THRESHOLD_SHORT: &str = ""; // no short flag
+    const VOLUME_CURVE_SHORT: &str = ""; // no short flag
 
     // Options that have different descriptions
     // depending on what backends were enabled at build time.
-    #[cfg(feature = "alsa-backend")]
-    const MIXER_TYPE_DESC: &str = "Mixer to use {alsa|softvol}. Defaults to softvol.";
-    #[cfg(not(feature = "alsa-backend"))]
-    const MIXER_TYPE_DESC: &str = "Not supported by the included audio backend(s).";
     #[cfg(any(
         feature = "alsa-backend",
+        feature = "pipewire-backend",
         feature = "rodio-backend",
         feature = "portaudio-backend"
     ))]
     const DEVICE_DESC: &str = "Audio device to use. Use ? to list options if using alsa, portaudio or rodio. Defaults to the backend's default.";
     #[cfg(not(any(
         feature = "alsa-backend",
+        feature = "pipewire-backend",
         feature = "rodio-backend",
         feature = "portaudio-backend"
     )))]
@@ -379,6 +425,14 @@ async fn get_setup() -> Setup {
     const VOLUME_STEPS_DESC: &str =
         "Number of incremental steps when responding to volume control updates. Defaults to 64.";
 
+    // The mixers also depend on what backends were enabled at build time.
+    let mut mixer_names: Vec<&str> = mixer::MIXERS.iter().map(|mixer| mixer.0).collect();
+    mixer_names.sort_unstable();
+    let mixer_type_desc = format!(
+        "Mixer to use {{{}}}. Defaults to softvol.",
+        mixer_names.join("|")
+    );
+
     let mut opts = getopts::Options::new();
     opts.optflag(
         HELP_SHORT,
@@ -430,6 +484,16 @@ async fn get_setup() -> Setup {
         ENABLE_VOLUME_NORMALISATION,
         "Play all tracks at approximately the same apparent volume.",
     )
+    .optflag(
+        SLEEP_TIMER_FADE_SHORT,
+        SLEEP_TIMER_FADE,
+        "Ramp the volume down over the final minute before the sleep timer pauses playback.",
+    )
+    .optflag(
+        TRIM_SILENCE_SHORT,
+        TRIM_SILENCE,
+        "Skip long stretches of silence within and between tracks.",
+    )
     .optflag(
         ENABLE_OAUTH_SHORT,
         ENABLE_OAUTH,
@@ -444,9 +508,15 @@ async fn get_setup() -> Setup {
     .optopt(
         BITRATE_SHORT,
         BITRATE,
-        "Bitrate (kbps) {96|160|320}. Defaults to 160.",
+        "Bitrate (kbps) {96|160|320|lossless}. Lossless plays FLAC where available and falls back to 320 otherwise. Defaults to 160.",
         "BITRATE",
     )
+    .optopt(
+        PREVIEW_SHORT,
+        PREVIEW,
+        "Play the 30-second previews of tracks {disabled|fallback|always}. Fallback plays the preview when the full track fails to load. Defaults to disabled.",
+        "MODE",
+    )
     .optopt(
         FORMAT_SHORT,
         FORMAT,
@@ -456,7 +526,7 @@ async fn get_setup() -> Setup {
     .optopt(
         DITHER_SHORT,
         DITHER,
-        "Specify the dither algorithm to use {none|gpdf|tpdf|tpdf_hp}. Defaults to tpdf for formats S16, S24, S24_3 and none for other formats.",
+        "Specify the dither algorithm to use {none|gpdf|tpdf|tpdf_hp|tpdf_lipshitz|tpdf_fweighted}. Defaults to tpdf for formats S16, S24, S24_3 and none for other formats.",
         "DITHER",
     )
     .optopt(
@@ -487,6 +557,12 @@ async fn get_setup() -> Setup {
         "Path to a directory where system files (credentials, volume) will be cached. May be different from the `--cache` option value.",
         "PATH",
     )
+    .optopt(
+        LOCAL_FILES_DIR_SHORT,
+        LOCAL_FILES_DIR,
+        "Path to a directory with mp3, ogg and flac files to play local tracks from, matched by their tags or filename. The Local Files context of a phone is not supported. Local tracks are skipped by default.",
+        "PATH",
+    )
     .optopt(
         CACHE_SIZE_LIMIT_SHORT,
         CACHE_SIZE_LIMIT,
@@ -499,6 +575,12 @@ async fn get_setup() -> Setup {
         "Audio backend to use. Use ? to list options.",
         "NAME",
     )
+    .optopt(
+        TEE_SHORT,
+        TEE,
+        "Semicolon-separated additional outputs as BACKEND[:FORMAT[:DEVICE]] that play along with the main backend, e.g. \"pipe:S16:/tmp/snapfifo\". FORMAT defaults to S16 if empty. Each output plays from its own buffer and drops audio when it doesn't keep up, and failures of these outputs don't stop playback. Disabled by default.",
+        "OUTPUTS",
+    )
     .optopt(
         USERNAME_SHORT,
         USERNAME,
@@ -550,9 +632,15 @@ async fn get_setup() -> Setup {
     .optopt(
         MIXER_TYPE_SHORT,
         MIXER_TYPE,
-        MIXER_TYPE_DESC,
+        &mixer_type_desc,
         "MIXER",
     )
+    .optopt(
+        MIXER_COMMAND_SHORT,
+        MIXER_COMMAND,
+        "Shell command of the command mixer, which is sent `set <volume>` and `get` lines and replies with `volume <volume>` lines, in 0.0 - 1.0 as mapped by the volume control.",
+        "COMMAND",
+    )
     .optopt(
         DEVICE_SHORT,
         DEVICE,
@@ -571,6 +659,12 @@ async fn get_setup() -> Setup {
         "Volume control scale type {cubic|fixed|linear|log}. Defaults to log.",
         "VOLUME_CTRL"
     )
+    .optopt(
+        VOLUME_CURVE_SHORT,
+        VOLUME_CURVE,
+        "Custom volume curve as comma-separated VOLUME:DB points with volume in % from 0 - 100, e.g. \"0:-60,20:-30,60:-8,100:0\", or a file with such points. The dB are interpolated between the points, and held at the first point below it. Volume 0 is always mute. Overrides `--volume-ctrl` and `--volume-range`.",
+        "POINTS|PATH",
+    )
     .optopt(
         VOLUME_RANGE_SHORT,
         VOLUME_RANGE,
@@ -583,6 +677,66 @@ async fn get_setup() -> Setup {
         VOLUME_STEPS_DESC,
         "STEPS",
     )
+    .optopt(
+        SAMPLE_RATE_SHORT,
+        SAMPLE_RATE,
+        "Sample rate (Hz) to open the audio device at {44100|48000|88200|96000|176400|192000}. Audio is resampled if this is not 44100. Defaults to 44100.",
+        "RATE",
+    )
+    .optopt(
+        CROSSFADE_SHORT,
+        CROSSFADE,
+        "Crossfade consecutive tracks over this many seconds from 1 to 12. Requires gapless playback. Disabled by default.",
+        "SECONDS",
+    )
+    .optopt(
+        CROSSFADE_CURVE_SHORT,
+        CROSSFADE_CURVE,
+        "Specify the crossfade curve to use {linear|equal-power}. Defaults to equal-power.",
+        "CURVE",
+    )
+    .optopt(
+        FADE_DURATION_SHORT,
+        FADE_DURATION,
+        "Fade (ms) applied when pausing, resuming, seeking and stopping to avoid clicks from 0 to 500. 0 disables fading. Disabled by default.",
+        "TIME",
+    )
+    .optopt(
+        LEVEL_METER_SHORT,
+        LEVEL_METER,
+        "Interval (ms) of level events with peak, RMS and band levels of the audio from 50 to 10000. `--onevent` runs for at most one of them every 500 ms. Disabled by default.",
+        "TIME",
+    )
+    .optopt(
+        OUTPUT_BUFFER_SHORT,
+        OUTPUT_BUFFER,
+        "Size (ms) of a buffer that is played to the backend from a separate thread, from 20 to 5000. Sends an event on buffer underruns and overruns. Disabled by default.",
+        "TIME",
+    )
+    .optopt(
+        EQUALIZER_SHORT,
+        EQUALIZER,
+        "Comma-separated equalizer bands as FREQ:GAIN:Q for peaking bands, e.g. \"100:+3:0.7,8000:-2:1.0\". Prefix a band with {lowshelf|highshelf} for shelving bands, or use {lowpass|highpass}:FREQ:Q for pass bands. Gain (dB) from -24.0 to 24.0. Disabled by default.",
+        "BANDS",
+    )
+    .optopt(
+        SLEEP_TIMER_SHORT,
+        SLEEP_TIMER,
+        "Pause playback after this many minutes, or at the end of the current track or context {MINUTES|end-of-track|end-of-context}. Disabled by default.",
+        "TIMER",
+    )
+    .optopt(
+        TRIM_SILENCE_THRESHOLD_SHORT,
+        TRIM_SILENCE_THRESHOLD,
+        "Level (dBFS) below which audio is considered silent from -90.0 to -20.0. Defaults to -60.0.",
+        "THRESHOLD",
+    )
+    .optopt(
+        TRIM_SILENCE_DURATION_SHORT,
+        TRIM_SILENCE_DURATION,
+        "Duration (ms) of silence that is kept before the rest is skipped from 100 to 10000. Defaults to 500.",
+        "TIME",
+    )
     .optopt(
         NORMALISATION_METHOD_SHORT,
         NORMALISATION_METHOD,
@@ -807,12 +961,7 @@ async fn get_setup() -> Setup {
     }
 
     #[cfg(not(feature = "alsa-backend"))]
-    for a in &[
-        MIXER_TYPE,
-        ALSA_MIXER_DEVICE,
-        ALSA_MIXER_INDEX,
-        ALSA_MIXER_CONTROL,
-    ] {
+    for a in &[ALSA_MIXER_DEVICE, ALSA_MIXER_INDEX, ALSA_MIXER_CONTROL] {
         if opt_present(a) {
             warn!(
                 "Alsa specific options have no effect if the alsa backend is not enabled at build time."
@@ -855,6 +1004,12 @@ async fn get_setup() -> Setup {
         exit(1);
     };
 
+    #[cfg(feature = "pipewire-backend")]
+    let pipewire_backend = backend_name
+        .as_deref()
+        .or_else(|| BACKENDS.first().map(|(name, _)| *name))
+        == Some("pipewire");
+
     let backend = audio_backend::find(backend_name).unwrap_or_else(|| {
         invalid_error_msg(
             BACKEND,
@@ -886,27 +1041,90 @@ async fn get_setup() -> Setup {
         })
         .unwrap_or_default();
 
+    let sample_rate = opt_str(SAMPLE_RATE)
+        .map(|rate| match rate.parse::<u32>() {
+            Ok(value) if VALID_SAMPLE_RATES.contains(&value) => value,
+            _ => {
+                let valid_values = &VALID_SAMPLE_RATES.map(|rate| rate.to_string()).join(", ");
+
+                invalid_error_msg(
+                    SAMPLE_RATE,
+                    SAMPLE_RATE_SHORT,
+                    &rate,
+                    valid_values,
+                    &librespot::playback::SAMPLE_RATE.to_string(),
+                );
+
+                exit(1);
+            }
+        })
+        .unwrap_or(librespot::playback::SAMPLE_RATE);
+
     let device = opt_str(DEVICE);
     if let Some(ref value) = device {
         if value == "?" {
-            backend(device, format);
+            backend(device, format, sample_rate);
             exit(0);
         } else if value.is_empty() {
             empty_string_error_msg(DEVICE, DEVICE_SHORT);
         }
     }
 
-    #[cfg(feature = "alsa-backend")]
+    let mut tee: Vec<TeeOutput> = opt_str(TEE)
+        .map(|outputs| {
+            outputs
+                .split(';')
+                .map(str::trim)
+                .filter(|output| !output.is_empty())
+                .map(|output| {
+                    let mut parts = output.splitn(3, ':');
+
+                    let backend = parts.next().unwrap_or_default().to_string();
+                    if audio_backend::find(Some(backend.clone())).is_none() {
+                        invalid_error_msg(TEE, TEE_SHORT, output, "", "");
+                        list_backends();
+                        exit(1);
+                    }
+
+                    let format = match parts.next().filter(|format| !format.is_empty()) {
+                        Some(format) => AudioFormat::from_str(format).unwrap_or_else(|_| {
+                            invalid_error_msg(
+                                TEE,
+                                TEE_SHORT,
+                                output,
+                                "F64, F32, S32, S24, S24_3, S16",
+                                "",
+                            );
+                            exit(1);
+                        }),
+                        None => AudioFormat::default(),
+                    };
+
+                    let device = parts
+                        .next()
+                        .filter(|device| !device.is_empty())
+                        .map(String::from);
+
+                    // The ditherer is set along with the one of the main output.
+                    TeeOutput {
+                        backend,
+                        device,
+                        format,
+                        ditherer: None,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
     let mixer_type = opt_str(MIXER_TYPE);
-    #[cfg(not(feature = "alsa-backend"))]
-    let mixer_type: Option<String> = None;
 
     let mixer = mixer::find(mixer_type.as_deref()).unwrap_or_else(|| {
         invalid_error_msg(
             MIXER_TYPE,
             MIXER_TYPE_SHORT,
             &opt_str(MIXER_TYPE).unwrap_or_default(),
-            "alsa, softvol",
+            &mixer_names.join(", "),
             "softvol",
         );
 
@@ -919,6 +1137,12 @@ async fn get_setup() -> Setup {
         _ => false,
     };
 
+    let is_command_mixer = mixer_type.as_deref() == Some(CommandMixer::NAME);
+
+    if !is_command_mixer && opt_present(MIXER_COMMAND) {
+        warn!("The `--{MIXER_COMMAND}` option has no effect if not using the command mixer.");
+    }
+
     #[cfg(feature = "alsa-backend")]
     if !is_alsa_mixer {
         for a in &[ALSA_MIXER_DEVICE, ALSA_MIXER_INDEX, ALSA_MIXER_CONTROL] {
@@ -1092,8 +1316,40 @@ async fn get_setup() -> Setup {
                 }
             });
 
+        let volume_curve = opt_str(VOLUME_CURVE).map(|curve| {
+            let points = if Path::new(&curve).is_file() {
+                read_to_string(&curve).unwrap_or_else(|e| {
+                    error!("Unable to read the volume curve from {curve}: {e}");
+                    exit(1);
+                })
+            } else {
+                curve.clone()
+            };
+
+            VolumeCurve::from_str(&points).unwrap_or_else(|_| {
+                invalid_error_msg(
+                    VOLUME_CURVE,
+                    VOLUME_CURVE_SHORT,
+                    &curve,
+                    "at least two VOLUME:DB points with increasing volume from 0 - 100 and dB that does not decrease, or a file with such points",
+                    "",
+                );
+
+                exit(1);
+            })
+        });
+
+        if volume_curve.is_some() {
+            for a in &[VOLUME_CTRL, VOLUME_RANGE] {
+                if opt_present(a) {
+                    warn!("`--{a}` has no effect with a custom volume curve.");
+                }
+            }
+        }
+
         let volume_ctrl = opt_str(VOLUME_CTRL)
             .as_deref()
+            .filter(|_| volume_curve.is_none())
             .map(|volume_ctrl| {
                 VolumeCtrl::from_str_with_range(volume_ctrl, volume_range).unwrap_or_else(|_| {
                     invalid_error_msg(
@@ -1107,13 +1363,38 @@ async fn get_setup() -> Setup {
                     exit(1);
                 })
             })
-            .unwrap_or_else(|| VolumeCtrl::Log(volume_range));
+            .unwrap_or_else(|| match volume_curve {
+                Some(curve) => VolumeCtrl::Custom(curve),
+                None => VolumeCtrl::Log(volume_range),
+            });
+
+        let command = if is_command_mixer {
+            match opt_str(MIXER_COMMAND) {
+                Some(command) if !command.is_empty() => Some(command),
+                _ => {
+                    error!(
+                        "`--{}` must be specified when `--{}` / `-{}` is set to \"{}\"",
+                        MIXER_COMMAND,
+                        MIXER_TYPE,
+                        MIXER_TYPE_SHORT,
+                        CommandMixer::NAME
+                    );
+
+                    exit(1);
+                }
+            }
+        } else {
+            None
+        };
 
         MixerConfig {
             device,
             control,
             index,
             volume_ctrl,
+            command,
+            #[cfg(feature = "pipewire-backend")]
+            pipewire_volume: mixer_default_config.pipewire_volume,
         }
     };
 
@@ -1564,14 +1845,49 @@ async fn get_setup() -> Setup {
             .as_deref()
             .map(|bitrate| {
                 Bitrate::from_str(bitrate).unwrap_or_else(|_| {
-                    invalid_error_msg(BITRATE, BITRATE_SHORT, bitrate, "96, 160, 320", "160");
+                    invalid_error_msg(
+                        BITRATE,
+                        BITRATE_SHORT,
+                        bitrate,
+                        "96, 160, 320, lossless",
+                        "160",
+                    );
                     exit(1);
                 })
             })
             .unwrap_or(player_default_config.bitrate);
 
+        let preview_mode = opt_str(PREVIEW)
+            .as_deref()
+            .map(|mode| {
+                PreviewMode::from_str(mode).unwrap_or_else(|_| {
+                    invalid_error_msg(
+                        PREVIEW,
+                        PREVIEW_SHORT,
+                        mode,
+                        "disabled, fallback, always",
+                        "disabled",
+                    );
+                    exit(1);
+                })
+            })
+            .unwrap_or(player_default_config.preview_mode);
+
         let gapless = !opt_present(DISABLE_GAPLESS);
 
+        let local_files_dir = opt_str(LOCAL_FILES_DIR).map(|dir| {
+            let dir = PathBuf::from(dir);
+            if !dir.is_dir() {
+                error!(
+                    "`--{LOCAL_FILES_DIR}` \"{}\" is not a directory",
+                    dir.display()
+                );
+                exit(1);
+            }
+
+            dir
+        });
+
         let normalisation = opt_present(ENABLE_VOLUME_NORMALISATION);
 
         let normalisation_method;
@@ -1784,7 +2100,7 @@ async fn get_setup() -> Setup {
                             DITHER,
                             DITHER_SHORT,
                             &opt_str(DITHER).unwrap_or_default(),
-                            "none, gpdf, tpdf, tpdf_hp for formats S16, S24, S24_3, S32, none for formats F32, F64",
+                            "none, gpdf, tpdf, tpdf_hp, tpdf_lipshitz, tpdf_fweighted for formats S16, S24, S24_3, S32, none for formats F32, F64",
                             "tpdf for formats S16, S24, S24_3 and none for formats S32, F32, F64",
                         );
 
@@ -1800,15 +2116,242 @@ async fn get_setup() -> Setup {
             },
         };
 
+        // The tee outputs dither like the main output, as far as their formats allow.
+        for output in &mut tee {
+            output.ditherer = match output.format {
+                AudioFormat::F64 | AudioFormat::F32 => None,
+                _ if opt_present(DITHER) => ditherer,
+                AudioFormat::S16 | AudioFormat::S24 | AudioFormat::S24_3 => {
+                    player_default_config.ditherer
+                }
+                AudioFormat::S32 => None,
+            };
+        }
+
         #[cfg(feature = "passthrough-decoder")]
         let passthrough = opt_present(PASSTHROUGH);
         #[cfg(not(feature = "passthrough-decoder"))]
         let passthrough = false;
 
+        let crossfade = opt_str(CROSSFADE).map(|crossfade| match crossfade.parse::<u64>() {
+            Ok(value) if (VALID_CROSSFADE_RANGE).contains(&value) => Duration::from_secs(value),
+            _ => {
+                let valid_values = &format!(
+                    "{} - {}",
+                    VALID_CROSSFADE_RANGE.start(),
+                    VALID_CROSSFADE_RANGE.end()
+                );
+
+                invalid_error_msg(CROSSFADE, CROSSFADE_SHORT, &crossfade, valid_values, "");
+
+                exit(1);
+            }
+        });
+
+        let crossfade_curve = opt_str(CROSSFADE_CURVE)
+            .as_deref()
+            .map(|curve| {
+                CrossfadeCurve::from_str(curve).unwrap_or_else(|_| {
+                    invalid_error_msg(
+                        CROSSFADE_CURVE,
+                        CROSSFADE_CURVE_SHORT,
+                        curve,
+                        "linear, equal-power",
+                        "equal-power",
+                    );
+
+                    exit(1);
+                })
+            })
+            .unwrap_or(player_default_config.crossfade_curve);
+
+        if crossfade.is_none() {
+            if opt_present(CROSSFADE_CURVE) {
+                warn!("Without the `--{CROSSFADE}` option the crossfade curve has no effect.");
+            }
+        } else if !gapless {
+            warn!("Crossfade requires gapless playback and will have no effect.");
+        } else if passthrough {
+            warn!("Crossfade is not available with passthrough and will have no effect.");
+        }
+
+        let fade_duration = opt_str(FADE_DURATION)
+            .map(|duration| match duration.parse::<u64>() {
+                Ok(0) => None,
+                Ok(value) if (VALID_FADE_DURATION_RANGE).contains(&value) => {
+                    Some(Duration::from_millis(value))
+                }
+                _ => {
+                    let valid_values = &format!(
+                        "{} - {}",
+                        VALID_FADE_DURATION_RANGE.start(),
+                        VALID_FADE_DURATION_RANGE.end()
+                    );
+
+                    invalid_error_msg(
+                        FADE_DURATION,
+                        FADE_DURATION_SHORT,
+                        &duration,
+                        valid_values,
+                        &player_default_config
+                            .fade_duration
+                            .unwrap_or_default()
+                            .as_millis()
+                            .to_string(),
+                    );
+
+                    exit(1);
+                }
+            })
+            .unwrap_or(player_default_config.fade_duration);
+
+        let level_meter_interval = opt_str(LEVEL_METER)
+            .map(|interval| match interval.parse::<u64>() {
+                Ok(value) if VALID_LEVEL_METER_RANGE.contains(&value) => {
+                    Duration::from_millis(value)
+                }
+                _ => {
+                    let valid_values = &format!(
+                        "{} - {}",
+                        VALID_LEVEL_METER_RANGE.start(),
+                        VALID_LEVEL_METER_RANGE.end()
+                    );
+
+                    invalid_error_msg(LEVEL_METER, LEVEL_METER_SHORT, &interval, valid_values, "");
+
+                    exit(1);
+                }
+            })
+            .or(player_default_config.level_meter_interval);
+
+        if level_meter_interval.is_some() && passthrough {
+            warn!("Level events are not available with passthrough and will not be sent.");
+        }
+
+        let output_buffer = opt_str(OUTPUT_BUFFER)
+            .map(|size| match size.parse::<u64>() {
+                Ok(value) if VALID_OUTPUT_BUFFER_RANGE.contains(&value) => {
+                    Duration::from_millis(value)
+                }
+                _ => {
+                    let valid_values = &format!(
+                        "{} - {}",
+                        VALID_OUTPUT_BUFFER_RANGE.start(),
+                        VALID_OUTPUT_BUFFER_RANGE.end()
+                    );
+
+                    invalid_error_msg(OUTPUT_BUFFER, OUTPUT_BUFFER_SHORT, &size, valid_values, "");
+
+                    exit(1);
+                }
+            })
+            .or(player_default_config.output_buffer);
+
+        if output_buffer.is_some() && passthrough {
+            warn!("The output buffer is not available with passthrough and will have no effect.");
+        }
+
+        if passthrough && opt_present(FADE_DURATION) {
+            warn!("Fading is not available with passthrough and will have no effect.");
+        }
+
+        if passthrough && sample_rate != librespot::playback::SAMPLE_RATE {
+            warn!("Resampling is not available with passthrough and will have no effect.");
+        }
+
+        let equalizer = opt_str(EQUALIZER)
+            .map(|bands| {
+                bands
+                    .split(',')
+                    .map(|band| {
+                        EqualizerBand::from_str(band).unwrap_or_else(|_| {
+                            invalid_error_msg(
+                                EQUALIZER,
+                                EQUALIZER_SHORT,
+                                band,
+                                "FREQ:GAIN:Q, {peak|lowshelf|highshelf}:FREQ:GAIN:Q, {lowpass|highpass}:FREQ:Q",
+                                "",
+                            );
+
+                            exit(1);
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let silence_trim = opt_present(TRIM_SILENCE);
+
+        if !silence_trim
+            && (opt_present(TRIM_SILENCE_THRESHOLD) || opt_present(TRIM_SILENCE_DURATION))
+        {
+            warn!("Without the `--{TRIM_SILENCE}` flag silence trimming options have no effect.");
+        } else if silence_trim && passthrough {
+            warn!("Silence trimming is not available with passthrough and will have no effect.");
+        }
+
+        let silence_trim_threshold_dbfs = opt_str(TRIM_SILENCE_THRESHOLD)
+            .map(|threshold| match threshold.parse::<f64>() {
+                Ok(value) if (VALID_TRIM_SILENCE_THRESHOLD_RANGE).contains(&value) => value,
+                _ => {
+                    let valid_values = &format!(
+                        "{} - {}",
+                        VALID_TRIM_SILENCE_THRESHOLD_RANGE.start(),
+                        VALID_TRIM_SILENCE_THRESHOLD_RANGE.end()
+                    );
+
+                    invalid_error_msg(
+                        TRIM_SILENCE_THRESHOLD,
+                        TRIM_SILENCE_THRESHOLD_SHORT,
+                        &threshold,
+                        valid_values,
+                        &player_default_config
+                            .silence_trim_threshold_dbfs
+                            .to_string(),
+                    );
+
+                    exit(1);
+                }
+            })
+            .unwrap_or(player_default_config.silence_trim_threshold_dbfs);
+
+        let silence_trim_min_duration = opt_str(TRIM_SILENCE_DURATION)
+            .map(|duration| match duration.parse::<u64>() {
+                Ok(value) if (VALID_TRIM_SILENCE_DURATION_RANGE).contains(&value) => {
+                    Duration::from_millis(value)
+                }
+                _ => {
+                    let valid_values = &format!(
+                        "{} - {}",
+                        VALID_TRIM_SILENCE_DURATION_RANGE.start(),
+                        VALID_TRIM_SILENCE_DURATION_RANGE.end()
+                    );
+
+                    invalid_error_msg(
+                        TRIM_SILENCE_DURATION,
+                        TRIM_SILENCE_DURATION_SHORT,
+                        &duration,
+                        valid_values,
+                        &player_default_config
+                            .silence_trim_min_duration
+                            .as_millis()
+                            .to_string(),
+                    );
+
+                    exit(1);
+                }
+            })
+            .unwrap_or(player_default_config.silence_trim_min_duration);
+
         PlayerConfig {
             bitrate,
+            preview_mode,
             gapless,
             passthrough,
+            sample_rate,
+            crossfade,
+            crossfade_curve,
+            fade_duration,
             normalisation,
             normalisation_type,
             normalisation_method,
@@ -1817,17 +2360,65 @@ async fn get_setup() -> Setup {
             normalisation_attack_cf,
             normalisation_release_cf,
             normalisation_knee_db,
+            silence_trim,
+            silence_trim_threshold_dbfs,
+            silence_trim_min_duration,
             ditherer,
+            filters: Vec::new(),
+            equalizer,
             position_update_interval: None,
+            level_meter_interval,
+            local_files_dir,
+            output_buffer,
+            tee,
         }
     };
 
+    if player_config.bitrate == Bitrate::Lossless {
+        if player_config.passthrough {
+            warn!("Lossless audio can not be passed through and will fall back to Ogg Vorbis.");
+        }
+
+        if format == AudioFormat::S16 {
+            warn!(
+                "24-bit lossless audio will be reduced to 16 bits. Use `--{FORMAT}` S24, S24_3 or S32 to play it without truncation."
+            );
+        }
+    }
+
+    let connect_config = ConnectConfig {
+        supports_hifi: player_config.bitrate == Bitrate::Lossless,
+        ..connect_config
+    };
+
+    let sleep_timer = opt_str(SLEEP_TIMER).as_deref().map(|timer| {
+        SleepTimer::from_str(timer).unwrap_or_else(|_| {
+            invalid_error_msg(
+                SLEEP_TIMER,
+                SLEEP_TIMER_SHORT,
+                timer,
+                "MINUTES, end-of-track, end-of-context",
+                "",
+            );
+
+            exit(1);
+        })
+    });
+
+    let sleep_timer_fade = opt_present(SLEEP_TIMER_FADE);
+
+    if sleep_timer.is_none() && sleep_timer_fade {
+        warn!("Without the `--{SLEEP_TIMER}` option the sleep timer fade has no effect.");
+    }
+
     let player_event_program = opt_str(ONEVENT);
     let emit_sink_events = opt_present(EMIT_SINK_EVENTS);
 
     Setup {
         format,
         backend,
+        #[cfg(feature = "pipewire-backend")]
+        pipewire_backend,
         device,
         mixer,
         cache,
@@ -1843,6 +2434,8 @@ async fn get_setup() -> Setup {
         emit_sink_events,
         zeroconf_ip,
         zeroconf_backend,
+        sleep_timer,
+        sleep_timer_fade,
     }
 }
 
@@ -1883,6 +2476,8 @@ async fn main() {
     let mut discovery = None;
     let mut connecting = false;
     let mut _event_handler: Option<EventHandler> = None;
+    // Only armed for the first connection, a reconnect does not restart it.
+    let mut sleep_timer = setup.sleep_timer;
 
     let mut session = Session::new(setup.session_config.clone(), setup.cache.clone());
 
@@ -1974,8 +2569,17 @@ async fn main() {
     let format = setup.format;
     let backend = setup.backend;
     let device = setup.device.clone();
+    let sample_rate = player_config.sample_rate;
+    #[cfg(feature = "pipewire-backend")]
+    let pipewire_volume = setup
+        .pipewire_backend
+        .then(|| setup.mixer_config.pipewire_volume.clone());
     let player = Player::new(player_config, session.clone(), soft_volume, move || {
-        (backend)(device, format)
+        #[cfg(feature = "pipewire-backend")]
+        if let Some(volume) = pipewire_volume {
+            return audio_backend::open_pipewire(device, format, sample_rate, volume);
+        }
+        (backend)(device, format, sample_rate)
     });
 
     if let Some(player_event_program) = setup.player_event_program.clone() {
@@ -2044,6 +2648,12 @@ async fn main() {
                         exit(1);
                     }
                 };
+                if let Some(timer) = sleep_timer.take() {
+                    if let Err(e) = spirc_.set_sleep_timer(Some(timer), setup.sleep_timer_fade) {
+                        error!("could not arm sleep timer: {e}");
+                    }
+                }
+
                 spirc = Some(spirc_);
                 spirc_task = Some(Box::pin(spirc_task_));
 
@@ -1,12 +1,20 @@
 use log::{debug, error, warn};
 
-use std::{collections::HashMap, process::Command, thread};
+use std::{
+    collections::HashMap,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
 
 use librespot::{
     metadata::audio::UniqueFields,
-    playback::player::{PlayerEvent, PlayerEventChannel, SinkStatus},
+    playback::player::{PlayerEvent, PlayerEventChannel, SinkStatus, SleepTimer},
 };
 
+// Level events can come every 50 ms, so they run the program at most this often.
+const LEVELS_MIN_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct EventHandler {
     thread_handle: Option<thread::JoinHandle<()>>,
 }
@@ -15,6 +23,8 @@ impl EventHandler {
     pub fn new(mut player_events: PlayerEventChannel, onevent: &str) -> Self {
         let on_event = onevent.to_string();
         let thread_handle = Some(thread::spawn(move || {
+            let mut levels_sent_at: Option<Instant> = None;
+
             loop {
                 match player_events.blocking_recv() {
                     None => break,
@@ -209,6 +219,51 @@ impl EventHandler {
                                     env_vars.insert("POSITION_MS", position_ms.to_string());
                                 }
                             },
+                            PlayerEvent::SilenceTrimmed {
+                                track_id,
+                                position_ms,
+                                trimmed_ms,
+                                ..
+                            } => match track_id.to_id() {
+                                Err(e) => {
+                                    warn!("PlayerEvent::SilenceTrimmed: Invalid track id: {e}")
+                                }
+                                Ok(id) => {
+                                    env_vars.insert("PLAYER_EVENT", "silence_trimmed".to_string());
+                                    env_vars.insert("TRACK_ID", id);
+                                    env_vars.insert("POSITION_MS", position_ms.to_string());
+                                    env_vars.insert("TRIMMED_MS", trimmed_ms.to_string());
+                                }
+                            },
+                            PlayerEvent::Levels {
+                                track_id, levels, ..
+                            } => match track_id.to_id() {
+                                Err(e) => warn!("PlayerEvent::Levels: Invalid track id: {e}"),
+                                Ok(_)
+                                    if levels_sent_at.is_some_and(|sent_at| {
+                                        sent_at.elapsed() < LEVELS_MIN_INTERVAL
+                                    }) => {}
+                                Ok(id) => {
+                                    levels_sent_at = Some(Instant::now());
+                                    env_vars.insert("PLAYER_EVENT", "levels".to_string());
+                                    env_vars.insert("TRACK_ID", id);
+                                    env_vars.insert("PEAK_DB", join_db(&levels.peak_db));
+                                    env_vars.insert("RMS_DB", join_db(&levels.rms_db));
+                                    env_vars.insert("BANDS_DB", join_db(&levels.bands_db));
+                                }
+                            },
+                            PlayerEvent::SinkError { error } => {
+                                env_vars.insert("PLAYER_EVENT", "sink_error".to_string());
+                                env_vars.insert("ERROR", error);
+                            }
+                            PlayerEvent::OutputBufferXrun {
+                                underruns,
+                                overruns,
+                            } => {
+                                env_vars.insert("PLAYER_EVENT", "output_buffer_xrun".to_string());
+                                env_vars.insert("UNDERRUNS", underruns.to_string());
+                                env_vars.insert("OVERRUNS", overruns.to_string());
+                            }
                             PlayerEvent::SessionConnected {
                                 connection_id,
                                 user_name,
@@ -251,6 +306,18 @@ impl EventHandler {
                                 env_vars.insert("PLAYER_EVENT", "auto_play_changed".to_string());
                                 env_vars.insert("AUTO_PLAY", auto_play.to_string());
                             }
+                            PlayerEvent::SleepTimerArmed { timer } => {
+                                env_vars.insert("PLAYER_EVENT", "sleep_timer_armed".to_string());
+                                insert_sleep_timer(&mut env_vars, timer);
+                            }
+                            PlayerEvent::SleepTimerCancelled => {
+                                env_vars
+                                    .insert("PLAYER_EVENT", "sleep_timer_cancelled".to_string());
+                            }
+                            PlayerEvent::SleepTimerFired { timer } => {
+                                env_vars.insert("PLAYER_EVENT", "sleep_timer_fired".to_string());
+                                insert_sleep_timer(&mut env_vars, timer);
+                            }
 
                             PlayerEvent::FilterExplicitContentChanged { filter } => {
                                 env_vars.insert(
@@ -302,6 +369,28 @@ pub fn run_program_on_sink_events(sink_status: SinkStatus, onevent: &str) {
     run_program(env_vars, onevent);
 }
 
+fn insert_sleep_timer(env_vars: &mut HashMap<&str, String>, timer: SleepTimer) {
+    let sleep_timer = match timer {
+        SleepTimer::After(duration) => {
+            env_vars.insert("SLEEP_TIMER_MS", duration.as_millis().to_string());
+            "after"
+        }
+        SleepTimer::EndOfTrack => "end_of_track",
+        SleepTimer::EndOfContext => "end_of_context",
+    };
+
+    env_vars.insert("SLEEP_TIMER", sleep_timer.to_string());
+}
+
+// Levels are passed as a comma-separated list with one decimal.
+fn join_db(levels: &[f64]) -> String {
+    levels
+        .iter()
+        .map(|level| format!("{level:.1}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 fn run_program(env_vars: HashMap<&str, String>, onevent: &str) {
     let mut v: Vec<&str> = onevent.split_whitespace().collect();
 